@@ -0,0 +1,29 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use stegnoapp::{ChannelByteMask, encoder};
+
+fn bench_embed(c: &mut Criterion) {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let image_len = 4000 * 3000 * 3;
+    let secret = vec![0xA5; encoder::capacity(image_len, mask, 1)];
+    let mut image = vec![0x80; image_len];
+    
+    let mut group = c.benchmark_group("embed_4000x3000");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter(|| encoder::embed_sequential(black_box(&mut image), &secret, mask))
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| encoder::embed_parallel(black_box(&mut image), &secret, mask))
+    });
+    #[cfg(feature = "simd")]
+    group.bench_function("whole_bytes", |b| {
+        b.iter(|| stegnoapp::simd::embed(black_box(&mut image), &secret, 2))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_embed);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use stegnoapp::header::HEADER_CARRIER_LEN;
+use image::RgbImage;
+
+use stegnoapp::{ChannelByteMask, Cover, Decoder, StegoHeader, decoder, encoder};
+
+// A bit-at-a-time extraction loop, kept here as the baseline.
+fn extract_bitwise(image: &[u8], mask: ChannelByteMask) -> Vec<u8> {
+    let header = StegoHeader::extract(image).unwrap();
+    let body = &image[HEADER_CARRIER_LEN..];
+    
+    let mut bits = body.iter().enumerate().flat_map(|(i, &b)| {
+        let channel = mask.channel(i);
+        (0..channel.bits).rev().map(move |k| (b >> k) & 1)
+    });
+    (0..header.length)
+        .map(|_| bits.by_ref().take(8).fold(0, |acc, bit| (acc << 1) | bit))
+        .collect()
+}
+
+fn bench_extract(c: &mut Criterion) {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut image = vec![0x80; 4000 * 3000 * 3];
+    let secret = vec![0xA5; encoder::capacity(image.len(), mask, 1)];
+    let header = StegoHeader::new(mask.bits(), secret.len() as u32);
+    encoder::embed(&mut image, &header, &secret, mask, encoder::Padding::Zero);
+    
+    let mut group = c.benchmark_group("extract_4000x3000");
+    group.sample_size(10);
+    group.bench_function("bitwise", |b| {
+        b.iter(|| extract_bitwise(black_box(&image), mask))
+    });
+    group.bench_function("accumulated", |b| {
+        b.iter(|| decoder::extract(black_box(&image)))
+    });
+    #[cfg(feature = "simd")]
+    group.bench_function("whole_bytes", |b| {
+        b.iter(|| stegnoapp::simd::extract(black_box(&image[HEADER_CARRIER_LEN..]), 2))
+    });
+    group.finish();
+}
+
+// Reading into memory, the batch size only changes how often a decode
+// stops to check for cancellation and report progress, this measures what
+// that costs.
+fn bench_buffer_len(c: &mut Criterion) {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut image = vec![0x80; 4000 * 3000 * 3];
+    let secret = vec![0xA5; encoder::capacity(image.len(), mask, 1)];
+    let header = StegoHeader::new(mask.bits(), secret.len() as u32);
+    encoder::embed(&mut image, &header, &secret, mask, encoder::Padding::Zero);
+    let mut decoder = Decoder::from_cover(Cover::Rgb(RgbImage::from_raw(4000, 3000, image).unwrap())).unwrap();
+    
+    let mut group = c.benchmark_group("read_to_vec_4000x3000");
+    group.sample_size(10);
+    for kib in [4, 64, 256, 4096] {
+        decoder.set_buffer_len(kib * 1024);
+        group.bench_function(format!("buffer_{}KiB", kib), |b| {
+            b.iter(|| black_box(&decoder).read_to_vec().unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_extract, bench_buffer_len);
+criterion_main!(benches);
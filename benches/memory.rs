@@ -0,0 +1,66 @@
+// Peak resident memory of opening, encoding and decoding a 4000x3000 cover.
+// The peak only ever grows within a process, so each stage runs in a child
+// process of its own, this same binary with the stage as its argument.
+// Linux only, it reads VmHWM from /proc/self/status.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use image::{Rgb, RgbImage};
+
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder};
+
+const WIDTH: u32 = 4000;
+const HEIGHT: u32 = 3000;
+const SECRET_LEN: usize = 4 * 1024 * 1024;
+const STAGES: [&str; 3] = ["open", "encode", "decode"];
+
+fn peak_rss_kib() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn run_stage(stage: &str, dir: &Path) {
+    let cover = dir.join("cover.png");
+    let stego = dir.join("stego.png");
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    match stage {
+        "open" => drop(Cover::open(&cover).unwrap()),
+        "encode" => {
+            let secret = (0..SECRET_LEN).map(|i| (i * 37) as u8).collect();
+            Encoder::from_bytes(cover, secret, mask).unwrap().save(stego).unwrap();
+        }
+        "decode" => drop(Decoder::new(stego).unwrap().read_to_vec().unwrap()),
+        _ => panic!("unknown stage {}", stage),
+    }
+    
+    match peak_rss_kib() {
+        Some(kib) => println!("{:<8} peak RSS {:>7.1} MiB", stage, kib as f64 / 1024.0),
+        None => println!("{:<8} peak RSS unavailable on this platform", stage),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).filter(|a| !a.starts_with("--")).collect();
+    if let [stage, dir] = args.as_slice() {
+        return run_stage(stage, Path::new(dir));
+    }
+    
+    let dir: PathBuf = env::temp_dir().join(format!("stegnoapp-memory-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| Rgb([x as u8, y as u8, (x ^ y) as u8]))
+        .save(dir.join("cover.png"))
+        .unwrap();
+    
+    let exe = env::current_exe().unwrap();
+    println!("{}x{} RGB8 cover, {} MiB secret at 2 bits", WIDTH, HEIGHT, SECRET_LEN / (1024 * 1024));
+    for stage in STAGES {
+        let status = Command::new(&exe).arg(stage).arg(&dir).status().unwrap();
+        assert!(status.success(), "{} failed", stage);
+    }
+    
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,32 @@
+#![no_main]
+
+use image::RgbImage;
+use libfuzzer_sys::fuzz_target;
+use stegnoapp::header::{HEADER_CARRIER_LEN, HEADER_LEN};
+use stegnoapp::{Cover, Decoder, decoder};
+
+// The input's first byte is the image width. The next HEADER_LEN bytes are
+// spread over the header's LSBs the way the encoder writes them, so the
+// fuzzer mutates header fields directly instead of having to find the magic
+// one bit per carrier byte. Whatever follows is the body, as is.
+fuzz_target!(|data: &[u8]| {
+    let Some((&width, rest)) = data.split_first() else {
+        return;
+    };
+    let (header, body) = rest.split_at(rest.len().min(HEADER_LEN));
+    
+    let mut carrier = vec![0; HEADER_CARRIER_LEN];
+    for (i, b) in carrier.iter_mut().take(header.len() * 8).enumerate() {
+        *b = (header[i / 8] >> (7 - i % 8)) & 1;
+    }
+    carrier.extend_from_slice(body);
+    let _ = decoder::extract(&carrier);
+    
+    let width = width.max(1) as usize;
+    let height = carrier.len() / 3 / width;
+    carrier.truncate(width * height * 3);
+    let image = RgbImage::from_raw(width as u32, height as u32, carrier).expect("the buffer matches the dimensions");
+    if let Ok(decoder) = Decoder::from_cover(Cover::Rgb(image)) {
+        let _ = decoder.read_all();
+    }
+});
@@ -0,0 +1,150 @@
+use image::RgbImage;
+
+use crate::errors::Error;
+
+/// Westfeld & Pfitzmann's chi-square attack: LSB embedding evens out the
+/// counts of each pair of values (2k, 2k + 1), so a histogram whose pairs are
+/// suspiciously balanced gets a score close to 1.
+pub struct Analysis {
+    pub channels: [f64; 3],
+    pub overall: f64,
+}
+
+const SUSPICIOUS_SCORE: f64 = 0.5;
+
+impl Analysis {
+    pub fn suspicious(&self) -> bool {
+        self.overall > SUSPICIOUS_SCORE
+    }
+}
+
+pub fn chi_square(carrier: &[u8]) -> Analysis {
+    let mut histograms = [[0u64; 256]; 3];
+    for (i, &b) in carrier.iter().enumerate() {
+        histograms[i % 3][b as usize] += 1;
+    }
+    
+    let channels = histograms.map(|histogram| pair_score(&histogram));
+    let overall = channels.iter().sum::<f64>() / channels.len() as f64;
+    
+    Analysis { channels, overall }
+}
+
+/// Peak signal-to-noise ratio in dB between two equally sized carriers,
+/// infinite when nothing changed.
+pub fn psnr(original: &[u8], modified: &[u8]) -> f64 {
+    let squared_error: u64 = original
+        .iter()
+        .zip(modified)
+        .map(|(&a, &b)| (a as i64 - b as i64).pow(2) as u64)
+        .sum();
+    
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+    
+    let mse = squared_error as f64 / original.len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// An image the size of both inputs where every channel that differs
+/// between them is white and every unchanged one black, so the spread of the
+/// embedding shows at a glance. Fails with [`Error::DimensionMismatch`] if
+/// the two aren't the same size.
+pub fn heatmap(cover: &RgbImage, stego: &RgbImage) -> Result<RgbImage, Error> {
+    if cover.dimensions() != stego.dimensions() {
+        return Err(Error::DimensionMismatch);
+    }
+    
+    let mut heatmap = RgbImage::new(cover.width(), cover.height());
+    for ((h, &a), &b) in heatmap.iter_mut().zip(cover.iter()).zip(stego.iter()) {
+        *h = if a == b { 0 } else { 255 };
+    }
+    
+    Ok(heatmap)
+}
+
+fn pair_score(histogram: &[u64; 256]) -> f64 {
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            pairs += 1;
+        }
+    }
+    
+    if pairs < 2 {
+        return 0.0;
+    }
+    
+    let dof = (pairs - 1) as f64;
+    1.0 - lower_gamma(dof / 2.0, statistic / 2.0)
+}
+
+// Regularized lower incomplete gamma function P(a, x), i.e. the chi-square
+// CDF, using the series below a + 1 and the continued fraction above.
+fn lower_gamma(a: f64, x: f64) -> f64 {
+    const ITERATIONS: usize = 200;
+    const EPSILON: f64 = 1e-12;
+    
+    if x <= 0.0 {
+        return 0.0;
+    }
+    
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..ITERATIONS {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        let tiny = f64::MIN_POSITIVE / EPSILON;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+// Stirling's series, shifted up by the recurrence for small arguments where
+// it loses accuracy.
+fn ln_gamma(a: f64) -> f64 {
+    let mut x = a;
+    let mut shift = 0.0;
+    while x < 8.0 {
+        shift += x.ln();
+        x += 1.0;
+    }
+    
+    let series = 1.0 / (12.0 * x) - 1.0 / (360.0 * x.powi(3)) + 1.0 / (1260.0 * x.powi(5));
+    (x - 0.5) * x.ln() - x + (2.0 * std::f64::consts::PI).ln() / 2.0 + series - shift
+}
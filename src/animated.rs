@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::errors::Error;
+
+// GIF frames are kept as palette indices rather than decoded to RGB, since
+// re-quantizing RGB frames on save would scramble the embedded LSBs. Palette
+// order is arbitrary, so flipping an index's low bit, 2k to 2k+1 or back,
+// could pick any other color. Palettes of up to 128 colors are doubled on
+// opening so both indices of every pair are the same color, and larger ones
+// are left alone, see GifAnimation::is_paired. Only pixels whose pair is
+// inside their palette and doesn't hold the frame's transparent index carry
+// the payload, in frame order. That doesn't change however their low bits
+// are set, so the decoder finds the same pixels.
+pub struct GifAnimation {
+    width: u16,
+    height: u16,
+    global_palette: Vec<u8>,
+    repeat: gif::Repeat,
+    frames: Vec<gif::Frame<'static>>,
+    pixels: Vec<u8>,
+}
+
+impl GifAnimation {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(path)?)?;
+        
+        let width = decoder.width();
+        let height = decoder.height();
+        let mut global_palette = decoder.global_palette().map(|p| p.to_vec()).unwrap_or_default();
+        let global_doubled = pair_palette(&mut global_palette);
+        let repeat = decoder.repeat();
+        
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame()? {
+            let mut frame = frame.clone();
+            let doubled = match frame.palette.as_mut() {
+                Some(palette) => pair_palette(palette),
+                None => global_doubled,
+            };
+            if doubled {
+                frame.buffer.to_mut().iter_mut().for_each(|index| *index = index.saturating_mul(2));
+                frame.transparent = frame.transparent.map(|index| index.saturating_mul(2));
+            }
+            frames.push(frame);
+        }
+        
+        let mut animation = GifAnimation {
+            width,
+            height,
+            global_palette,
+            repeat,
+            frames,
+            pixels: Vec::new(),
+        };
+        animation.pixels = animation.frames
+            .iter()
+            .flat_map(|frame| animation.carrier_positions(frame).map(|i| frame.buffer[i]))
+            .collect();
+        
+        Ok(animation)
+    }
+    
+    // Indices into a frame's pixels of those that carry the payload.
+    fn carrier_positions<'a>(&self, frame: &'a gif::Frame) -> impl Iterator<Item = usize> + 'a {
+        let colors = frame.palette.as_ref().unwrap_or(&self.global_palette).len() / 3;
+        let transparent = frame.transparent.map(|index| index & !1);
+        
+        frame.buffer
+            .iter()
+            .enumerate()
+            .filter(move |&(_, &index)| ((index | 1) as usize) < colors && (transparent != Some(index & !1)))
+            .map(|(i, _)| i)
+    }
+    
+    /// Whether both indices of every pair in the palettes the frames use
+    /// are the same color, so embedding leaves the animation looking as it
+    /// did. False when a palette has more than 128 colors.
+    pub fn is_paired(&self) -> bool {
+        self.frames.iter().all(|frame| is_paired(frame.palette.as_ref().unwrap_or(&self.global_palette)))
+    }
+    
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+    
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+    
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut encoder = gif::Encoder::new(File::create(path)?, self.width, self.height, &self.global_palette)?;
+        encoder.set_repeat(self.repeat)?;
+        
+        let mut pixels = self.pixels.iter();
+        for frame in &self.frames {
+            let mut written = frame.clone();
+            let buffer = written.buffer.to_mut();
+            for (i, &p) in self.carrier_positions(frame).zip(&mut pixels) {
+                buffer[i] = p;
+            }
+            encoder.write_frame(&written)?;
+        }
+        
+        Ok(())
+    }
+}
+
+fn is_paired(palette: &[u8]) -> bool {
+    palette.len().is_multiple_of(6) && palette.chunks_exact(6).all(|pair| pair[..3] == pair[3..])
+}
+
+// Doubles a palette of up to 128 colors, color k becoming 2k and 2k+1, and
+// says whether it did, in which case the indices into it double as well.
+// One that's already paired is left as it is, like a stego GIF's, so
+// opening that to decode it doesn't move its indices.
+fn pair_palette(palette: &mut Vec<u8>) -> bool {
+    if is_paired(palette) || (palette.len() > 128 * 3) {
+        return false;
+    }
+    
+    *palette = palette.chunks_exact(3).flat_map(|color| [color, color]).flatten().copied().collect();
+    true
+}
+
+// APNG frames are kept exactly as stored, each with its own size, offset,
+// delay and blend and dispose ops, so re-encoding leaves the animation as it
+// was. The carrier is the color samples of every frame in order, skipping
+// alpha and taking the low byte of 16-bit samples, so a frame's capacity
+// adds to the others' the same way GIF frames' do.
+pub struct ApngAnimation {
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    plays: u32,
+    frames: Vec<ApngFrame>,
+    pixels: Vec<u8>,
+}
+
+// A frame's control is None for a default image that isn't part of the
+// animation, which viewers without APNG support show instead.
+struct ApngFrame {
+    control: Option<png::FrameControl>,
+    data: Vec<u8>,
+}
+
+impl ApngAnimation {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        
+        let (width, height) = (reader.info().width, reader.info().height);
+        let (color, depth) = reader.output_color_type();
+        let animation = reader.info().animation_control.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "not an animated PNG")
+        })?;
+        let separate_default = reader.info().frame_control.is_none();
+        
+        let mut buffer = vec![0; reader.output_buffer_size().ok_or(png::DecodingError::LimitsExceeded)?];
+        let mut frames = Vec::new();
+        for _ in 0..animation.num_frames + separate_default as u32 {
+            let output = reader.next_frame(&mut buffer)?;
+            let control = reader.info().frame_control.filter(|_| !(separate_default && frames.is_empty()));
+            let len = output.line_size * output.height as usize;
+            frames.push(ApngFrame { control, data: buffer[..len].to_vec() });
+        }
+        
+        let mut animation = ApngAnimation {
+            width,
+            height,
+            color,
+            depth,
+            plays: animation.num_plays,
+            frames,
+            pixels: Vec::new(),
+        };
+        animation.pixels = animation.frames
+            .iter()
+            .flat_map(|frame| animation.carrier_positions(frame).map(|i| frame.data[i]))
+            .collect();
+        
+        Ok(animation)
+    }
+    
+    // Indices into a frame's samples of the bytes that carry the payload.
+    fn carrier_positions(&self, frame: &ApngFrame) -> impl Iterator<Item = usize> {
+        let samples = self.color.samples();
+        let alpha = matches!(self.color, png::ColorType::GrayscaleAlpha | png::ColorType::Rgba);
+        let colors = if alpha { samples - 1 } else { samples };
+        let sample_len = if self.depth == png::BitDepth::Sixteen { 2 } else { 1 };
+        
+        (0..frame.data.len() / sample_len)
+            .filter(move |s| s % samples < colors)
+            .map(move |s| s * sample_len + sample_len - 1)
+    }
+    
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+    
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+    
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut encoder = png::Encoder::new(File::create(path)?, self.width, self.height);
+        encoder.set_color(self.color);
+        encoder.set_depth(self.depth);
+        let separate_default = self.frames[0].control.is_none();
+        encoder.set_animated(self.frames.len() as u32 - separate_default as u32, self.plays)?;
+        encoder.set_sep_def_img(separate_default)?;
+        let mut writer = encoder.write_header()?;
+        
+        let mut pixels = self.pixels.iter();
+        for frame in &self.frames {
+            let mut data = frame.data.clone();
+            for (i, &p) in self.carrier_positions(frame).zip(&mut pixels) {
+                data[i] = p;
+            }
+            if let Some(control) = frame.control {
+                writer.reset_frame_position()?;
+                writer.set_frame_dimension(control.width, control.height)?;
+                writer.set_frame_position(control.x_offset, control.y_offset)?;
+                writer.set_frame_delay(control.delay_num, control.delay_den)?;
+                writer.set_dispose_op(control.dispose_op)?;
+                writer.set_blend_op(control.blend_op)?;
+            }
+            writer.write_image_data(&data)?;
+        }
+        writer.finish()?;
+        
+        Ok(())
+    }
+}
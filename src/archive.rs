@@ -0,0 +1,25 @@
+use std::fs;
+use std::path::Path;
+
+use crate::errors::Error;
+
+/// Packs the directory at `dir` into a tar archive in memory, with paths
+/// relative to it so it unpacks into whatever directory is chosen later.
+/// Symlinks are followed and stored as what they point to.
+pub fn pack(dir: &Path) -> Result<Vec<u8>, Error> {
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.follow_symlinks(true);
+    builder.append_dir_all(".", dir)?;
+    
+    Ok(builder.into_inner()?)
+}
+
+/// Unpacks an archive written by [`pack`] into `output`, creating it if
+/// it's missing. Nothing lands outside it: entries going up through `..`
+/// are skipped, and absolute paths are taken as relative to it.
+pub fn unpack(archive: &[u8], output: &Path) -> Result<(), Error> {
+    fs::create_dir_all(output)?;
+    tar::Archive::new(archive).unpack(output)?;
+    
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use crate::errors::Error;
+
+// Payload bits go into the low byte of each 16-bit PCM sample. The low bytes
+// are split out into their own buffer so the image embedding code can treat
+// them exactly like pixel channels.
+pub struct WavAudio {
+    spec: WavSpec,
+    samples: Vec<i16>,
+    low_bytes: Vec<u8>,
+}
+
+impl WavAudio {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let mut reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        check_spec(spec)?;
+        
+        let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+        let low_bytes = samples.iter().map(|&s| s as u8).collect();
+        
+        Ok(WavAudio { spec, samples, low_bytes })
+    }
+    
+    pub fn samples(&self) -> &[u8] {
+        &self.low_bytes
+    }
+    
+    pub fn samples_mut(&mut self) -> &mut [u8] {
+        &mut self.low_bytes
+    }
+    
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut writer = WavWriter::create(path, self.spec)?;
+        
+        for (&sample, &low) in self.samples.iter().zip(&self.low_bytes) {
+            let sample = ((sample as u16 & 0xFF00) | low as u16) as i16;
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+        
+        Ok(())
+    }
+}
+
+pub fn sample_count(path: &Path) -> Result<usize, Error> {
+    Ok(read_header(path)?.1)
+}
+
+// The format and sample count, without reading any samples.
+pub fn read_header(path: &Path) -> Result<(WavSpec, usize), Error> {
+    let reader = WavReader::open(path)?;
+    check_spec(reader.spec())?;
+    
+    Ok((reader.spec(), reader.len() as usize))
+}
+
+fn check_spec(spec: WavSpec) -> Result<(), Error> {
+    if (spec.sample_format != SampleFormat::Int) || (spec.bits_per_sample != 16) {
+        Err(Error::UnsupportedAudio)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use stegnoapp::Error;
+use stegnoapp::crypto::KdfParams;
+
+use crate::keymap::Keymap;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AppConfig {
+    pub bits: u8,
+    pub output_dir: Option<PathBuf>,
+    pub channels: String,
+    pub theme: String,
+    pub kdf_memory_kib: u32,
+    pub kdf_iterations: u32,
+    // Output file names, with {name} standing for the stem of the cover
+    // or stego image being worked on.
+    pub encode_output_name: String,
+    pub decode_output_name: String,
+    // Show the output in the file manager after every successful operation.
+    pub reveal_output: bool,
+    // Where the file explorer was last left, keyed by what it was picking.
+    pub explorer_dirs: BTreeMap<String, PathBuf>,
+    pub keys: Keymap,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            bits: 2,
+            output_dir: None,
+            channels: "rgb".to_string(),
+            theme: "dark".to_string(),
+            kdf_memory_kib: KdfParams::default().memory_kib,
+            kdf_iterations: KdfParams::default().iterations,
+            encode_output_name: "{name}_stego.png".to_string(),
+            decode_output_name: "{name}_extracted".to_string(),
+            reveal_output: false,
+            explorer_dirs: BTreeMap::new(),
+            keys: Keymap::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "stegnoapp").map(|dirs| dirs.config_dir().join("config.toml"))
+    }
+    
+    // A missing or unreadable config is not worth refusing to start over,
+    // nor is one with values out of range, like a KDF cost decoding would
+    // refuse.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .filter(|config: &AppConfig| (1..=8).contains(&config.bits) && config.kdf_params().within_max())
+            .unwrap_or_default()
+    }
+    
+    pub fn encode_output_name(&self, cover: Option<&Path>) -> OsString {
+        expand_name(&self.encode_output_name, cover, "stego.png")
+    }
+    
+    pub fn decode_output_name(&self, image: Option<&Path>) -> OsString {
+        expand_name(&self.decode_output_name, image, "extracted")
+    }
+    
+    pub fn kdf_params(&self) -> KdfParams {
+        KdfParams { memory_kib: self.kdf_memory_kib, iterations: self.kdf_iterations }
+    }
+    
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+        
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        
+        Ok(())
+    }
+}
+
+// Until a file is picked there's no stem for {name}, so templates using it
+// fall back to the fixed name. The stem is spliced in as it is, so names
+// that aren't UTF-8 come through intact.
+fn expand_name(template: &str, source: Option<&Path>, fallback: &str) -> OsString {
+    if !template.contains("{name}") {
+        return template.into();
+    }
+    
+    let Some(stem) = source.and_then(Path::file_stem) else {
+        return fallback.into();
+    };
+    let mut name = OsString::new();
+    for (i, part) in template.split("{name}").enumerate() {
+        if i > 0 {
+            name.push(stem);
+        }
+        name.push(part);
+    }
+    name
+}
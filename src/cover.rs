@@ -0,0 +1,341 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use image::{ColorType, DynamicImage, GrayImage, ImageBuffer, ImageDecoder, ImageReader, Luma, Pixel, PixelWithColorType, Rgb};
+use log::debug;
+
+use crate::animated::{ApngAnimation, GifAnimation};
+use crate::audio::{self, WavAudio};
+use crate::errors::Error;
+
+/// A carrier opened from disk, exposed as one flat buffer of embeddable bytes.
+/// Grayscale images carry one byte per pixel rather than three.
+pub enum Cover {
+    Rgb(ImageBuffer<Rgb<u8>, Vec<u8>>),
+    Luma(GrayImage),
+    Rgb16(DeepImage),
+    Luma16(DeepImage<Luma<u16>>),
+    Gif(GifAnimation),
+    Apng(ApngAnimation),
+    Wav(WavAudio),
+}
+
+impl Cover {
+    /// Fails with [`Error::IndexedImageUnsupported`] for palette PNGs and
+    /// BMPs, see [`Cover::open_truecolor`].
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        check_indexed(path)?;
+        Cover::open_truecolor(path)
+    }
+    
+    /// Like [`Cover::open`], but converts a palette image to true-color
+    /// instead of refusing it, for when the user asked for exactly that.
+    pub fn open_truecolor(path: &Path) -> Result<Self, Error> {
+        let cover = if is_gif(path) {
+            Cover::Gif(GifAnimation::open(path)?)
+        } else if is_apng(path) {
+            Cover::Apng(ApngAnimation::open(path)?)
+        } else if is_wav(path) {
+            Cover::Wav(WavAudio::open(path)?)
+        } else {
+            Cover::from_image(image::open(path)?)
+        };
+        debug!("Opened {}, {} carrier bytes", path.display(), cover.bytes().len());
+        
+        Ok(cover)
+    }
+    
+    // into_rgb8/into_rgb16/into_luma8/into_luma16 hand the decoded buffer
+    // over as is when it's already in that layout, so the usual covers
+    // aren't copied. Grayscale stays single-channel at either depth instead
+    // of being tripled to RGB.
+    pub fn from_image(image: DynamicImage) -> Self {
+        debug!("Loaded a {}x{} {:?} image", image.width(), image.height(), image.color());
+        match image.color() {
+            ColorType::L16 | ColorType::La16 => Cover::Luma16(DeepImage::new(image.into_luma16())),
+            ColorType::Rgb16 | ColorType::Rgba16 => Cover::Rgb16(DeepImage::new(image.into_rgb16())),
+            ColorType::L8 | ColorType::La8 => Cover::Luma(image.into_luma8()),
+            _ => Cover::Rgb(image.into_rgb8()),
+        }
+    }
+    
+    /// Width and height in pixels, for still images only.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        match self {
+            Cover::Rgb(image) => Some(image.dimensions()),
+            Cover::Luma(image) => Some(image.dimensions()),
+            Cover::Rgb16(image) => Some(image.image.dimensions()),
+            Cover::Luma16(image) => Some(image.image.dimensions()),
+            Cover::Gif(_) | Cover::Apng(_) | Cover::Wav(_) => None,
+        }
+    }
+    
+    /// Whether the cover is an 8 or 16-bit grayscale image.
+    pub fn is_grayscale(&self) -> bool {
+        matches!(self, Cover::Luma(_) | Cover::Luma16(_))
+    }
+    
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Cover::Rgb(image) => image,
+            Cover::Luma(image) => image,
+            Cover::Rgb16(image) => image.samples(),
+            Cover::Luma16(image) => image.samples(),
+            Cover::Gif(animation) => animation.pixels(),
+            Cover::Apng(animation) => animation.pixels(),
+            Cover::Wav(audio) => audio.samples(),
+        }
+    }
+    
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        match self {
+            Cover::Rgb(image) => image,
+            Cover::Luma(image) => image,
+            Cover::Rgb16(image) => image.samples_mut(),
+            Cover::Luma16(image) => image.samples_mut(),
+            Cover::Gif(animation) => animation.pixels_mut(),
+            Cover::Apng(animation) => animation.pixels_mut(),
+            Cover::Wav(audio) => audio.samples_mut(),
+        }
+    }
+    
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        match self {
+            Cover::Rgb(image) => Ok(image.save(path)?),
+            Cover::Luma(image) => Ok(image.save(path)?),
+            Cover::Rgb16(image) => image.save(path),
+            Cover::Luma16(image) => image.save(path),
+            Cover::Gif(animation) => animation.save(path),
+            Cover::Apng(animation) => animation.save(path),
+            Cover::Wav(audio) => audio.save(path),
+        }
+    }
+}
+
+// 16-bit covers carry the payload in the low byte of each sample, the same
+// way WAV samples do. Even at 8 bits that stays below 1/256 of the sample
+// range, so deep images tolerate far higher bit depths than 8-bit ones.
+// `P` is Rgb<u16>, or Luma<u16> for grayscale.
+pub struct DeepImage<P: Pixel<Subpixel = u16> = Rgb<u16>> {
+    image: ImageBuffer<P, Vec<u16>>,
+    low_bytes: Vec<u8>,
+}
+
+impl<P: Pixel<Subpixel = u16> + PixelWithColorType> DeepImage<P> {
+    pub fn new(image: ImageBuffer<P, Vec<u16>>) -> Self {
+        let low_bytes = image.iter().map(|&s| s as u8).collect();
+        
+        DeepImage { image, low_bytes }
+    }
+    
+    pub fn samples(&self) -> &[u8] {
+        &self.low_bytes
+    }
+    
+    pub fn samples_mut(&mut self) -> &mut [u8] {
+        &mut self.low_bytes
+    }
+    
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut image = self.image.clone();
+        for (sample, &low) in image.iter_mut().zip(&self.low_bytes) {
+            *sample = (*sample & 0xFF00) | low as u16;
+        }
+        image.save(path)?;
+        
+        Ok(())
+    }
+}
+
+/// Byte length of the embeddable buffer, without decoding pixels when the
+/// header alone is enough to know it.
+pub fn carrier_len(path: &Path) -> Result<usize, Error> {
+    if is_gif(path) {
+        Ok(GifAnimation::open(path)?.pixels().len())
+    } else if is_apng(path) {
+        Ok(ApngAnimation::open(path)?.pixels().len())
+    } else if is_wav(path) {
+        audio::sample_count(path)
+    } else {
+        let decoder = ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+        let (width, height) = decoder.dimensions();
+        let channels = if matches!(decoder.color_type(), ColorType::L8 | ColorType::La8 | ColorType::L16 | ColorType::La16) { 1 } else { 3 };
+        Ok(width as usize * height as usize * channels)
+    }
+}
+
+/// A cover's format, read from its header alone.
+pub enum CoverInfo {
+    Image { width: u32, height: u32, color: ColorType },
+    Audio { channels: u16, sample_rate: u32, samples: usize },
+}
+
+impl CoverInfo {
+    pub fn probe(path: &Path) -> Result<Self, Error> {
+        if is_wav(path) {
+            let (spec, samples) = audio::read_header(path)?;
+            return Ok(CoverInfo::Audio { channels: spec.channels, sample_rate: spec.sample_rate, samples });
+        }
+        
+        check_indexed(path)?;
+        let decoder = ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+        let (width, height) = decoder.dimensions();
+        
+        Ok(CoverInfo::Image { width, height, color: decoder.color_type() })
+    }
+    
+    /// Size of the decoded pixels or samples.
+    pub fn byte_len(&self) -> u64 {
+        match *self {
+            CoverInfo::Image { width, height, color } => width as u64 * height as u64 * color.bytes_per_pixel() as u64,
+            CoverInfo::Audio { samples, .. } => samples as u64 * 2,
+        }
+    }
+}
+
+impl fmt::Display for CoverInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoverInfo::Image { width, height, color } => write!(
+                f,
+                "{}x{}, {}, {} bytes",
+                width,
+                height,
+                format!("{:?}", color).to_uppercase(),
+                group_thousands(self.byte_len())
+            ),
+            CoverInfo::Audio { channels, sample_rate, .. } => write!(
+                f,
+                "{} channel(s), {} Hz, 16-bit PCM, {} bytes",
+                channels,
+                sample_rate,
+                group_thousands(self.byte_len())
+            ),
+        }
+    }
+}
+
+fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    
+    for (i, c) in digits.chars().enumerate() {
+        if (i > 0) && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    
+    grouped
+}
+
+pub fn is_gif(path: &Path) -> bool {
+    has_extension(path, "gif")
+}
+
+/// Whether `path` is a PNG with an animation control chunk ahead of its
+/// image data. Files that can't be read are reported as not animated and
+/// left for the decoder to reject.
+pub fn is_apng(path: &Path) -> bool {
+    has_extension(path, "png") && has_animation_control(path).unwrap_or(false)
+}
+
+fn has_animation_control(path: &Path) -> Result<bool, Error> {
+    let mut file = File::open(path)?;
+    file.seek_relative(8)?;
+    
+    let mut chunk = [0; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        match &chunk[4..] {
+            b"acTL" => return Ok(true),
+            b"IDAT" => return Ok(false),
+            _ => {}
+        }
+        let len = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as i64;
+        file.seek_relative(len + 4)?;
+    }
+    
+    Ok(false)
+}
+
+pub fn is_wav(path: &Path) -> bool {
+    has_extension(path, "wav")
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+/// Whether `path` is a WebP compressed lossily (VP8) rather than losslessly
+/// (VP8L). The extended format names its bitstream in a later chunk, after
+/// ones like the color profile, so chunks are skipped until one turns up.
+/// Anything that isn't a WebP is reported as not lossy.
+pub fn is_lossy_webp(path: &Path) -> Result<bool, Error> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 12];
+    if file.read_exact(&mut header).is_err() || (&header[..4] != b"RIFF") || (&header[8..] != b"WEBP") {
+        return Ok(false);
+    }
+    
+    let mut chunk = [0; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        match &chunk[..4] {
+            b"VP8 " => return Ok(true),
+            b"VP8L" => return Ok(false),
+            _ => {}
+        }
+        let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as i64;
+        file.seek_relative(len + len % 2)?;
+    }
+    
+    Ok(false)
+}
+
+// Palette images would come back as true-color on save, looking different
+// and holding more than they seemed to, so they are refused rather than
+// silently converted. GIFs are embedded into their palette indices instead.
+fn check_indexed(path: &Path) -> Result<(), Error> {
+    if has_extension(path, "bmp") {
+        check_bmp_depth(path)
+    } else if has_extension(path, "png") {
+        check_png_palette(path)
+    } else {
+        Ok(())
+    }
+}
+
+// Palettized BMPs have 8 bits per pixel or fewer.
+fn check_bmp_depth(path: &Path) -> Result<(), Error> {
+    let mut header = [0; 30];
+    File::open(path)?.read_exact(&mut header)?;
+    
+    let bits_per_pixel = u16::from_le_bytes([header[28], header[29]]);
+    if (&header[..2] == b"BM") && (bits_per_pixel <= 8) {
+        Err(Error::IndexedImageUnsupported)
+    } else {
+        Ok(())
+    }
+}
+
+// The color type follows the signature, the IHDR chunk's length and name,
+// and the width, height and bit depth. Type 3 is a palette. Files too short
+// to tell are left for the decoder to reject.
+fn check_png_palette(path: &Path) -> Result<(), Error> {
+    let mut header = Vec::with_capacity(26);
+    File::open(path)?.take(26).read_to_end(&mut header)?;
+    
+    let indexed = (header.len() == 26)
+        && header.starts_with(b"\x89PNG\r\n\x1a\n")
+        && (&header[12..16] == b"IHDR")
+        && (header[25] == 3);
+    if indexed {
+        Err(Error::IndexedImageUnsupported)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use aes::Aes256;
+use argon2::{Algorithm, Argon2, Params, Version};
+use ctr::Ctr128BE;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+
+use crate::errors::Error;
+
+pub const SALT_LEN: usize = 16;
+
+pub const TAG_LEN: usize = 32;
+
+const KEY_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Argon2id cost. The defaults are the OWASP minimum of 19 MiB and two
+/// passes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+}
+
+/// The highest cost [`Keys::derive`] accepts, and the highest the app's
+/// settings offer, so nothing it writes is refused. Decoding derives the
+/// keys before the tag can be checked, so a forged header could otherwise
+/// have a decode allocate gigabytes and spend minutes before failing.
+pub const MAX_KDF: KdfParams = KdfParams { memory_kib: 256 * 1024, iterations: 10 };
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams { memory_kib: 19 * 1024, iterations: 2 }
+    }
+}
+
+impl KdfParams {
+    /// Whether the cost is at most [`MAX_KDF`] on both counts.
+    pub fn within_max(&self) -> bool {
+        (self.memory_kib <= MAX_KDF.memory_kib) && (self.iterations <= MAX_KDF.iterations)
+    }
+}
+
+/// What the header needs to check and decrypt a passphrase-protected
+/// payload: the salt and cost the keys were derived with, and the HMAC of
+/// the ciphertext.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Protection {
+    pub salt: [u8; SALT_LEN],
+    pub kdf: KdfParams,
+    pub tag: [u8; TAG_LEN],
+}
+
+/// The cipher and MAC keys derived from one passphrase and salt.
+#[derive(PartialEq)]
+pub struct Keys {
+    cipher: [u8; KEY_LEN],
+    mac: [u8; KEY_LEN],
+}
+
+impl Keys {
+    /// Fails with [`Error::KdfCostTooHigh`] when `kdf` is above [`MAX_KDF`].
+    pub fn derive(passphrase: &str, salt: &[u8; SALT_LEN], kdf: KdfParams) -> Result<Self, Error> {
+        if !kdf.within_max() {
+            return Err(Error::KdfCostTooHigh);
+        }
+        
+        let params = Params::new(kdf.memory_kib, kdf.iterations, 1, Some(2 * KEY_LEN)).map_err(Error::Kdf)?;
+        let mut out = [0; 2 * KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut out)
+            .map_err(Error::Kdf)?;
+        
+        let (cipher, mac) = out.split_at(KEY_LEN);
+        Ok(Keys { cipher: cipher.try_into().unwrap(), mac: mac.try_into().unwrap() })
+    }
+    
+    // Every encode draws a fresh salt and so a fresh key, which makes a
+    // fixed counter start safe.
+    fn apply_keystream(&self, data: &mut [u8]) {
+        Ctr128BE::<Aes256>::new(&self.cipher.into(), &[0; 16].into()).apply_keystream(data);
+    }
+    
+    fn hmac(&self, data: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.mac).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac
+    }
+}
+
+/// Encrypts `payload` in place with AES-256-CTR under a fresh salt.
+pub fn seal(passphrase: &str, payload: &mut [u8], kdf: KdfParams) -> Result<Protection, Error> {
+    let mut salt = [0; SALT_LEN];
+    rand::rng().fill(&mut salt);
+    
+    let keys = Keys::derive(passphrase, &salt, kdf)?;
+    keys.apply_keystream(payload);
+    let tag = keys.hmac(payload).finalize().into_bytes().into();
+    
+    Ok(Protection { salt, kdf, tag })
+}
+
+/// Checks the ciphertext against its tag, in constant time, then decrypts
+/// it in place. Fails with [`Error::IntegrityCheckFailed`] when the payload
+/// was altered or the passphrase is wrong.
+pub fn open(passphrase: &str, payload: &mut [u8], protection: &Protection) -> Result<(), Error> {
+    let keys = Keys::derive(passphrase, &protection.salt, protection.kdf)?;
+    keys.hmac(payload)
+        .verify_slice(&protection.tag)
+        .map_err(|_| Error::IntegrityCheckFailed)?;
+    keys.apply_keystream(payload);
+    
+    Ok(())
+}
+
+/// Decrypts `payload` in place whether or not it matches its tag, and says
+/// whether it did. CTR decrypts any prefix of the ciphertext on its own, so
+/// this is what reads back what's left of a truncated payload.
+pub fn open_unverified(passphrase: &str, payload: &mut [u8], protection: &Protection) -> Result<bool, Error> {
+    let keys = Keys::derive(passphrase, &protection.salt, protection.kdf)?;
+    let verified = keys.hmac(payload).verify_slice(&protection.tag).is_ok();
+    keys.apply_keystream(payload);
+    
+    Ok(verified)
+}
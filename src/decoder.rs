@@ -1,54 +1,732 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
-
-use image::{ImageBuffer, Rgb};
-
-use crate::errors::Error;
-use crate::utils::ByteMask;
-
-pub struct Decoder {
-    image: ImageBuffer<Rgb<u8>, Vec<u8>>,
-    mask: ByteMask
-}
-
-impl Decoder {
-    pub fn new(
-        image_path: PathBuf,
-        mask: ByteMask
-    ) -> Result<Self, Error> {
-        let image = image::open(image_path)?.to_rgb8();
-        
-        Ok(Decoder { image, mask })
-    }
-    
-    pub fn save(&self, output: PathBuf) -> Result<(), Error> {
-        let mut secret = BufWriter::new(File::create(output)?);
-        let mut chunks = Vec::with_capacity(self.mask.chunks as usize);
-        let mut start = false;
-        
-        for (i, b) in self.image.iter().map(|b| b & self.mask.mask).enumerate() {
-            if !start && (b > 0) {
-                let n = self.mask.chunks as usize;
-                let offset = (self.image.len() - i) % n;
-                if offset != 0 {
-                    (0..(n - offset)).for_each(|_| chunks.push(0));
-                }
-                start = true;
-            };
-            
-            if start {
-                chunks.push(b);
-            }
-            
-            if chunks.len() == chunks.capacity() {
-                let byte = self.mask.join_chunks(&chunks);
-                secret.write_all(&[byte])?;
-                chunks.clear();
-            }
-        }
-        
-        secret.flush()?;
-        Ok(())
-    }
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use zeroize::Zeroizing;
+
+use crate::archive;
+use crate::cover::Cover;
+use crate::crypto;
+use crate::errors::Error;
+use crate::header::{HEADER_CARRIER_LEN, StegoHeader};
+use crate::selection;
+use crate::utils::{self, ChannelByteMask, Progress, Timings};
+
+/// What a finished decode wrote, see [`Decoder::save_with_outcome`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct DecodeOutcome {
+    pub output: PathBuf,
+    pub payload_len: usize,
+    /// The depths the header says the payload was embedded at.
+    pub mask: ChannelByteMask,
+    /// Only there when debug logging is on.
+    pub timings: Option<Timings>,
+}
+
+impl fmt::Display for DecodeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes to {} from {} LSB bits", self.payload_len, self.output.display(), self.mask)?;
+        if let Some(timings) = &self.timings {
+            write!(f, " ({})", timings)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a lenient decode got back, see [`Decoder::recover`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct Recovery {
+    pub secret: Vec<u8>,
+    /// Payload bytes read, out of the `declared` ones the header promised.
+    pub recovered: usize,
+    pub declared: usize,
+    /// Whether a protected payload matched its tag, or None when there's no
+    /// tag to check it against.
+    pub verified: Option<bool>,
+}
+
+impl Recovery {
+    pub fn complete(&self) -> bool {
+        self.recovered == self.declared
+    }
+}
+
+/// Reads back a secret embedded by [`crate::Encoder`]. A cover can hold
+/// several messages, see [`crate::Encoder::set_append`], and the first one
+/// is read unless another is picked with [`Decoder::select`].
+pub struct Decoder {
+    cover: Cover,
+    headers: Vec<StegoHeader>,
+    selected: usize,
+    passphrase: Option<Zeroizing<String>>,
+    load_time: Option<Duration>,
+    buffer_len: usize,
+    cancel: Arc<AtomicBool>,
+    progress: Option<Progress>
+}
+
+impl Decoder {
+    /// Fails with [`Error::NotAStegoImage`] when there is no valid header,
+    /// and with [`Error::InvalidHeader`] when one declares a payload longer
+    /// than the image could hold.
+    pub fn new(image_path: PathBuf) -> Result<Self, Error> {
+        Decoder::load(&image_path, false)
+    }
+    
+    /// Reads from a carrier already in memory, such as an image buffer
+    /// wrapped in [`Cover::Rgb`], instead of loading it again. A payload
+    /// written into a grayscale cover only reads back from one, and one
+    /// written into a color cover only from a color one.
+    pub fn from_cover(cover: Cover) -> Result<Self, Error> {
+        Decoder::open(cover, false)
+    }
+    
+    /// Like [`Decoder::new`], but only the headers have to be intact, not
+    /// the payloads, so an image cropped or damaged past them still opens
+    /// for [`Decoder::recover`].
+    pub fn new_lenient(image_path: PathBuf) -> Result<Self, Error> {
+        Decoder::load(&image_path, true)
+    }
+    
+    fn load(image_path: &Path, lenient: bool) -> Result<Self, Error> {
+        let started = utils::stopwatch();
+        let mut decoder = Decoder::open(Cover::open(image_path)?, lenient)?;
+        decoder.load_time = started.map(|s| s.elapsed());
+        Ok(decoder)
+    }
+    
+    fn open(cover: Cover, lenient: bool) -> Result<Self, Error> {
+        let headers = StegoHeader::extract_all(cover.bytes())?;
+        for header in &headers {
+            debug!("Parsed {:?}", header);
+            if header.grayscale != cover.is_grayscale() {
+                return Err(Error::NotAStegoImage);
+            }
+            if lenient {
+                continue;
+            }
+            if header.spread.is_some() {
+                spread_positions(cover.bytes(), header, utils::spread_seed(None))?;
+            } else {
+                locate(cover.bytes(), header, false)?;
+            }
+        }
+        
+        Ok(Decoder {
+            cover,
+            headers,
+            selected: 0,
+            passphrase: None,
+            load_time: None,
+            buffer_len: utils::DEFAULT_BUFFER_LEN,
+            cancel: Arc::new(AtomicBool::new(false)),
+            progress: None
+        })
+    }
+    
+    /// The bit depths detected from the selected message's header.
+    pub fn mask(&self) -> ChannelByteMask {
+        ChannelByteMask::new(self.headers[self.selected].bits).expect("headers with invalid depths don't decode")
+    }
+    
+    /// The header of every message in the cover, in the order they were
+    /// written.
+    pub fn headers(&self) -> &[StegoHeader] {
+        &self.headers
+    }
+    
+    /// Picks the message to read by its index in [`Decoder::headers`].
+    /// Fails with [`Error::MessageNotFound`] when there is no such message.
+    pub fn select(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.headers.len() {
+            return Err(Error::MessageNotFound(index));
+        }
+        
+        self.selected = index;
+        Ok(())
+    }
+    
+    /// Needed when the payload was written with a passphrase, see
+    /// [`crate::Encoder::set_passphrase`]. A spread payload also needs it to
+    /// find its bits, and reads back as noise without it. The decoder's copy
+    /// is zeroed when it's dropped.
+    pub fn set_passphrase(&mut self, passphrase: &str) {
+        self.passphrase = Some(Zeroizing::new(passphrase.to_string()));
+    }
+    
+    /// How many payload bytes to extract between checks for cancellation
+    /// and progress reports, [`utils::DEFAULT_BUFFER_LEN`] unless set.
+    /// [`Decoder::save`] and [`Decoder::write_to`] hand an unprotected
+    /// secret to their output a batch this size at a time, so it also
+    /// bounds how much of one is held in memory.
+    pub fn set_buffer_len(&mut self, len: usize) {
+        self.buffer_len = len;
+    }
+    
+    /// Setting `cancel` makes a running decode stop at the next batch with
+    /// [`Error::Cancelled`]. A cancelled [`Decoder::save`] removes what it
+    /// already wrote.
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+    
+    /// `progress` is called as a decode reads the payload, every batch and
+    /// once more when it's done.
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = Some(progress);
+    }
+    
+    /// Empty for a message whose header declares a length of 0. Fails with
+    /// [`Error::PassphraseRequired`] or [`Error::IntegrityCheckFailed`] when
+    /// the payload is encrypted and the passphrase is missing or doesn't
+    /// match.
+    pub fn read_to_vec(&self) -> Result<Vec<u8>, Error> {
+        self.read_message(&self.headers[self.selected])
+    }
+    
+    /// Every message the passphrase, or the lack of one, opens, along with
+    /// its index. Messages protected by another passphrase are skipped.
+    pub fn read_all(&self) -> Result<Vec<(usize, Vec<u8>)>, Error> {
+        let mut secrets = Vec::new();
+        for (index, header) in self.headers.iter().enumerate() {
+            match self.read_message(header) {
+                Ok(secret) => secrets.push((index, secret)),
+                Err(Error::PassphraseRequired | Error::IntegrityCheckFailed) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(secrets)
+    }
+    
+    /// The selected message's comment, see [`crate::Encoder::set_comment`],
+    /// or None without one. A sealed comment needs the passphrase like the
+    /// payload does, and reading it decrypts the whole payload.
+    pub fn comment(&self) -> Result<Option<String>, Error> {
+        let header = &self.headers[self.selected];
+        if !header.sealed_comment {
+            return Ok(Some(header.comment.as_str().to_string()).filter(|c| !c.is_empty()));
+        }
+        
+        let (comment, _) = split_comment(self.read_payload(header)?)?;
+        Ok(Some(comment).filter(|c| !c.is_empty()))
+    }
+    
+    /// Reads as much of the selected message as the image still holds,
+    /// for an image cropped or damaged after it was written, and says how
+    /// much that was and whether it checks out. Nothing is verified on the
+    /// way, so a wrong passphrase reads back as noise rather than failing.
+    /// A spread payload is scattered over the whole image, so it can't be
+    /// partly read and recovers only when it's whole.
+    pub fn recover(&self) -> Result<Recovery, Error> {
+        let header = &self.headers[self.selected];
+        if header.protection.is_some() && self.passphrase.is_none() {
+            return Err(Error::PassphraseRequired);
+        }
+        
+        let seed = utils::spread_seed(self.passphrase.as_deref().map(String::as_str));
+        let progress = |done, total| {
+            if let Some(progress) = &self.progress {
+                progress(done, total);
+            }
+        };
+        let mut payload = extract_message(self.cover.bytes(), header, seed, true, self.buffer_len, &self.cancel, &progress)?;
+        let recovered = payload.len();
+        let verified = match (&header.protection, &self.passphrase) {
+            (Some(protection), Some(passphrase)) => Some(crypto::open_unverified(passphrase, &mut payload, protection)?),
+            _ => None,
+        };
+        // A comment cut short takes the whole secret behind it with it.
+        let secret = if header.sealed_comment {
+            split_comment(payload).map(|(_, secret)| secret).unwrap_or_default()
+        } else {
+            payload
+        };
+        
+        Ok(Recovery { secret, recovered, declared: header.length as usize, verified })
+    }
+    
+    fn read_message(&self, header: &StegoHeader) -> Result<Vec<u8>, Error> {
+        let payload = self.read_payload(header)?;
+        if header.sealed_comment {
+            Ok(split_comment(payload)?.1)
+        } else {
+            Ok(payload)
+        }
+    }
+    
+    fn read_payload(&self, header: &StegoHeader) -> Result<Vec<u8>, Error> {
+        if header.protection.is_some() && self.passphrase.is_none() {
+            return Err(Error::PassphraseRequired);
+        }
+        
+        let seed = utils::spread_seed(self.passphrase.as_deref().map(String::as_str));
+        let progress = |done, total| {
+            if let Some(progress) = &self.progress {
+                progress(done, total);
+            }
+        };
+        let mut secret = extract_message(self.cover.bytes(), header, seed, false, self.buffer_len, &self.cancel, &progress)?;
+        debug!("Extracted {} payload bytes from the message at byte {}", secret.len(), header.offset);
+        if let (Some(protection), Some(passphrase)) = (&header.protection, &self.passphrase) {
+            crypto::open(passphrase, &mut secret, protection)?;
+        }
+        
+        Ok(secret)
+    }
+    
+    /// Writes the secret with [`save_payload`]. Returns the path written.
+    pub fn save(&self, output: PathBuf) -> Result<PathBuf, Error> {
+        Ok(self.save_streamed(output, false)?.0)
+    }
+    
+    /// [`Decoder::save`], also returning how much was extracted and how.
+    pub fn save_with_outcome(&self, output: PathBuf) -> Result<DecodeOutcome, Error> {
+        let started = utils::stopwatch();
+        let (output, payload_len, saving) = self.save_streamed(output, started.is_some())?;
+        let timings = started.map(|started| Timings {
+            load: self.load_time.unwrap_or_default(),
+            lsb: started.elapsed().saturating_sub(saving),
+            save: saving,
+        });
+        
+        Ok(DecodeOutcome {
+            output,
+            payload_len,
+            mask: self.mask(),
+            timings,
+        })
+    }
+    
+    /// Writes the selected message's secret to `w`, see
+    /// [`Decoder::set_buffer_len`] for how much of it is held at once.
+    /// Returns how many bytes that was. A decode that fails partway may
+    /// leave some of an unprotected secret written.
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<usize, Error> {
+        let len = self.stream(&mut |batch| Ok(w.write_all(batch)?))?;
+        w.flush()?;
+        Ok(len)
+    }
+    
+    // Hands the selected message's secret to `sink` a batch at a time as
+    // it's extracted. A protected payload is read whole and handed over at
+    // once, since none of it can be released before its tag checks out,
+    // and so is a sealed comment's, which shares that tag. A spread one is
+    // gathered from all over the image, so it's only whole at the end.
+    fn stream(&self, sink: &mut dyn FnMut(&[u8]) -> Result<(), Error>) -> Result<usize, Error> {
+        let header = &self.headers[self.selected];
+        if header.protection.is_some() || header.sealed_comment || header.spread.is_some() {
+            let secret = self.read_to_vec()?;
+            sink(&secret)?;
+            return Ok(secret.len());
+        }
+        
+        let progress = |done, total| {
+            if let Some(progress) = &self.progress {
+                progress(done, total);
+            }
+        };
+        let len = extract_batches(self.cover.bytes(), header, false, self.buffer_len, &self.cancel, &progress, sink)?;
+        debug!("Streamed {} payload bytes from the message at byte {}", len, header.offset);
+        Ok(len)
+    }
+    
+    // Writes the secret to `output` as it's streamed, along with how long
+    // writing took when `timed`. An archive is unpacked from memory, so
+    // it's read whole first. Without an extension the file is written
+    // under `output` and renamed once its contents say what it is. A
+    // decode that fails partway removes what it wrote.
+    fn save_streamed(&self, output: PathBuf, timed: bool) -> Result<(PathBuf, usize, Duration), Error> {
+        if self.headers[self.selected].archive {
+            let secret = self.read_to_vec()?;
+            let started = timed.then(Instant::now);
+            let output = save_payload(&secret, output, true)?;
+            return Ok((output, secret.len(), started.map(|s| s.elapsed()).unwrap_or_default()));
+        }
+        
+        let mut file = BufWriter::new(File::create(&output)?);
+        let mut sniffer = output.extension().is_none().then(Sniffer::default);
+        let mut saving = Duration::ZERO;
+        let streamed = self.stream(&mut |batch| {
+            let started = timed.then(Instant::now);
+            if let Some(sniffer) = &mut sniffer {
+                sniffer.feed(batch);
+            }
+            file.write_all(batch)?;
+            saving += started.map(|s| s.elapsed()).unwrap_or_default();
+            Ok(())
+        });
+        let len = match streamed.and_then(|len| Ok(file.flush().map(|_| len)?)) {
+            Ok(len) => len,
+            Err(e) => {
+                drop(file);
+                let _ = fs::remove_file(&output);
+                return Err(e);
+            }
+        };
+        drop(file);
+        
+        let Some(sniffer) = sniffer else {
+            return Ok((output, len, saving));
+        };
+        let named = output.with_extension(sniffer.extension());
+        fs::rename(&output, &named)?;
+        Ok((named, len, saving))
+    }
+}
+
+// A sealed comment sits in front of the secret as a length byte and its
+// UTF-8. The tag already passed, so a malformed one means a buggy writer.
+fn split_comment(mut payload: Vec<u8>) -> Result<(String, Vec<u8>), Error> {
+    let len = *payload.first().ok_or(Error::InvalidHeader)? as usize;
+    let comment = payload.get(1..1 + len).ok_or(Error::InvalidHeader)?;
+    let comment = std::str::from_utf8(comment).map_err(|_| Error::InvalidHeader)?.to_string();
+    payload.drain(..1 + len);
+    
+    Ok((comment, payload))
+}
+
+/// Unpacks a directory archived by [`crate::Encoder::from_directory`] into
+/// `output`, see [`archive::unpack`], when `archive` says the secret is
+/// one, as the header does, and otherwise writes it with [`save_secret`].
+/// Returns the path written.
+pub fn save_payload(secret: &[u8], output: PathBuf, archive: bool) -> Result<PathBuf, Error> {
+    if !archive {
+        return save_secret(secret, output);
+    }
+    
+    archive::unpack(secret, &output)?;
+    Ok(output)
+}
+
+/// Writes a secret already read out to `output`. An `output` without an
+/// extension gets one guessed from the secret's contents, see
+/// [`infer_extension`]. Returns the path written.
+pub fn save_secret(secret: &[u8], output: PathBuf) -> Result<PathBuf, Error> {
+    let output = if output.extension().is_none() {
+        output.with_extension(infer_extension(secret))
+    } else {
+        output
+    };
+    
+    fs::write(&output, secret)?;
+    Ok(output)
+}
+
+/// Only the header is parsed, so this works even when the payload is
+/// truncated or otherwise unreadable.
+pub fn read_header(image_path: &Path) -> Result<StegoHeader, Error> {
+    let cover = Cover::open(image_path)?;
+    StegoHeader::extract(cover.bytes())
+}
+
+/// The header of every message in the image, see [`read_header`].
+pub fn read_headers(image_path: &Path) -> Result<Vec<StegoHeader>, Error> {
+    let cover = Cover::open(image_path)?;
+    StegoHeader::extract_all(cover.bytes())
+}
+
+const SIGNATURES: [(&[u8], &str); 12] = [
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\xff\xd8\xff", "jpg"),
+    (b"GIF87a", "gif"),
+    (b"GIF89a", "gif"),
+    (b"%PDF-", "pdf"),
+    (b"PK\x03\x04", "zip"),
+    (b"\x1f\x8b", "gz"),
+    (b"7z\xbc\xaf\x27\x1c", "7z"),
+    (b"ID3", "mp3"),
+    (b"OggS", "ogg"),
+    (b"fLaC", "flac"),
+    (b"\x7fELF", "elf"),
+];
+
+/// A file extension for a secret, from the magic number of common formats.
+/// Other printable UTF-8 is `txt`, anything else `bin`.
+pub fn infer_extension(secret: &[u8]) -> &'static str {
+    let mut sniffer = Sniffer::default();
+    sniffer.feed(secret);
+    sniffer.extension()
+}
+
+// Every magic number infer_extension looks for sits within this many bytes
+// of the start.
+const SNIFF_LEN: usize = 262;
+
+// Works out infer_extension's answer for a secret fed to it in pieces.
+// Only the start is kept for the magic numbers, and whether it's all text
+// is tracked as it goes, holding back a character split between pieces.
+#[derive(Default)]
+struct Sniffer {
+    start: Vec<u8>,
+    split: Vec<u8>,
+    binary: bool,
+}
+
+impl Sniffer {
+    fn feed(&mut self, mut bytes: &[u8]) {
+        let kept = SNIFF_LEN.saturating_sub(self.start.len()).min(bytes.len());
+        self.start.extend_from_slice(&bytes[..kept]);
+        
+        while !self.split.is_empty() && !bytes.is_empty() && !self.binary {
+            self.split.push(bytes[0]);
+            bytes = &bytes[1..];
+            match std::str::from_utf8(&self.split) {
+                Ok(c) => {
+                    self.binary = !is_text(c);
+                    self.split.clear();
+                }
+                Err(e) => self.binary = e.error_len().is_some(),
+            }
+        }
+        if self.binary || !self.split.is_empty() {
+            return;
+        }
+        
+        match std::str::from_utf8(bytes) {
+            Ok(text) => self.binary = !is_text(text),
+            Err(e) if e.error_len().is_none() => {
+                let (text, split) = bytes.split_at(e.valid_up_to());
+                self.binary = !std::str::from_utf8(text).is_ok_and(is_text);
+                self.split.extend_from_slice(split);
+            }
+            Err(_) => self.binary = true,
+        }
+    }
+    
+    fn extension(&self) -> &'static str {
+        let start = &self.start[..];
+        if let Some(&(_, extension)) = SIGNATURES.iter().find(|(magic, _)| start.starts_with(magic)) {
+            return extension;
+        }
+        
+        // RIFF and tar put their identifiers past the start of the file.
+        match (start.get(..4), start.get(8..12), start.get(257..262)) {
+            (Some(b"RIFF"), Some(b"WAVE"), _) => "wav",
+            (Some(b"RIFF"), Some(b"WEBP"), _) => "webp",
+            (_, _, Some(b"ustar")) => "tar",
+            _ if !self.binary && self.split.is_empty() => "txt",
+            _ => "bin",
+        }
+    }
+}
+
+fn is_text(text: &str) -> bool {
+    text.chars().all(|c| !c.is_control() || c.is_whitespace())
+}
+
+/// Decodes the first payload of a raw carrier buffer. A spread payload is
+/// read as if it was written without a passphrase.
+pub fn extract(image: &[u8]) -> Result<Vec<u8>, Error> {
+    extract_cancellable(image, &AtomicBool::new(false))
+}
+
+pub fn extract_cancellable(image: &[u8], cancel: &AtomicBool) -> Result<Vec<u8>, Error> {
+    let (header, seed) = (StegoHeader::extract(image)?, utils::spread_seed(None));
+    extract_message(image, &header, seed, false, utils::DEFAULT_BUFFER_LEN, cancel, &|_, _| {})
+}
+
+// A `partial` read takes whatever payload bytes the image still holds
+// instead of failing when there are fewer than the header declares.
+fn extract_message(
+    image: &[u8],
+    header: &StegoHeader,
+    seed: [u8; 32],
+    partial: bool,
+    buffer_len: usize,
+    cancel: &AtomicBool,
+    progress: &dyn Fn(u64, u64)
+) -> Result<Vec<u8>, Error> {
+    let total = header.length as u64;
+    if let Some(factor) = header.spread {
+        let positions = spread_positions(image, header, seed)?;
+        let (_, body) = body(image, header)?;
+        let secret = utils::despread(body, &positions, header.length as usize, factor as usize);
+        progress(total, total);
+        return Ok(secret);
+    }
+    
+    let mut secret = Vec::with_capacity((header.length as usize).min(image.len()));
+    extract_batches(image, header, partial, buffer_len, cancel, progress, &mut |batch| {
+        secret.extend_from_slice(batch);
+        Ok(())
+    })?;
+    Ok(secret)
+}
+
+// Extracts a payload that isn't spread a batch at a time, handing each
+// batch's bytes to `sink` before reading the next. Returns how many bytes
+// that came to.
+fn extract_batches(
+    image: &[u8],
+    header: &StegoHeader,
+    partial: bool,
+    buffer_len: usize,
+    cancel: &AtomicBool,
+    progress: &dyn Fn(u64, u64),
+    sink: &mut dyn FnMut(&[u8]) -> Result<(), Error>
+) -> Result<usize, Error> {
+    let total = header.length as u64;
+    let (mask, payload) = locate(image, header, partial)?;
+    let batch_len = utils::batch_len(buffer_len, mask);
+    let mut done = 0;
+    #[cfg(feature = "simd")]
+    if let Some(bits) = crate::simd::whole_bytes(mask) {
+        for batch in payload.chunks(batch_len) {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(Error::Cancelled);
+            }
+            progress(done as u64, total);
+            let bytes = crate::simd::extract(batch, bits);
+            sink(&bytes)?;
+            done += bytes.len();
+        }
+        progress(total, total);
+        return Ok(done);
+    }
+    
+    let mut bytes = Vec::with_capacity(mask.payload_bytes_for(batch_len.min(payload.len())) + 1);
+    let (mut acc, mut filled) = (0u16, 0);
+    // Batches are whole pixels, so a byte's channel is the same counted
+    // from the start of its batch.
+    for batch in payload.chunks(batch_len) {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        progress(done as u64, total);
+        
+        bytes.clear();
+        for (i, b) in batch.iter().enumerate() {
+            let channel = mask.channel(i);
+            acc = (acc << channel.bits) | (b & channel.mask) as u16;
+            filled += channel.bits;
+            
+            if filled >= 8 {
+                filled -= 8;
+                bytes.push((acc >> filled) as u8);
+                acc &= (1 << filled) - 1;
+            }
+        }
+        sink(&bytes)?;
+        done += bytes.len();
+    }
+    
+    progress(total, total);
+    Ok(done)
+}
+
+/// How far into `image` the message `header` describes reaches: past its
+/// last payload byte, and past the neighbours an adaptive payload's
+/// texture scores read, rounded up to a whole pixel. Another message can
+/// be appended from there without disturbing it. A spread payload reaches
+/// the end of the image.
+pub fn message_end(image: &[u8], header: &StegoHeader) -> Result<usize, Error> {
+    let mask = ChannelByteMask::new(header.bits)?;
+    let (start, body) = body(image, header)?;
+    if header.spread.is_some() {
+        return Ok(image.len());
+    }
+    
+    let len = mask.carrier_len(header.length as usize * 8);
+    let used = match (header.stride, header.region, header.threshold, header.interleave) {
+        (1, None, None, 1) => len,
+        _ => chosen(body, start, mask, header, len).into_iter().max().map_or(0, |i| i + 1),
+    };
+    let guard = if header.threshold.is_some() { selection::RADIUS * 3 } else { 0 };
+    
+    Ok((start + used.next_multiple_of(3) + guard).min(image.len()))
+}
+
+/// The carrier bytes, counted from the start of the message's body, a
+/// spread payload goes into: those the stride, region and threshold pick,
+/// shuffled with `seed`. The payload takes them in order, `factor` per bit,
+/// and leaves the rest unused. Fails with [`Error::NotAStegoImage`] if there
+/// are too few for the declared length.
+pub fn spread_positions(image: &[u8], header: &StegoHeader, seed: [u8; 32]) -> Result<Vec<usize>, Error> {
+    let mask = ChannelByteMask::new(header.bits)?;
+    let (start, body) = body(image, header)?;
+    check_length(body, header, mask)?;
+    let needed = header.length as usize * 8 * header.spread.unwrap_or(1) as usize;
+    
+    let mut positions: Vec<usize> = selection::carrier_bytes(
+        body,
+        start / 3,
+        mask,
+        header.stride as usize,
+        header.region,
+        header.threshold
+    ).collect();
+    if positions.len() < needed {
+        return Err(Error::NotAStegoImage);
+    }
+    
+    utils::shuffle_positions(&mut positions, seed);
+    Ok(positions)
+}
+
+// The carrier bytes after a message's header, and where they start.
+fn body<'a>(image: &'a [u8], header: &StegoHeader) -> Result<(usize, &'a [u8]), Error> {
+    let start = header.offset as usize + HEADER_CARRIER_LEN;
+    let body = image.get(start..).ok_or(Error::NotAStegoImage)?;
+    
+    Ok((start, body))
+}
+
+// The declared length has to fit the body even if every byte of it carried
+// the payload, before anything is sized from it. A spread payload takes one
+// LSB per copy of each bit.
+fn check_length(body: &[u8], header: &StegoHeader, mask: ChannelByteMask) -> Result<(), Error> {
+    let capacity = match header.spread {
+        Some(factor) => body.len() / factor as usize / 8,
+        None => mask.payload_bytes_for(body.len()),
+    };
+    if header.length as usize > capacity {
+        return Err(Error::InvalidHeader);
+    }
+    
+    Ok(())
+}
+
+// The first `len` of the body's carrier bytes the payload takes, in the
+// order it takes them: those the stride, region and threshold pick, taken
+// channel by channel when the header interleaves them.
+fn chosen(body: &[u8], start: usize, mask: ChannelByteMask, header: &StegoHeader, len: usize) -> Vec<usize> {
+    let stride = header.stride as usize;
+    let bytes = selection::carrier_bytes(body, start / 3, mask, stride, header.region, header.threshold);
+    match header.interleave {
+        1 => bytes.take(len).collect(),
+        run => {
+            let mut bytes = selection::interleave(&bytes.collect::<Vec<_>>(), run as usize);
+            bytes.truncate(len);
+            bytes
+        }
+    }
+}
+
+// Returns the payload's mask along with the carrier bytes holding exactly
+// the declared payload, gathered from the pixels the stride, region and
+// threshold pick, in the interleave's order, when those are set.
+fn locate<'a>(image: &'a [u8], header: &StegoHeader, partial: bool) -> Result<(ChannelByteMask, Cow<'a, [u8]>), Error> {
+    let mask = ChannelByteMask::new(header.bits)?;
+    let (start, body) = body(image, header)?;
+    if !partial {
+        check_length(body, header, mask)?;
+    }
+    
+    let len = mask.carrier_len(header.length as usize * 8);
+    let payload = match (header.stride, header.region, header.threshold, header.interleave) {
+        (1, None, None, 1) => Cow::Borrowed(&body[..len.min(body.len())]),
+        _ => Cow::Owned(chosen(body, start, mask, header, len).into_iter().map(|i| body[i]).collect()),
+    };
+    if payload.len() < len && !partial {
+        return Err(Error::NotAStegoImage);
+    }
+    
+    Ok((mask, payload))
 }
\ No newline at end of file
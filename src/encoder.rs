@@ -1,67 +1,1082 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
-
-use image::{ImageBuffer, Rgb};
-
-use crate::errors::Error;
-use crate::utils::ByteMask;
-
-pub struct Encoder {
-    image: ImageBuffer<Rgb<u8>, Vec<u8>>,
-    secret: File,
-    mask: ByteMask,
-    zeroes: usize,
-}
-
-impl Encoder {
-    pub fn new(
-        image_path: PathBuf,
-        secret_path: PathBuf,
-        mask: ByteMask
-    ) -> Result<Self, Error> {
-        let image = image::open(image_path)?.to_rgb8();
-        let secret = File::open(secret_path)?;
-        let metadata = secret.metadata()?;
-        
-        let image_size = image.len();
-        let secret_size = (metadata.len() * mask.chunks as u64) as usize;
-        
-        if image_size < secret_size {
-            Err(Error::SecretTooLarge)
-        } else {
-            let zeroes = image_size - secret_size;
-            
-            Ok(Encoder {
-                image,
-                secret,
-                mask,
-                zeroes
-            })
-        }
-    }
-    
-    pub fn save(&mut self, output: PathBuf) -> Result<(), Error> {
-        let mut byte_iter = self.mask;
-        let mask = !byte_iter.mask;
-        
-        let secret_bytes = self
-            .secret
-            .try_clone()?
-            .bytes()
-            .flat_map(|b| byte_iter.set_byte(b.unwrap()));
-        
-        let image_secret_bytes = self
-            .image
-            .iter_mut()
-            .zip((0..self.zeroes).map(|_| 0).chain(secret_bytes));
-        
-        for (p, b) in image_secret_bytes {
-            *p = (*p & mask) | b; 
-        }
-        
-        self.image.save(output)?;
-        
-        Ok(())
-    }
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Take};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use image::ImageFormat;
+use log::{debug, warn};
+use rand::Rng;
+use rayon::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::analysis;
+use crate::archive;
+use crate::cover::{self, Cover};
+use crate::crypto::{self, KdfParams};
+use crate::decoder::{self, Decoder};
+use crate::errors::Error;
+use crate::header::{Comment, HEADER_CARRIER_LEN, Region, StegoHeader};
+use crate::selection;
+use crate::utils::{self, ChannelByteMask, Progress, Timings, read_bits};
+
+/// What to do with the LSBs past the end of the payload. The decoder reads
+/// only as far as the header's length, so it never looks at them.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Padding {
+    None,
+    Zero,
+    #[default]
+    Random,
+}
+
+impl FromStr for Padding {
+    type Err = Error;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Padding::None),
+            "zero" => Ok(Padding::Zero),
+            "random" => Ok(Padding::Random),
+            _ => Err(Error::InvalidPadding),
+        }
+    }
+}
+
+/// What an encode changed, see [`Encoder::save_with_report`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EmbedReport {
+    pub carrier_bytes: usize,
+    pub touched: usize,
+    pub mask: ChannelByteMask,
+    pub psnr: f64,
+}
+
+impl EmbedReport {
+    /// Percentage of the carrier bytes that changed.
+    pub fn density(&self) -> f64 {
+        100.0 * self.touched as f64 / self.carrier_bytes.max(1) as f64
+    }
+}
+
+impl fmt::Display for EmbedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes touched ({:.2}%) at {} LSB bits, PSNR {:.2} dB",
+            self.touched,
+            self.carrier_bytes,
+            self.density(),
+            self.mask,
+            self.psnr
+        )
+    }
+}
+
+/// What a finished encode wrote, see [`Encoder::save_with_outcome`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct EncodeOutcome {
+    pub output: PathBuf,
+    /// Secret bytes embedded, not counting the header or a sealed comment.
+    pub payload_len: u64,
+    pub mask: ChannelByteMask,
+    /// Only there when [`Encoder::set_report`] is on.
+    pub report: Option<EmbedReport>,
+    /// Only there when debug logging is on.
+    pub timings: Option<Timings>,
+}
+
+impl fmt::Display for EncodeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes into {} at {} LSB bits", self.payload_len, self.output.display(), self.mask)?;
+        if let Some(report) = &self.report {
+            write!(f, ", {}", report)?;
+        }
+        if let Some(timings) = &self.timings {
+            write!(f, " ({})", timings)?;
+        }
+        Ok(())
+    }
+}
+
+// Where the secret comes from. A file is only read while embedding, a batch
+// at a time, unless the payload has to be whole first.
+enum Secret {
+    Bytes(Vec<u8>),
+    File(PathBuf, u64),
+}
+
+impl Secret {
+    fn path(&self) -> Option<&Path> {
+        match self {
+            Secret::Bytes(_) => None,
+            Secret::File(path, _) => Some(path),
+        }
+    }
+    
+    fn len(&self) -> u64 {
+        match self {
+            Secret::Bytes(bytes) => bytes.len() as u64,
+            Secret::File(_, len) => *len,
+        }
+    }
+    
+    fn reader(&self) -> Result<Box<dyn Read + '_>, Error> {
+        Ok(match self {
+            Secret::Bytes(bytes) => Box::new(&bytes[..]),
+            Secret::File(path, len) => Box::new(BufReader::new(File::open(path)?).take(*len)),
+        })
+    }
+    
+    fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(self.len() as usize);
+        self.reader()?.read_to_end(&mut bytes)?;
+        
+        Ok(bytes)
+    }
+    
+    // Compares a batch at a time, so a file secret is never read whole.
+    fn matches(&self, other: &[u8]) -> Result<bool, Error> {
+        if other.len() as u64 != self.len() {
+            return Ok(false);
+        }
+        
+        let mut reader = self.reader()?;
+        let mut buffer = vec![0; STREAM_CHUNK.min(other.len())];
+        for expected in other.chunks(STREAM_CHUNK) {
+            let buffer = &mut buffer[..expected.len()];
+            reader.read_exact(buffer)?;
+            if buffer != expected {
+                return Ok(false);
+            }
+        }
+        
+        Ok(true)
+    }
+}
+
+/// Embeds a secret into a cover, see [`Encoder::save`]. An empty secret is
+/// embedded like any other, as a header declaring a length of 0, and
+/// decodes to an empty payload.
+///
+/// A secret read from a file with [`Encoder::new`] is streamed into the
+/// cover a batch at a time rather than held in memory, unless a passphrase
+/// or spreading is set: encrypting and tagging it, or scattering its bits,
+/// needs the whole payload at once. Verifying reads the output's payload
+/// back into memory either way.
+pub struct Encoder {
+    cover: Cover,
+    cover_path: Option<PathBuf>,
+    secret: Secret,
+    mask: ChannelByteMask,
+    padding: Padding,
+    stride: u16,
+    interleave: u16,
+    adaptive: bool,
+    region: Option<Region>,
+    offset: usize,
+    previous: Option<StegoHeader>,
+    spread: Option<u16>,
+    format: Option<ImageFormat>,
+    lossy_cover: bool,
+    preserve_format: bool,
+    passphrase: Option<Zeroizing<String>>,
+    kdf: KdfParams,
+    comment: Comment,
+    seal_comment: bool,
+    archive: bool,
+    verify: bool,
+    report: bool,
+    embed_report: Option<EmbedReport>,
+    load_time: Option<Duration>,
+    timings: Option<Timings>,
+    buffer_len: usize,
+    cancel: Arc<AtomicBool>,
+    progress: Option<Progress>,
+}
+
+impl Encoder {
+    /// Reads the secret from `secret_path` while saving, see [`Encoder`].
+    /// Fails with [`Error::SamePath`] if that's the cover itself. A
+    /// directory is archived first, see [`Encoder::from_directory`].
+    pub fn new(
+        image_path: PathBuf,
+        secret_path: PathBuf,
+        mask: ChannelByteMask
+    ) -> Result<Self, Error> {
+        Encoder::open(image_path, secret_path, mask, Cover::open)
+    }
+    
+    /// Like [`Encoder::new`], but converts a palette cover to true-color
+    /// instead of refusing it, so the stego image is true-color as well.
+    pub fn new_truecolor(
+        image_path: PathBuf,
+        secret_path: PathBuf,
+        mask: ChannelByteMask
+    ) -> Result<Self, Error> {
+        Encoder::open(image_path, secret_path, mask, Cover::open_truecolor)
+    }
+    
+    // `open_cover` is Cover::open or Cover::open_truecolor.
+    fn open(
+        image_path: PathBuf,
+        secret_path: PathBuf,
+        mask: ChannelByteMask,
+        open_cover: fn(&Path) -> Result<Cover, Error>
+    ) -> Result<Self, Error> {
+        if utils::same_file(&image_path, &secret_path) {
+            return Err(Error::SamePath);
+        }
+        if secret_path.is_dir() {
+            return Encoder::pack(image_path, &secret_path, mask, open_cover);
+        }
+        let len = fs::metadata(&secret_path)?.len();
+        let started = utils::stopwatch();
+        let cover = open_cover(&image_path)?;
+        
+        Encoder::from_cover_at(&image_path, cover, started, Secret::File(secret_path, len), mask)
+    }
+    
+    /// Packs the directory at `dir` into a tar archive in memory, see
+    /// [`archive::pack`], and embeds that, flagged in the header so
+    /// [`Decoder::save`] unpacks it again. An archive too large for the
+    /// cover fails with [`Error::SecretTooLarge`] after a warning saying
+    /// how large it came out.
+    pub fn from_directory(
+        image_path: PathBuf,
+        dir: &Path,
+        mask: ChannelByteMask
+    ) -> Result<Self, Error> {
+        Encoder::pack(image_path, dir, mask, Cover::open)
+    }
+    
+    fn pack(
+        image_path: PathBuf,
+        dir: &Path,
+        mask: ChannelByteMask,
+        open_cover: fn(&Path) -> Result<Cover, Error>
+    ) -> Result<Self, Error> {
+        let packed = archive::pack(dir)?;
+        debug!("Packed {} into a {} byte archive", dir.display(), packed.len());
+        let started = utils::stopwatch();
+        let cover = open_cover(&image_path)?;
+        let (len, room) = (packed.len(), capacity(cover.bytes().len(), mask, 1));
+        let mut encoder = match Encoder::from_cover_at(&image_path, cover, started, Secret::Bytes(packed), mask) {
+            Err(Error::SecretTooLarge) => {
+                warn!("{} archives to {} bytes, the cover holds {} at {} bits", dir.display(), len, room, mask);
+                return Err(Error::SecretTooLarge);
+            }
+            encoder => encoder?,
+        };
+        encoder.archive = true;
+        
+        Ok(encoder)
+    }
+    
+    /// Embeds a secret already held in memory. Fails with
+    /// [`Error::IndexedImageUnsupported`] for palette covers.
+    pub fn from_bytes(
+        image_path: PathBuf,
+        secret: Vec<u8>,
+        mask: ChannelByteMask
+    ) -> Result<Self, Error> {
+        let started = utils::stopwatch();
+        let cover = Cover::open(&image_path)?;
+        Encoder::from_cover_at(&image_path, cover, started, Secret::Bytes(secret), mask)
+    }
+    
+    // The path is only needed for the cover's format, and `started` for
+    // how long loading it took.
+    fn from_cover_at(
+        image_path: &Path,
+        cover: Cover,
+        started: Option<Instant>,
+        secret: Secret,
+        mask: ChannelByteMask
+    ) -> Result<Self, Error> {
+        let load_time = started.map(|s| s.elapsed());
+        let mut encoder = Encoder::with_secret(cover, secret, mask)?;
+        encoder.load_time = load_time;
+        encoder.cover_path = Some(image_path.to_path_buf());
+        encoder.format = ImageFormat::from_path(image_path).ok();
+        encoder.lossy_cover = (encoder.format == Some(ImageFormat::WebP)) && cover::is_lossy_webp(image_path)?;
+        Ok(encoder)
+    }
+    
+    /// Fails with [`Error::SecretTooLarge`] if the secret won't fit in
+    /// `cover`, and with [`Error::GifBitDepth`] for a GIF cover at more than
+    /// 1 bit.
+    pub fn from_cover(
+        cover: Cover,
+        secret: Vec<u8>,
+        mask: ChannelByteMask
+    ) -> Result<Self, Error> {
+        Encoder::with_secret(cover, Secret::Bytes(secret), mask)
+    }
+    
+    // GIF covers can only take 1 bit per pixel, see GifAnimation.
+    fn with_secret(cover: Cover, secret: Secret, mask: ChannelByteMask) -> Result<Self, Error> {
+        if let Cover::Gif(animation) = &cover {
+            if mask.bits().iter().any(|&bits| bits > 1) {
+                return Err(Error::GifBitDepth);
+            }
+            if !animation.is_paired() {
+                warn!("The GIF has a palette of over 128 colors, too many to pair up, so embedding may visibly change colors");
+            }
+        }
+        debug!(
+            "Capacity {} bytes at {} bits, secret {} bytes",
+            capacity(cover.bytes().len(), mask, 1),
+            mask,
+            secret.len()
+        );
+        if !fits(secret.len(), cover.bytes().len(), mask, 1) {
+            Err(Error::SecretTooLarge)
+        } else {
+            Ok(Encoder {
+                cover,
+                cover_path: None,
+                secret,
+                mask,
+                padding: Padding::default(),
+                stride: 1,
+                interleave: 1,
+                adaptive: false,
+                region: None,
+                offset: 0,
+                previous: None,
+                spread: None,
+                format: None,
+                lossy_cover: false,
+                preserve_format: true,
+                passphrase: None,
+                kdf: KdfParams::default(),
+                comment: Comment::default(),
+                seal_comment: false,
+                archive: false,
+                verify: false,
+                report: false,
+                embed_report: None,
+                load_time: None,
+                timings: None,
+                buffer_len: utils::DEFAULT_BUFFER_LEN,
+                cancel: Arc::new(AtomicBool::new(false)),
+                progress: None
+            })
+        }
+    }
+    
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+    }
+    
+    /// Spreads the secret over every `stride`th pixel. Fails with
+    /// [`Error::SecretTooLarge`] if it no longer fits.
+    pub fn set_stride(&mut self, stride: usize) -> Result<(), Error> {
+        let stride = u16::try_from(stride)
+            .ok()
+            .filter(|&s| s > 0)
+            .ok_or(Error::InvalidStride)?;
+        if !self.fits_in(self.offset, stride as usize, self.region, self.spread) {
+            return Err(Error::SecretTooLarge);
+        }
+        
+        self.stride = stride;
+        Ok(())
+    }
+    
+    /// Fills the red channels of `run` pixels with the payload, then their
+    /// green and blue, before moving on to the next `run` pixels, rather
+    /// than taking every channel of one pixel after the other. Changes end
+    /// up spread more evenly over the channels. Spread payloads pick their
+    /// own order and ignore it. Fails with [`Error::InvalidInterleave`]
+    /// unless `run` is between 1 and `u16::MAX`, or if it's more than 1 and
+    /// the mask takes different bits from each channel.
+    pub fn set_interleave(&mut self, run: usize) -> Result<(), Error> {
+        let [r, g, b] = self.mask.bits();
+        let run = u16::try_from(run)
+            .ok()
+            .filter(|&run| (run == 1) || ((run > 1) && (r == g) && (g == b)))
+            .ok_or(Error::InvalidInterleave)?;
+        
+        self.interleave = run;
+        Ok(())
+    }
+    
+    /// Confines the secret to the `width` x `height` rectangle of pixels at
+    /// (`x`, `y`), leaving the rest of the image untouched. Fails with
+    /// [`Error::RegionOutOfBounds`] if the rectangle is empty, leaves the
+    /// image or the cover isn't a still color image, and with
+    /// [`Error::SecretTooLarge`] if the secret doesn't fit inside it.
+    pub fn set_region(&mut self, x: u32, y: u32, width: u32, height: u32) -> Result<(), Error> {
+        // Selection works on groups of three carrier bytes, which only line
+        // up with pixels when each pixel has three channels.
+        let (image_width, image_height) = self.cover
+            .dimensions()
+            .filter(|_| !self.cover.is_grayscale())
+            .ok_or(Error::RegionOutOfBounds)?;
+        let inside = (width > 0)
+            && (height > 0)
+            && x.checked_add(width).is_some_and(|right| right <= image_width)
+            && y.checked_add(height).is_some_and(|bottom| bottom <= image_height);
+        if !inside {
+            return Err(Error::RegionOutOfBounds);
+        }
+        
+        let region = Region { x, y, width, height, image_width };
+        if !self.fits_in(self.offset, self.stride as usize, Some(region), self.spread) {
+            return Err(Error::SecretTooLarge);
+        }
+        
+        self.region = Some(region);
+        Ok(())
+    }
+    
+    /// Adds the secret as another message after those already in the
+    /// cover instead of overwriting them, so one image can carry secrets
+    /// for several recipients, each with its own passphrase. Fails with
+    /// [`Error::NotAStegoImage`] if the cover holds no message yet, and with
+    /// [`Error::SecretTooLarge`] if the secret doesn't fit in the room the
+    /// others leave.
+    pub fn set_append(&mut self, append: bool) -> Result<(), Error> {
+        let (offset, previous) = if append {
+            let image = self.cover.bytes();
+            let last = *StegoHeader::extract_all(image)?.last().expect("a chain has at least one header");
+            (decoder::message_end(image, &last)?, Some(last))
+        } else {
+            (0, None)
+        };
+        if !self.fits_in(offset, self.stride as usize, self.region, self.spread) {
+            return Err(Error::SecretTooLarge);
+        }
+        
+        self.offset = offset;
+        self.previous = previous;
+        Ok(())
+    }
+    
+    /// Repeats every payload bit over `factor` carrier bytes picked
+    /// pseudo-randomly with the passphrase as the seed, and has [`Decoder`]
+    /// take the majority of the copies, so the payload survives noise and
+    /// a few changed LSBs at the cost of capacity. Spread payloads take a
+    /// single LSB per carrier byte whatever the mask says. Fails with
+    /// [`Error::InvalidSpreadFactor`] unless `factor` is between 1 and
+    /// `u16::MAX`, and with [`Error::SecretTooLarge`] if the secret no
+    /// longer fits.
+    pub fn set_spread(&mut self, factor: usize) -> Result<(), Error> {
+        let factor = u16::try_from(factor)
+            .ok()
+            .filter(|&f| f > 0)
+            .ok_or(Error::InvalidSpreadFactor)?;
+        if !self.fits_in(self.offset, self.stride as usize, self.region, Some(factor)) {
+            return Err(Error::SecretTooLarge);
+        }
+        
+        self.spread = Some(factor);
+        self.mask = ChannelByteMask::uniform(1)?;
+        Ok(())
+    }
+    
+    // Capacity only counts the pixels after the header at `offset` that are
+    // inside the region, which is all of them without one. A spread payload
+    // needs `factor` carrier bytes for every bit.
+    fn fits_in(&self, offset: usize, stride: usize, region: Option<Region>, spread: Option<u16>) -> bool {
+        let start = offset + HEADER_CARRIER_LEN;
+        let body_len = self.cover.bytes().len().saturating_sub(start);
+        let pixels = selection::region_pixels(body_len, start / 3, region);
+        let len = self.payload_len();
+        match spread {
+            Some(factor) => len * 8 * factor as u64 <= (pixels.div_ceil(stride) * 3) as u64,
+            None => fits(len, HEADER_CARRIER_LEN + pixels * 3, self.mask, stride),
+        }
+    }
+    
+    // A sealed comment goes in front of the secret, see `set_comment`.
+    fn payload_len(&self) -> u64 {
+        let prefix = if self.seal_comment { 1 + self.comment.as_str().len() as u64 } else { 0 };
+        self.secret.len() + prefix
+    }
+    
+    /// Keeps the payload to the most textured pixels it needs, where LSB
+    /// changes are hardest to spot, instead of the first ones.
+    pub fn set_adaptive(&mut self, adaptive: bool) {
+        self.adaptive = adaptive;
+    }
+    
+    /// Whether to write the stego image in the cover's own format whatever
+    /// the output's extension says, so a changed format doesn't give it away.
+    /// On by default, and only known for covers opened from a path. A lossy
+    /// WebP cover can't be kept lossy any more than a JPEG can, so saving
+    /// one fails with [`Error::LossyOutputFormat`] unless this is off.
+    pub fn set_preserve_format(&mut self, preserve: bool) {
+        self.preserve_format = preserve;
+    }
+    
+    /// Encrypts the payload with a key derived from `passphrase` and tags
+    /// it with an HMAC, which [`Decoder`] then needs to check and decrypt it.
+    /// The encoder's copy is zeroed when it's dropped.
+    pub fn set_passphrase(&mut self, passphrase: &str) {
+        self.passphrase = Some(Zeroizing::new(passphrase.to_string()));
+    }
+    
+    /// The Argon2id cost of deriving the keys from the passphrase, stored in
+    /// the header so decoding uses the same.
+    pub fn set_kdf_params(&mut self, kdf: KdfParams) {
+        self.kdf = kdf;
+    }
+    
+    /// A short note, at most [`crate::header::MAX_COMMENT_LEN`] bytes, kept
+    /// in the header where [`Decoder::comment`] and `info` read it without
+    /// the passphrase. Fails with [`Error::CommentTooLong`] past that.
+    pub fn set_comment(&mut self, comment: &str) -> Result<(), Error> {
+        self.comment = Comment::new(comment)?;
+        Ok(())
+    }
+    
+    /// Puts the comment in front of the payload instead, so it's encrypted
+    /// along with it and only readable with the passphrase. Saving fails
+    /// with [`Error::PassphraseRequired`] when there's none.
+    pub fn set_seal_comment(&mut self, seal: bool) {
+        self.seal_comment = seal;
+    }
+    
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+    
+    pub fn set_report(&mut self, report: bool) {
+        self.report = report;
+    }
+    
+    /// How many payload bytes to read from the secret and embed at a time,
+    /// [`utils::DEFAULT_BUFFER_LEN`] unless set. A larger buffer takes that
+    /// much more memory for a file secret but means fewer, longer batches,
+    /// and a batch spanning a MiB of carrier bytes or more is embedded on
+    /// several threads. A smaller one makes cancelling and progress reports
+    /// more responsive, see [`Encoder::set_progress`].
+    pub fn set_buffer_len(&mut self, len: usize) {
+        self.buffer_len = len;
+    }
+    
+    /// Setting `cancel` makes a running [`Encoder::save`] stop at the next
+    /// batch with [`Error::Cancelled`], removing any output it wrote.
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+    
+    /// `progress` is called after every batch [`Encoder::save`] embeds, and
+    /// once more when a spread payload is done.
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = Some(progress);
+    }
+    
+    /// The PSNR of the last save, when [`Encoder::set_report`] is on.
+    pub fn psnr(&self) -> Option<f64> {
+        self.embed_report.map(|r| r.psnr)
+    }
+    
+    /// What the last save changed, when [`Encoder::set_report`] is on.
+    pub fn report(&self) -> Option<EmbedReport> {
+        self.embed_report
+    }
+    
+    /// [`Encoder::save`], also reporting how many carrier bytes the
+    /// embedding changed and how visibly.
+    pub fn save_with_report(&mut self, output: PathBuf) -> Result<(PathBuf, EmbedReport), Error> {
+        let reporting = self.report;
+        self.report = true;
+        let saved = self.save(output);
+        self.report = reporting;
+        
+        let output = saved?;
+        Ok((output, self.embed_report.expect("save reports when asked to")))
+    }
+    
+    /// [`Encoder::save`], also returning what was embedded and, when
+    /// [`Encoder::set_report`] is on, how visibly.
+    pub fn save_with_outcome(&mut self, output: PathBuf) -> Result<EncodeOutcome, Error> {
+        let output = self.save(output)?;
+        Ok(EncodeOutcome {
+            output,
+            payload_len: self.secret.len(),
+            mask: self.mask,
+            report: self.embed_report,
+            timings: self.timings,
+        })
+    }
+    
+    /// Writes the stego file and returns the path written. Its format is the
+    /// cover's when [`Encoder::set_preserve_format`] is on, with the extension
+    /// changed to match, and otherwise the one `output`'s extension implies.
+    /// A missing extension defaults to the cover's own kind, PNG for still
+    /// images, and GIF, APNG and WAV covers always get their own. Image
+    /// formats the `image` crate can't write fail with
+    /// [`Error::UnknownOutputFormat`], and lossy ones, which would destroy
+    /// the payload, with [`Error::LossyOutputFormat`]. Writing over the
+    /// secret file, or over the cover unless appending to it, fails with
+    /// [`Error::SamePath`]. An output directory that's missing or read-only
+    /// fails with [`Error::OutputNotWritable`] before anything is embedded.
+    pub fn save(&mut self, output: PathBuf) -> Result<PathBuf, Error> {
+        let started = utils::stopwatch();
+        let output = self.embed_for(output)?;
+        let embedded = utils::stopwatch();
+        self.cover.save(&output)?;
+        debug!("Saved {}", output.display());
+        self.timings = self.timings_since(started, embedded);
+        if self.cancel.load(Ordering::Relaxed) {
+            fs::remove_file(&output)?;
+            return Err(Error::Cancelled);
+        }
+        
+        if self.verify {
+            verify_output(&output, &self.secret, self.passphrase.as_deref().map(String::as_str))?;
+        }
+        
+        Ok(output)
+    }
+    
+    /// Everything [`Encoder::save`] does short of writing the file: the
+    /// same checks, sealing and embedding, into a copy of the cover kept in
+    /// memory. The outcome always carries a report, so it says what the
+    /// save would write and how visibly, and the encoder can still save
+    /// afterwards.
+    pub fn dry_run(&mut self, output: PathBuf) -> Result<EncodeOutcome, Error> {
+        let cover = self.cover.bytes().to_vec();
+        let reporting = self.report;
+        self.report = true;
+        let started = utils::stopwatch();
+        let embedded = self.embed_for(output);
+        self.report = reporting;
+        self.cover.bytes_mut().copy_from_slice(&cover);
+        
+        Ok(EncodeOutcome {
+            output: embedded?,
+            payload_len: self.secret.len(),
+            mask: self.mask,
+            report: self.embed_report.take(),
+            timings: self.timings_since(started, None),
+        })
+    }
+    
+    // None with debug logging off. A dry run saves nothing, so it has no
+    // `embedded` and its save takes no time.
+    fn timings_since(&self, started: Option<Instant>, embedded: Option<Instant>) -> Option<Timings> {
+        let started = started?;
+        let lsb = embedded.map_or_else(|| started.elapsed(), |e| e.duration_since(started));
+        Some(Timings {
+            load: self.load_time.unwrap_or_default(),
+            lsb,
+            save: embedded.map(|e| e.elapsed()).unwrap_or_default(),
+        })
+    }
+    
+    // Checks `output` and embeds the payload into the cover in memory,
+    // returning the path the stego file is to be written to.
+    fn embed_for(&mut self, output: PathBuf) -> Result<PathBuf, Error> {
+        let preserve = self.format.filter(|_| self.preserve_format);
+        if let Some(format) = preserve.filter(|_| self.lossy_cover) {
+            return Err(Error::LossyOutputFormat(format));
+        }
+        let output = output_path(&self.cover, output, preserve)?;
+        // Appending adds to the stego image it read, so writing it back over
+        // itself is the point there.
+        let cover = self.cover_path.as_deref().filter(|_| self.previous.is_none());
+        if cover.into_iter().chain(self.secret.path()).any(|p| utils::same_file(p, &output)) {
+            return Err(Error::SamePath);
+        }
+        utils::check_writable(&output)?;
+        if self.seal_comment && self.passphrase.is_none() {
+            return Err(Error::PassphraseRequired);
+        }
+        if !self.fits_in(self.offset, self.stride as usize, self.region, self.spread) {
+            return Err(Error::SecretTooLarge);
+        }
+        // The payload fits, so its length fits a usize and the header.
+        let len = self.payload_len() as usize;
+        let mut whole = if self.passphrase.is_some() || self.spread.is_some() {
+            let mut payload = Vec::with_capacity(len);
+            if self.seal_comment {
+                payload.push(self.comment.as_str().len() as u8);
+                payload.extend_from_slice(self.comment.as_str().as_bytes());
+            }
+            payload.extend(self.secret.to_vec()?);
+            Some(payload)
+        } else {
+            None
+        };
+        let protection = match (self.passphrase.as_deref().map(String::as_str), whole.as_mut()) {
+            (Some(passphrase), Some(payload)) => Some(crypto::seal(passphrase, payload, self.kdf)?),
+            _ => None,
+        };
+        
+        let grayscale = self.cover.is_grayscale();
+        let image = self.cover.bytes_mut();
+        let start = self.offset + HEADER_CARRIER_LEN;
+        let threshold = self.adaptive.then(|| {
+            let bits = len * 8;
+            let needed = match self.spread {
+                Some(factor) => (bits * factor as usize).div_ceil(3),
+                None => self.mask.carrier_len(bits).div_ceil(3),
+            };
+            let body = &image[start..];
+            selection::threshold(body, start / 3, self.mask, self.stride as usize, self.region, needed)
+        });
+        let header = StegoHeader {
+            stride: self.stride,
+            threshold,
+            region: self.region,
+            protection,
+            offset: self.offset as u32,
+            spread: self.spread,
+            grayscale,
+            interleave: if self.spread.is_some() { 1 } else { self.interleave },
+            comment: if self.seal_comment { Comment::default() } else { self.comment },
+            sealed_comment: self.seal_comment,
+            archive: self.archive,
+            ..StegoHeader::new(self.mask.bits(), len as u32)
+        };
+        let original = self.report.then(|| image.to_vec());
+        debug!("Writing {:?}", header);
+        
+        let progress = |done, total| {
+            if let Some(progress) = &self.progress {
+                progress(done, total);
+            }
+        };
+        let batches = Batches { buffer_len: self.buffer_len, cancel: &self.cancel, progress: &progress };
+        match (&whole, self.spread) {
+            (Some(payload), Some(_)) => {
+                let seed = utils::spread_seed(self.passphrase.as_deref().map(String::as_str));
+                embed_spread(image, &header, payload, seed, self.padding)?;
+                progress(len as u64, len as u64);
+            }
+            (Some(payload), None) => {
+                let secret = &mut payload.take(len as u64);
+                embed_tracked(image, &header, secret, self.mask, self.padding, &batches)?;
+            }
+            (None, _) => {
+                let secret = &mut self.secret.reader()?.take(len as u64);
+                embed_tracked(image, &header, secret, self.mask, self.padding, &batches)?;
+            }
+        }
+        debug!("Embedded {} payload bytes", len);
+        if let Some(previous) = self.previous {
+            debug!("Linking the header at byte {} to this one", previous.offset);
+            let previous = StegoHeader { next: Some(header.offset), ..previous };
+            previous.embed(&mut image[previous.offset as usize..]);
+        }
+        self.embed_report = original.map(|original| EmbedReport {
+            carrier_bytes: image.len(),
+            touched: original.iter().zip(image.iter()).filter(|(a, b)| a != b).count(),
+            mask: self.mask,
+            psnr: analysis::psnr(&original, image),
+        });
+        
+        Ok(output)
+    }
+}
+
+// Formats whose compression would wipe the LSBs out.
+const LOSSY_FORMATS: [ImageFormat; 2] = [ImageFormat::Jpeg, ImageFormat::Avif];
+
+// GIF, APNG and WAV covers are always written back as what they are, under
+// their own extension whatever `output` has. Only still images pick their
+// format from the extension, or from `preserve` when set.
+fn output_path(cover: &Cover, output: PathBuf, preserve: Option<ImageFormat>) -> Result<PathBuf, Error> {
+    let (default, still) = match cover {
+        Cover::Gif(_) => ("gif", false),
+        Cover::Apng(_) => ("png", false),
+        Cover::Wav(_) => ("wav", false),
+        Cover::Rgb(_) | Cover::Luma(_) | Cover::Rgb16(_) | Cover::Luma16(_) => ("png", true),
+    };
+    // Spellings like .tif and .tiff or .jpg and .jpeg are left alone.
+    let output = match preserve {
+        Some(format) if still && (ImageFormat::from_path(&output).ok() != Some(format)) => {
+            output.with_extension(format.extensions_str()[0])
+        }
+        _ => output,
+    };
+    let Some(extension) = output.extension() else {
+        return Ok(output.with_extension(default));
+    };
+    if !still {
+        let own = extension.eq_ignore_ascii_case(default) || (matches!(cover, Cover::Apng(_)) && extension.eq_ignore_ascii_case("apng"));
+        return Ok(if own { output } else { output.with_extension(default) });
+    }
+    
+    match ImageFormat::from_extension(extension) {
+        Some(format) if LOSSY_FORMATS.contains(&format) => Err(Error::LossyOutputFormat(format)),
+        Some(format) if format.writing_enabled() => Ok(output),
+        _ => Err(Error::UnknownOutputFormat(extension.to_string_lossy().into_owned())),
+    }
+}
+
+// Any decode error counts as a failed verification, the output is what
+// the user would be handed either way. The message just written is always
+// the last one.
+fn verify_output(output: &Path, secret: &Secret, passphrase: Option<&str>) -> Result<(), Error> {
+    let extracted = Decoder::new(output.to_path_buf()).and_then(|mut d| {
+        if let Some(passphrase) = passphrase {
+            d.set_passphrase(passphrase);
+        }
+        d.select(d.headers().len() - 1)?;
+        d.read_to_vec()
+    });
+    
+    if extracted.is_ok_and(|extracted| secret.matches(&extracted).unwrap_or(false)) {
+        Ok(())
+    } else {
+        Err(Error::VerificationFailed)
+    }
+}
+
+// Below this many image bytes the rayon split costs more than it saves.
+const PARALLEL_THRESHOLD: usize = 1 << 20;
+const PARALLEL_CHUNK: usize = 1 << 16;
+
+// How much of a file secret is compared against the output at a time.
+const STREAM_CHUNK: usize = 1 << 16;
+
+pub fn embed(
+    image: &mut [u8],
+    header: &StegoHeader,
+    secret: &[u8],
+    mask: ChannelByteMask,
+    padding: Padding
+) {
+    // Nothing else can see this flag, so every batch runs.
+    let _ = embed_cancellable(image, header, secret, mask, padding, &AtomicBool::new(false));
+}
+
+pub fn embed_cancellable(
+    image: &mut [u8],
+    header: &StegoHeader,
+    secret: &[u8],
+    mask: ChannelByteMask,
+    padding: Padding,
+    cancel: &AtomicBool
+) -> Result<(), Error> {
+    embed_from(image, header, &mut &secret[..], secret.len(), mask, padding, cancel)
+}
+
+/// Like [`embed_cancellable`], but reads the `len` bytes of the secret from
+/// `secret` one batch at a time as they're embedded, so they never have to
+/// be in memory all at once.
+pub fn embed_from(
+    image: &mut [u8],
+    header: &StegoHeader,
+    secret: &mut dyn Read,
+    len: usize,
+    mask: ChannelByteMask,
+    padding: Padding,
+    cancel: &AtomicBool
+) -> Result<(), Error> {
+    let batches = Batches { buffer_len: utils::DEFAULT_BUFFER_LEN, cancel, progress: &|_, _| {} };
+    embed_tracked(image, header, &mut secret.take(len as u64), mask, padding, &batches)
+}
+
+// How an embed splits the payload up, and what it checks and reports on
+// between batches.
+struct Batches<'a> {
+    buffer_len: usize,
+    cancel: &'a AtomicBool,
+    progress: &'a dyn Fn(u64, u64),
+}
+
+// The secret comes limited to its length, which is what the header says.
+fn embed_tracked<R: Read>(
+    image: &mut [u8],
+    header: &StegoHeader,
+    secret: &mut Take<R>,
+    mask: ChannelByteMask,
+    padding: Padding,
+    batches: &Batches
+) -> Result<(), Error> {
+    let start = header.offset as usize + HEADER_CARRIER_LEN;
+    let (head, body) = image[header.offset as usize..].split_at_mut(HEADER_CARRIER_LEN);
+    header.embed(head);
+    
+    let stride = header.stride as usize;
+    let plain = (stride == 1) && header.threshold.is_none() && header.region.is_none();
+    if plain && (header.interleave == 1) {
+        return embed_body(body, secret, mask, padding, batches);
+    }
+    
+    // Embed into a copy of the chosen bytes, in the order the interleave
+    // takes them, and write them back after. The selection is read off the
+    // untouched original, though embedding never changes it anyway.
+    let original = body.to_vec();
+    let chosen: Vec<usize> = selection::carrier_bytes(&original, start / 3, mask, stride, header.region, header.threshold)
+        .collect();
+    let chosen = selection::interleave(&chosen, header.interleave as usize);
+    let mut carrier: Vec<u8> = chosen.iter().map(|&i| original[i]).collect();
+    embed_body(&mut carrier, secret, mask, padding, batches)?;
+    for (i, c) in chosen.into_iter().zip(carrier) {
+        body[i] = c;
+    }
+    
+    Ok(())
+}
+
+/// Embeds `secret` into the carrier bytes [`decoder::spread_positions`]
+/// picks for `seed`, `header.spread` copies per bit, and pads the ones it
+/// leaves unused.
+pub fn embed_spread(
+    image: &mut [u8],
+    header: &StegoHeader,
+    secret: &[u8],
+    seed: [u8; 32],
+    padding: Padding
+) -> Result<(), Error> {
+    let positions = decoder::spread_positions(image, header, seed)?;
+    let factor = header.spread.unwrap_or(1) as usize;
+    let (head, body) = image[header.offset as usize..].split_at_mut(HEADER_CARRIER_LEN);
+    header.embed(head);
+    
+    utils::spread(body, &positions, secret, factor);
+    let unused = &positions[(secret.len() * 8 * factor).min(positions.len())..];
+    let mut rest: Vec<u8> = unused.iter().map(|&p| body[p]).collect();
+    pad(&mut rest, 0, ChannelByteMask::uniform(1)?, padding);
+    for (&p, r) in unused.iter().zip(rest) {
+        body[p] = r;
+    }
+    
+    Ok(())
+}
+
+// Each batch reads just the secret bytes its carrier bytes hold, which
+// start on a byte boundary, into one buffer reused for all of them.
+fn embed_body<R: Read>(
+    body: &mut [u8],
+    secret: &mut Take<R>,
+    mask: ChannelByteMask,
+    padding: Padding,
+    batches: &Batches
+) -> Result<(), Error> {
+    let len = secret.limit() as usize;
+    let used = mask.carrier_len(len * 8).min(body.len());
+    let (payload, rest) = body.split_at_mut(used);
+    let batch_len = utils::batch_len(batches.buffer_len, mask);
+    let mut chunk = vec![0; mask.payload_bytes_for(batch_len).min(len)];
+    
+    for (b, batch) in payload.chunks_mut(batch_len).enumerate() {
+        if batches.cancel.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        
+        let first = mask.bit_offset(b * batch_len) / 8;
+        let done = (mask.bit_offset(b * batch_len + batch.len()).div_ceil(8)).min(len);
+        let chunk = &mut chunk[..done - first];
+        secret.read_exact(chunk)?;
+        embed_batch(batch, chunk, mask);
+        (batches.progress)(done as u64, len as u64);
+    }
+    
+    pad(rest, used, mask, padding);
+    Ok(())
+}
+
+// A PARALLEL_CHUNK is a whole number of payload bytes at every depth the
+// whole-byte loops take, so the batch splits along payload byte boundaries.
+fn embed_batch(batch: &mut [u8], chunk: &[u8], mask: ChannelByteMask) {
+    #[cfg(feature = "simd")]
+    if let Some(bits) = crate::simd::whole_bytes(mask) {
+        let lanes = 8 / bits as usize;
+        batch
+            .par_chunks_mut(PARALLEL_CHUNK)
+            .enumerate()
+            .for_each(|(c, carrier)| {
+                let secret = chunk.get(c * PARALLEL_CHUNK / lanes..).unwrap_or_default();
+                crate::simd::embed(carrier, secret, bits);
+            });
+        return;
+    }
+    if batch.len() < PARALLEL_THRESHOLD {
+        embed_sequential(batch, chunk, mask);
+    } else {
+        embed_parallel(batch, chunk, mask);
+    }
+}
+
+fn pad(rest: &mut [u8], start: usize, mask: ChannelByteMask, padding: Padding) {
+    let channels = rest
+        .iter_mut()
+        .enumerate()
+        .map(|(i, p)| (p, mask.channel(start + i).mask));
+    
+    match padding {
+        Padding::None => {}
+        Padding::Zero => channels.for_each(|(p, m)| *p &= !m),
+        Padding::Random => {
+            let mut rng = rand::rng();
+            channels.for_each(|(p, m)| *p = (*p & !m) | (rng.random::<u8>() & m));
+        }
+    }
+}
+
+/// Overwrites the bits `mask` covers in every carrier byte with zeros or
+/// noise, wiping out anything hidden there along with its header. Returns
+/// how many bytes changed.
+pub fn sanitize(image: &mut [u8], mask: ChannelByteMask, padding: Padding) -> usize {
+    let original = image.to_vec();
+    pad(image, 0, mask, padding);
+    
+    original.iter().zip(image.iter()).filter(|(a, b)| a != b).count()
+}
+
+pub fn embed_sequential(image: &mut [u8], secret: &[u8], mask: ChannelByteMask) {
+    let mut offset = 0;
+    
+    for (i, p) in image.iter_mut().enumerate() {
+        let channel = mask.channel(i);
+        *p = (*p & !channel.mask) | read_bits(secret, offset, channel.bits);
+        offset += channel.bits as usize;
+    }
+}
+
+pub fn embed_parallel(image: &mut [u8], secret: &[u8], mask: ChannelByteMask) {
+    image
+        .par_chunks_mut(PARALLEL_CHUNK)
+        .enumerate()
+        .for_each(|(c, chunk)| {
+            let base = c * PARALLEL_CHUNK;
+            for (i, p) in chunk.iter_mut().enumerate() {
+                let channel = mask.channel(base + i);
+                let offset = mask.bit_offset(base + i);
+                *p = (*p & !channel.mask) | read_bits(secret, offset, channel.bits);
+            }
+        });
+}
+
+/// Payload bytes a carrier of `image_len` bytes can hold at `mask` when
+/// using every `stride`th pixel. The header's length field caps a single
+/// payload at u32::MAX bytes.
+pub fn capacity(image_len: usize, mask: ChannelByteMask, stride: usize) -> usize {
+    let pixels = (image_len.saturating_sub(HEADER_CARRIER_LEN) / 3).div_ceil(stride);
+    mask.payload_bytes_for(pixels * 3).min(u32::MAX as usize)
+}
+
+/// Whether a secret of `secret_len` bytes fits, compared in u64 so a length
+/// that doesn't fit in a usize still fails the check rather than wrapping.
+pub fn fits(secret_len: u64, image_len: usize, mask: ChannelByteMask, stride: usize) -> bool {
+    (image_len >= HEADER_CARRIER_LEN) && (secret_len <= capacity(image_len, mask, stride) as u64)
+}
+
+/// The fewest LSB bits per channel, up to `max_bits`, at which a secret of
+/// `secret_len` bytes fits a carrier of `image_len` bytes, so the cover
+/// changes as little as it can. Fails with [`Error::SecretTooLarge`] if
+/// even `max_bits` isn't enough.
+pub fn fitting_bits(secret_len: u64, image_len: usize, max_bits: u8) -> Result<u8, Error> {
+    // Every carrier byte past the header holds `bits` of the payload, which
+    // is where the search starts; rounding down to whole pixels only ever
+    // asks for more.
+    let carrier = image_len.saturating_sub(HEADER_CARRIER_LEN).max(1) as u64;
+    let estimate = secret_len.saturating_mul(8).div_ceil(carrier).clamp(1, 8) as u8;
+    
+    (estimate..=max_bits.min(8))
+        .find(|&bits| ChannelByteMask::uniform(bits).is_ok_and(|mask| fits(secret_len, image_len, mask, 1)))
+        .ok_or(Error::SecretTooLarge)
 }
\ No newline at end of file
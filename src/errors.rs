@@ -1,33 +1,177 @@
-#[derive(Debug)]
-pub enum Error {
-    SecretReadError,
-    SecretTooLarge,
-    InvalidNumberOfBits,
-    ImageReadWriteError
-}
-
-impl std::error::Error for Error {}
-
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Error::SecretReadError => write!(f, "Something when while reading secret file"),
-            Error::SecretTooLarge => write!(f, "Secret is too large to fit in image"),
-            Error::InvalidNumberOfBits => write!(f, "Only 1 to 8 LSB bits are allowed"),
-            Error::ImageReadWriteError => write!(f, "Something went wrong while processing the image")
-        }   
-    } 
-}
-
-impl From<std::io::Error> for Error {
-    fn from(_value: std::io::Error) -> Self {
-        Error::SecretReadError
-    }
-}
-
-impl From<image::ImageError> for Error {
-    fn from(_value: image::ImageError) -> Self {
-        Error::ImageReadWriteError
-    }
-}
-
+/// Every error the library and the app can report.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    SecretTooLarge,
+    InvalidNumberOfBits,
+    InvalidChannelSpec,
+    InvalidChannels,
+    InvalidPadding,
+    InvalidStride,
+    InvalidSpreadFactor,
+    InvalidInterleave,
+    CommentTooLong,
+    Image(image::ImageError),
+    UnsupportedImageFormat(image::error::UnsupportedError),
+    UnknownOutputFormat(String),
+    LossyOutputFormat(image::ImageFormat),
+    DimensionMismatch,
+    RegionOutOfBounds,
+    QrTooLarge,
+    InvalidQr,
+    InvalidShard,
+    ShardGroupMismatch,
+    Config(toml::ser::Error),
+    GifDecode(gif::DecodingError),
+    GifEncode(gif::EncodingError),
+    ApngDecode(png::DecodingError),
+    ApngEncode(png::EncodingError),
+    Wav(hound::Error),
+    UnsupportedAudio,
+    IndexedImageUnsupported,
+    GifBitDepth,
+    NotAStegoImage,
+    InvalidHeader,
+    UnsupportedStegoVersion(u8),
+    MessageNotFound(usize),
+    VerificationFailed,
+    SamePath,
+    OutputNotWritable(std::path::PathBuf),
+    IntegrityCheckFailed,
+    PassphraseRequired,
+    Kdf(argon2::Error),
+    KdfCostTooHigh,
+    Cancelled
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::UnsupportedImageFormat(e) => Some(e),
+            Error::Config(e) => Some(e),
+            Error::GifDecode(e) => Some(e),
+            Error::GifEncode(e) => Some(e),
+            Error::ApngDecode(e) => Some(e),
+            Error::ApngEncode(e) => Some(e),
+            Error::Wav(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::SecretTooLarge => write!(f, "Secret is too large to fit in image"),
+            Error::InvalidNumberOfBits => write!(f, "Only 1 to 8 LSB bits are allowed"),
+            Error::InvalidChannelSpec => write!(f, "Bits must be a single number or a per-channel spec like R:1,G:3,B:2"),
+            Error::InvalidChannels => write!(f, "Channels must name each of r, g and b at most once and at least one of them, like rgb, rb or g"),
+            Error::InvalidPadding => write!(f, "Padding must be one of none, zero or random"),
+            Error::InvalidStride => write!(f, "Stride must be between 1 and {}", u16::MAX),
+            Error::InvalidSpreadFactor => write!(f, "Spread factor must be between 1 and {}", u16::MAX),
+            Error::CommentTooLong => write!(f, "Comments may be at most {} bytes", crate::header::MAX_COMMENT_LEN),
+            Error::InvalidInterleave => write!(f, "Interleave must be between 1 and {} and needs the same bits on every channel", u16::MAX),
+            Error::Image(e) => write!(f, "Image error: {}", e),
+            Error::UnsupportedImageFormat(e) => write!(f, "Unsupported image format: {}", e),
+            Error::UnknownOutputFormat(ext) => write!(f, "Cannot write images with the extension \"{}\", use e.g. .png or .bmp", ext),
+            Error::LossyOutputFormat(format) => write!(f, "{:?} is lossy and would destroy the hidden data, save as PNG or another lossless format", format),
+            Error::DimensionMismatch => write!(f, "The two images have different dimensions"),
+            Error::RegionOutOfBounds => write!(f, "The region must be a non-empty rectangle inside a still color image"),
+            Error::QrTooLarge => write!(f, "Secret is too large for a QR code, which holds at most 1,273 bytes at this error correction level"),
+            Error::InvalidQr => write!(f, "The payload is not a readable QR code"),
+            Error::InvalidShard => write!(f, "Shards are missing, duplicated or corrupt"),
+            Error::ShardGroupMismatch => write!(f, "Shards belong to different encodes"),
+            Error::Config(e) => write!(f, "Could not write config: {}", e),
+            Error::GifDecode(e) => write!(f, "Could not read GIF: {}", e),
+            Error::GifEncode(e) => write!(f, "Could not write GIF: {}", e),
+            Error::ApngDecode(e) => write!(f, "Could not read APNG: {}", e),
+            Error::ApngEncode(e) => write!(f, "Could not write APNG: {}", e),
+            Error::Wav(e) => write!(f, "WAV error: {}", e),
+            Error::UnsupportedAudio => write!(f, "Only uncompressed 16-bit PCM WAV files are supported"),
+            Error::IndexedImageUnsupported => write!(f, "Indexed/palettized images are not supported, convert the cover to true-color first, e.g. with encode --truecolor"),
+            Error::GifBitDepth => write!(f, "GIF covers carry 1 bit per pixel, any more would turn pixels into other palette colors"),
+            Error::NotAStegoImage => write!(f, "No hidden data found, this does not look like a stego image"),
+            Error::InvalidHeader => write!(f, "The hidden data's header is corrupt, it declares more than the image can hold"),
+            Error::UnsupportedStegoVersion(version) => write!(f, "Stego format version {} is not supported by this build", version),
+            Error::MessageNotFound(index) => write!(f, "The image holds no message number {}", index),
+            Error::VerificationFailed => write!(f, "The written file does not decode back to the secret, check the output format"),
+            Error::SamePath => write!(f, "The cover, the secret and the output must all be different files"),
+            Error::OutputNotWritable(dir) => write!(f, "Cannot write to {}, it doesn't exist or isn't writable", dir.display()),
+            Error::IntegrityCheckFailed => write!(f, "The payload failed its integrity check, it was altered or the passphrase is wrong"),
+            Error::PassphraseRequired => write!(f, "The payload is protected by a passphrase, supply it to decode"),
+            Error::Kdf(e) => write!(f, "Key derivation failed: {}", e),
+            Error::KdfCostTooHigh => write!(
+                f,
+                "Key derivation may use at most {} MiB and {} passes",
+                crate::crypto::MAX_KDF.memory_kib / 1024,
+                crate::crypto::MAX_KDF.iterations
+            ),
+            Error::Cancelled => write!(f, "Cancelled")
+        }   
+    } 
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(value: image::ImageError) -> Self {
+        match value {
+            image::ImageError::Unsupported(e) => Error::UnsupportedImageFormat(e),
+            image::ImageError::IoError(e) => Error::Io(e),
+            e => Error::Image(e)
+        }
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(value: toml::ser::Error) -> Self {
+        Error::Config(value)
+    }
+}
+
+impl From<gif::DecodingError> for Error {
+    fn from(value: gif::DecodingError) -> Self {
+        Error::GifDecode(value)
+    }
+}
+
+impl From<gif::EncodingError> for Error {
+    fn from(value: gif::EncodingError) -> Self {
+        Error::GifEncode(value)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(value: png::DecodingError) -> Self {
+        match value {
+            png::DecodingError::IoError(e) => Error::Io(e),
+            e => Error::ApngDecode(e)
+        }
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(value: png::EncodingError) -> Self {
+        match value {
+            png::EncodingError::IoError(e) => Error::Io(e),
+            e => Error::ApngEncode(e)
+        }
+    }
+}
+
+impl From<hound::Error> for Error {
+    fn from(value: hound::Error) -> Self {
+        match value {
+            hound::Error::IoError(e) => Error::Io(e),
+            hound::Error::Unsupported => Error::UnsupportedAudio,
+            e => Error::Wav(e)
+        }
+    }
+}
\ No newline at end of file
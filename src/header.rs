@@ -0,0 +1,306 @@
+use std::fmt;
+use std::str;
+
+use crate::crypto::{KdfParams, Protection, SALT_LEN, TAG_LEN};
+use crate::errors::Error;
+use crate::utils::ChannelByteMask;
+
+pub const MAGIC: &[u8; 4] = b"STGN";
+
+pub const HEADER_VERSION: u8 = 12;
+
+pub const HEADER_LEN: usize = 1 + MAGIC.len() + 3 + 2 + 4 + 1 + 4 + REGION_LEN + PROTECTION_LEN + 4 + 4 + 2 + 2 + 1 + MAX_COMMENT_LEN;
+
+/// Longest comment a header holds, in bytes of UTF-8.
+pub const MAX_COMMENT_LEN: usize = 64;
+
+const REGION_LEN: usize = 5 * 4;
+
+const PROTECTION_LEN: usize = SALT_LEN + 4 + 4 + TAG_LEN;
+
+const FLAG_PROTECTED: u8 = 1;
+
+const FLAG_ADAPTIVE: u8 = 2;
+
+const FLAG_REGION: u8 = 4;
+
+const FLAG_SPREAD: u8 = 8;
+
+const FLAG_GRAYSCALE: u8 = 16;
+
+const FLAG_SEALED_COMMENT: u8 = 32;
+
+const FLAG_ARCHIVE: u8 = 64;
+
+// The header always takes a single LSB per carrier byte so it can be read
+// before the payload's bit depth is known. It's rounded up to a whole pixel
+// so the payload still starts on a red byte.
+pub const HEADER_CARRIER_LEN: usize = (HEADER_LEN * 8).next_multiple_of(3);
+
+/// A rectangle of the image, in pixels, the payload is confined to. The
+/// image's width is kept alongside since the flat carrier doesn't record it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub image_width: u32,
+}
+
+impl Region {
+    /// Whether the image's `pixel`th pixel, counting row by row, is inside.
+    pub fn contains(&self, pixel: usize) -> bool {
+        let (x, y) = (pixel % self.image_width as usize, pixel / self.image_width as usize);
+        (x >= self.x as usize) && (x - (self.x as usize) < self.width as usize)
+            && (y >= self.y as usize) && (y - (self.y as usize) < self.height as usize)
+    }
+}
+
+/// A short note stored in the header next to the payload, readable without
+/// the passphrase. Empty when there's none.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Comment {
+    len: u8,
+    bytes: [u8; MAX_COMMENT_LEN],
+}
+
+impl Comment {
+    /// Fails with [`Error::CommentTooLong`] past [`MAX_COMMENT_LEN`] bytes.
+    pub fn new(text: &str) -> Result<Self, Error> {
+        let mut bytes = [0; MAX_COMMENT_LEN];
+        bytes.get_mut(..text.len()).ok_or(Error::CommentTooLong)?.copy_from_slice(text.as_bytes());
+        
+        Ok(Comment { len: text.len() as u8, bytes })
+    }
+    
+    // None unless `len` is in range and the bytes up to it are UTF-8.
+    fn decode(len: u8, bytes: &[u8]) -> Option<Self> {
+        let text = str::from_utf8(bytes.get(..len as usize).filter(|_| len as usize <= MAX_COMMENT_LEN)?).ok()?;
+        Comment::new(text).ok()
+    }
+    
+    pub fn as_str(&self) -> &str {
+        str::from_utf8(&self.bytes[..self.len as usize]).expect("comments are checked to be UTF-8")
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for Comment {
+    fn default() -> Self {
+        Comment { len: 0, bytes: [0; MAX_COMMENT_LEN] }
+    }
+}
+
+impl fmt::Debug for Comment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// The fixed-size header written ahead of every payload. The payload is
+/// carried by every `stride`th pixel after the header, narrowed down to the
+/// pixels at least as textured as `threshold` when that's set, see
+/// [`crate::selection`], and to `region` when that's set. `protection` is
+/// set when the payload was encrypted with a passphrase, and `spread` when
+/// every payload bit is repeated over that many pseudo-randomly chosen
+/// carrier bytes instead, see [`crate::utils::spread`]. `offset` is the
+/// carrier byte the header itself starts at, and `next` where the header
+/// of the message appended after this one does, if any. `grayscale` is set
+/// when the carrier holds one byte per pixel rather than three.
+/// `interleave` is how many pixels the payload takes from one channel
+/// before moving to the next, see [`crate::selection::interleaved`], 1
+/// being plain buffer order.
+/// `comment` is a note left in the clear, while `sealed_comment` says one
+/// was put in front of the encrypted payload instead, see
+/// [`crate::Encoder::set_comment`]. `archive` is set when the payload is a
+/// directory packed by [`crate::archive::pack`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StegoHeader {
+    pub version: u8,
+    pub bits: [u8; 3],
+    pub stride: u16,
+    pub length: u32,
+    pub threshold: Option<u32>,
+    pub region: Option<Region>,
+    pub protection: Option<Protection>,
+    pub offset: u32,
+    pub next: Option<u32>,
+    pub spread: Option<u16>,
+    pub grayscale: bool,
+    pub interleave: u16,
+    pub comment: Comment,
+    pub sealed_comment: bool,
+    pub archive: bool,
+}
+
+impl StegoHeader {
+    pub fn new(bits: [u8; 3], length: u32) -> Self {
+        StegoHeader {
+            version: HEADER_VERSION,
+            bits,
+            stride: 1,
+            length,
+            threshold: None,
+            region: None,
+            protection: None,
+            offset: 0,
+            next: None,
+            spread: None,
+            grayscale: false,
+            interleave: 1,
+            comment: Comment::default(),
+            sealed_comment: false,
+            archive: false,
+        }
+    }
+    
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0] = self.version;
+        bytes[1..5].copy_from_slice(MAGIC);
+        bytes[5..8].copy_from_slice(&self.bits);
+        bytes[8..10].copy_from_slice(&self.stride.to_be_bytes());
+        bytes[10..14].copy_from_slice(&self.length.to_be_bytes());
+        if let Some(threshold) = self.threshold {
+            bytes[14] |= FLAG_ADAPTIVE;
+            bytes[15..19].copy_from_slice(&threshold.to_be_bytes());
+        }
+        if let Some(region) = &self.region {
+            bytes[14] |= FLAG_REGION;
+            let fields = [region.x, region.y, region.width, region.height, region.image_width];
+            for (chunk, field) in bytes[19..39].chunks_mut(4).zip(fields) {
+                chunk.copy_from_slice(&field.to_be_bytes());
+            }
+        }
+        if let Some(protection) = &self.protection {
+            bytes[14] |= FLAG_PROTECTED;
+            bytes[39..55].copy_from_slice(&protection.salt);
+            bytes[55..59].copy_from_slice(&protection.kdf.memory_kib.to_be_bytes());
+            bytes[59..63].copy_from_slice(&protection.kdf.iterations.to_be_bytes());
+            bytes[63..95].copy_from_slice(&protection.tag);
+        }
+        bytes[95..99].copy_from_slice(&self.offset.to_be_bytes());
+        bytes[99..103].copy_from_slice(&self.next.unwrap_or(0).to_be_bytes());
+        if let Some(spread) = self.spread {
+            bytes[14] |= FLAG_SPREAD;
+            bytes[103..105].copy_from_slice(&spread.to_be_bytes());
+        }
+        if self.grayscale {
+            bytes[14] |= FLAG_GRAYSCALE;
+        }
+        bytes[105..107].copy_from_slice(&self.interleave.to_be_bytes());
+        bytes[107] = self.comment.len;
+        bytes[108..108 + MAX_COMMENT_LEN].copy_from_slice(&self.comment.bytes);
+        if self.sealed_comment {
+            bytes[14] |= FLAG_SEALED_COMMENT;
+        }
+        if self.archive {
+            bytes[14] |= FLAG_ARCHIVE;
+        }
+        
+        bytes
+    }
+    
+    // The magic is checked before the version so an ordinary image reports
+    // NotAStegoImage rather than a bogus version number.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if (bytes.len() < HEADER_LEN) || (&bytes[1..5] != MAGIC) {
+            return Err(Error::NotAStegoImage);
+        }
+        if bytes[0] != HEADER_VERSION {
+            return Err(Error::UnsupportedStegoVersion(bytes[0]));
+        }
+        
+        let stride = u16::from_be_bytes([bytes[8], bytes[9]]);
+        let field = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        let region = ((bytes[14] & FLAG_REGION) != 0).then(|| Region {
+            x: field(19),
+            y: field(23),
+            width: field(27),
+            height: field(31),
+            image_width: field(35),
+        });
+        // The first header sits at offset 0, so 0 can stand for no next one.
+        // Each header has to point past itself or the chain could loop.
+        let (offset, next) = (field(95), Some(field(99)).filter(|&n| n != 0));
+        let spread = ((bytes[14] & FLAG_SPREAD) != 0).then(|| u16::from_be_bytes([bytes[103], bytes[104]]));
+        let interleave = u16::from_be_bytes([bytes[105], bytes[106]]);
+        let Some(comment) = Comment::decode(bytes[107], &bytes[108..108 + MAX_COMMENT_LEN]) else {
+            return Err(Error::NotAStegoImage);
+        };
+        let bits = [bytes[5], bytes[6], bytes[7]];
+        let invalid = ChannelByteMask::new(bits).is_err()
+            || (stride == 0)
+            || (interleave == 0)
+            || region.is_some_and(|r| r.image_width == 0)
+            || next.is_some_and(|n| n <= offset)
+            || (spread == Some(0));
+        if invalid {
+            return Err(Error::NotAStegoImage);
+        }
+        
+        Ok(StegoHeader {
+            version: bytes[0],
+            bits,
+            stride,
+            length: u32::from_be_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]),
+            threshold: ((bytes[14] & FLAG_ADAPTIVE) != 0)
+                .then(|| field(15)),
+            region,
+            protection: ((bytes[14] & FLAG_PROTECTED) != 0).then(|| Protection {
+                salt: bytes[39..55].try_into().unwrap(),
+                kdf: KdfParams { memory_kib: field(55), iterations: field(59) },
+                tag: bytes[63..95].try_into().unwrap(),
+            }),
+            offset,
+            next,
+            spread,
+            grayscale: (bytes[14] & FLAG_GRAYSCALE) != 0,
+            interleave,
+            comment,
+            sealed_comment: (bytes[14] & FLAG_SEALED_COMMENT) != 0,
+            archive: (bytes[14] & FLAG_ARCHIVE) != 0,
+        })
+    }
+    
+    pub fn embed(&self, carrier: &mut [u8]) {
+        let bytes = self.encode();
+        
+        for (i, p) in carrier.iter_mut().take(HEADER_LEN * 8).enumerate() {
+            let bit = (bytes[i / 8] >> (7 - i % 8)) & 1;
+            *p = (*p & !1) | bit;
+        }
+    }
+    
+    pub fn extract(carrier: &[u8]) -> Result<Self, Error> {
+        if carrier.len() < HEADER_CARRIER_LEN {
+            return Err(Error::NotAStegoImage);
+        }
+        
+        let mut bytes = [0; HEADER_LEN];
+        for (byte, bits) in bytes.iter_mut().zip(carrier.chunks(8)) {
+            *byte = bits.iter().fold(0, |acc, b| (acc << 1) | (b & 1));
+        }
+        
+        StegoHeader::decode(&bytes)
+    }
+    
+    /// Every header in the chain that starts at the front of `carrier`, one
+    /// per message, in the order they were written.
+    pub fn extract_all(carrier: &[u8]) -> Result<Vec<Self>, Error> {
+        let mut headers = vec![StegoHeader::extract(carrier)?];
+        while let Some(next) = headers[headers.len() - 1].next {
+            let header = StegoHeader::extract(carrier.get(next as usize..).unwrap_or_default())?;
+            if header.offset != next {
+                return Err(Error::NotAStegoImage);
+            }
+            headers.push(header);
+        }
+        
+        Ok(headers)
+    }
+}
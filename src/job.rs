@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use stegnoapp::cover::CoverInfo;
+use stegnoapp::encoder::EncodeOutcome;
+use stegnoapp::header::StegoHeader;
+use stegnoapp::utils::Progress;
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Error};
+
+pub enum JobEvent {
+    Progress(&'static str),
+    // Payload bytes done and in all, from the library's progress callback.
+    Advanced(u64, u64),
+    Encoded(Result<EncodeOutcome, Error>),
+    // What an encode would have written, from a dry run.
+    Planned(Result<EncodeOutcome, Error>),
+    // The payload, held in memory until the user saves it.
+    Decoded(Result<(ChannelByteMask, Vec<u8>), Error>),
+    // What opening a picked file found, and what it was picked for.
+    Probed(Pick, PathBuf, Result<Probe, Error>),
+    Lost,
+}
+
+// What a file was picked as: a cover, a stego image, or, given on the
+// command line, whichever it turns out to be, along with its format.
+pub enum Pick {
+    Cover,
+    Stego,
+    Either(CoverInfo),
+}
+
+// What's in a picked file beyond its format. Finding out decodes the
+// whole cover, so it's done on a worker rather than the UI thread.
+pub struct Probe {
+    pub carrier_len: usize,
+    // Whether the cover holds a stego header at all.
+    pub used: bool,
+    // The first message's header, once its payload checks out as well.
+    pub header: Result<StegoHeader, Error>,
+}
+
+impl Probe {
+    // The cover is only checked for cancellation once it's decoded.
+    pub fn open(path: &Path, cancel: &AtomicBool) -> Result<Self, Error> {
+        let cover = Cover::open(path)?;
+        if cancel.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        
+        let carrier_len = cover.bytes().len();
+        let used = StegoHeader::extract(cover.bytes()).is_ok();
+        let header = Decoder::from_cover(cover).map(|d| d.headers()[0]);
+        Ok(Probe { carrier_len, used, header })
+    }
+}
+
+// An encode, decode or probe running on a worker thread. The worker reports over
+// the channel and watches the shared flag, so the UI stays responsive and
+// can ask it to stop.
+pub struct Job {
+    cancel: Arc<AtomicBool>,
+    events: Receiver<JobEvent>,
+    started: Instant,
+}
+
+impl Job {
+    pub fn spawn<F>(work: F) -> Self
+    where
+        F: FnOnce(Arc<AtomicBool>, Sender<JobEvent>) + Send + 'static,
+    {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, events) = mpsc::channel();
+        let flag = Arc::clone(&cancel);
+        thread::spawn(move || work(flag, sender));
+        
+        Job { cancel, events, started: Instant::now() }
+    }
+    
+    // A progress callback for the worker to hand the encoder or decoder,
+    // reporting back as Advanced events.
+    pub fn progress(events: &Sender<JobEvent>) -> Progress {
+        let events = events.clone();
+        Arc::new(move |done, total| {
+            let _ = events.send(JobEvent::Advanced(done, total));
+        })
+    }
+    
+    // How long the job has been running, for the status line once it's done.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+    
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+    
+    // A worker that hung up without a result panicked, which is reported
+    // as Lost rather than leaving the UI waiting forever.
+    pub fn poll(&self) -> Option<JobEvent> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(JobEvent::Lost),
+        }
+    }
+}
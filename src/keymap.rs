@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use ratatui::crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    History,
+    Back,
+    Run,
+    SelectImage,
+    SelectSecret,
+    EditOutput,
+    BrowseOutput,
+    ToggleVerify,
+    Preview,
+    RevealOutput,
+    ToggleReveal,
+    EditEncodeName,
+    EditDecodeName,
+    EditComment,
+    ToggleEncrypt,
+    MoreBits,
+    FewerBits,
+    FitBits,
+    CycleTheme,
+    CycleKdfMemory,
+    CycleKdfPasses,
+    ToggleFilter,
+}
+
+// Available on every screen, on top of the screen's own actions.
+const GLOBAL: [Action; 2] = [Action::Quit, Action::History];
+
+pub const ENCODE: [Action; 14] = [
+    Action::SelectImage,
+    Action::SelectSecret,
+    Action::EditOutput,
+    Action::BrowseOutput,
+    Action::ToggleVerify,
+    Action::Preview,
+    Action::EditComment,
+    Action::ToggleEncrypt,
+    Action::MoreBits,
+    Action::FewerBits,
+    Action::FitBits,
+    Action::RevealOutput,
+    Action::Run,
+    Action::Back,
+];
+
+pub const DECODE: [Action; 7] = [
+    Action::SelectImage,
+    Action::EditOutput,
+    Action::BrowseOutput,
+    Action::Preview,
+    Action::RevealOutput,
+    Action::Run,
+    Action::Back,
+];
+
+pub const EXPLORER: [Action; 1] = [Action::ToggleFilter];
+
+pub const SETTINGS: [Action; 9] = [
+    Action::MoreBits,
+    Action::FewerBits,
+    Action::CycleTheme,
+    Action::CycleKdfMemory,
+    Action::CycleKdfPasses,
+    Action::ToggleReveal,
+    Action::EditEncodeName,
+    Action::EditDecodeName,
+    Action::Back,
+];
+
+fn default_key(action: Action) -> KeyCode {
+    match action {
+        Action::Quit => KeyCode::Char('q'),
+        Action::History => KeyCode::Char('H'),
+        Action::Back => KeyCode::Backspace,
+        Action::Run => KeyCode::Enter,
+        Action::SelectImage => KeyCode::Char('i'),
+        Action::SelectSecret => KeyCode::Char('s'),
+        Action::EditOutput => KeyCode::Char('o'),
+        Action::BrowseOutput => KeyCode::Char('O'),
+        Action::ToggleVerify => KeyCode::Char('v'),
+        Action::Preview => KeyCode::Char('p'),
+        Action::RevealOutput => KeyCode::Char('f'),
+        Action::ToggleReveal => KeyCode::Char('r'),
+        Action::EditEncodeName => KeyCode::Char('e'),
+        Action::EditDecodeName => KeyCode::Char('d'),
+        Action::EditComment => KeyCode::Char('c'),
+        Action::ToggleEncrypt => KeyCode::Char('k'),
+        Action::MoreBits => KeyCode::Up,
+        Action::FewerBits => KeyCode::Down,
+        Action::FitBits => KeyCode::Char('a'),
+        Action::CycleTheme => KeyCode::Char('t'),
+        Action::CycleKdfMemory => KeyCode::Char('m'),
+        Action::CycleKdfPasses => KeyCode::Char('p'),
+        Action::ToggleFilter => KeyCode::Char('f'),
+    }
+}
+
+// Single characters stand for themselves, other keys go by name.
+const NAMED_KEYS: [(&str, KeyCode); 12] = [
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Enter", KeyCode::Enter),
+    ("Backspace", KeyCode::Backspace),
+    ("Tab", KeyCode::Tab),
+    ("Delete", KeyCode::Delete),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+];
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(KeyCode::Char(c)),
+        _ => NAMED_KEYS
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, code)| code),
+    }
+}
+
+fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(c) => c.to_string(),
+        _ => NAMED_KEYS
+            .iter()
+            .find(|&&(_, c)| c == code)
+            .map_or_else(|| format!("{:?}", code), |(n, _)| n.to_string()),
+    }
+}
+
+// Bindings from the config file by action. Actions left out, or bound to a
+// key name that doesn't parse, keep their default key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keymap(BTreeMap<Action, String>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let actions = GLOBAL.iter().chain(&ENCODE).chain(&DECODE).chain(&EXPLORER).chain(&SETTINGS);
+        Keymap(actions.map(|&a| (a, key_name(default_key(a)))).collect())
+    }
+}
+
+impl Keymap {
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.0
+            .get(&action)
+            .and_then(|name| parse_key(name))
+            .unwrap_or(default_key(action))
+    }
+    
+    // How the key is shown in hints, e.g. 'i' or Enter.
+    pub fn label(&self, action: Action) -> String {
+        match self.key(action) {
+            KeyCode::Char(c) => format!("'{}'", c),
+            code => key_name(code),
+        }
+    }
+    
+    // The action `code` triggers among the global ones and the screen's.
+    pub fn action(&self, screen: &[Action], code: KeyCode) -> Option<Action> {
+        GLOBAL.iter().chain(screen).copied().find(|&a| self.key(a) == code)
+    }
+    
+    // One line per binding that won't work: a key name that doesn't parse,
+    // or a key shared by two actions on the same screen.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self.0
+            .iter()
+            .filter(|(_, name)| parse_key(name).is_none())
+            .map(|(action, name)| format!("Unknown key \"{}\" for {:?}, using the default", name, action))
+            .collect();
+        
+        for (screen, actions) in [
+            ("encode", &ENCODE[..]),
+            ("decode", &DECODE[..]),
+            ("file explorer", &EXPLORER[..]),
+            ("settings", &SETTINGS[..]),
+        ] {
+            let actions: Vec<Action> = GLOBAL.iter().chain(actions).copied().collect();
+            for (i, &a) in actions.iter().enumerate() {
+                for &b in &actions[i + 1..] {
+                    if self.key(a) == self.key(b) {
+                        problems.push(format!("{:?} and {:?} share {} on the {} screen", a, b, self.label(a), screen));
+                    }
+                }
+            }
+        }
+        
+        problems
+    }
+}
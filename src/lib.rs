@@ -0,0 +1,49 @@
+//! LSB steganography for images and 16-bit PCM WAV audio.
+//!
+//! [`Encoder`] hides a secret behind a small [`StegoHeader`] recording the
+//! format version, per-channel bit depth, pixel selection and payload
+//! length, so [`Decoder`] can read it back without being told how it was
+//! written.
+//!
+//! Covers and outputs can be any lossless format the `image` crate reads and
+//! writes. WebP needs its `webp` feature, which is on by default, and only
+//! lossless WebP is written, see [`cover::is_lossy_webp`]. The `simd` feature
+//! speeds up embedding and extracting at uniform depths of 1, 2, 4 or 8
+//! bits, see [`simd`].
+//!
+//! ```no_run
+//! use std::path::PathBuf;
+//! use stegnoapp::{ChannelByteMask, Decoder, Encoder};
+//!
+//! let mask = ChannelByteMask::uniform(2)?;
+//! let mut encoder = Encoder::from_bytes(PathBuf::from("cover.png"), b"secret".to_vec(), mask)?;
+//! encoder.save(PathBuf::from("stego.png"))?;
+//!
+//! let secret = Decoder::new(PathBuf::from("stego.png"))?.read_to_vec()?;
+//! assert_eq!(secret, b"secret");
+//! # Ok::<(), stegnoapp::Error>(())
+//! ```
+
+pub mod analysis;
+pub mod animated;
+pub mod archive;
+pub mod audio;
+pub mod cover;
+pub mod crypto;
+pub mod decoder;
+pub mod encoder;
+pub mod errors;
+pub mod header;
+pub mod qr;
+pub mod selection;
+pub mod shard;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod utils;
+
+pub use cover::Cover;
+pub use decoder::Decoder;
+pub use encoder::{Encoder, Padding};
+pub use errors::Error;
+pub use header::StegoHeader;
+pub use utils::{ByteMask, ChannelByteMask};
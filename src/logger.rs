@@ -0,0 +1,78 @@
+use std::io::{self, Write};
+use std::mem;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use env_logger::{Builder, Target, WriteStyle};
+use log::LevelFilter;
+
+// Where env_logger writes records from this crate and the library: stderr
+// for commands. The TUI can't write there without tearing the screen, so
+// once it calls capture() they're queued for it to drain into the status
+// history.
+struct Sink {
+    capture: AtomicBool,
+    quiet: AtomicBool,
+    queue: Mutex<Vec<String>>,
+}
+
+static SINK: Sink = Sink {
+    capture: AtomicBool::new(false),
+    quiet: AtomicBool::new(false),
+    queue: Mutex::new(Vec::new()),
+};
+
+// env_logger writes each record whole, so every write is one or more
+// complete lines.
+struct Pipe;
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if SINK.capture.load(Ordering::Relaxed) {
+            let text = String::from_utf8_lossy(buf);
+            let mut queue = SINK.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue.extend(text.lines().map(str::to_string));
+            Ok(buf.len())
+        } else {
+            io::stderr().write(buf)
+        }
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+// Warnings only by default, one -v adds debug output and two trace.
+// Quiet leaves errors alone. RUST_LOG, when set, is applied on top, e.g.
+// RUST_LOG=stegnoapp::decoder=trace for just the decoder.
+pub fn init(verbosity: u8, quiet: bool) {
+    SINK.quiet.store(quiet, Ordering::Relaxed);
+    let level = match verbosity {
+        _ if quiet => LevelFilter::Error,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    
+    let _ = Builder::new()
+        .filter_level(LevelFilter::Off)
+        .filter_module("stegnoapp", level)
+        .parse_env("RUST_LOG")
+        .format(|buf, record| writeln!(buf, "{} {}: {}", record.level(), record.target(), record.args()))
+        .write_style(WriteStyle::Never)
+        .target(Target::Pipe(Box::new(Pipe)))
+        .try_init();
+}
+
+pub fn capture() {
+    SINK.capture.store(true, Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+    SINK.quiet.load(Ordering::Relaxed)
+}
+
+pub fn drain() -> Vec<String> {
+    mem::take(&mut *SINK.queue.lock().unwrap_or_else(|e| e.into_inner()))
+}
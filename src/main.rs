@@ -1,57 +1,341 @@
-mod errors;
-mod utils;
-mod encoder;
-mod decoder;
+mod config;
+mod job;
+mod keymap;
+mod logger;
+mod passphrase;
+mod preview;
+mod progress;
+mod reveal;
+mod status;
+mod theme;
 
-use std::io::{self, stdout};
+use std::io::{self, IsTerminal, Write, stdout};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
-use ratatui_explorer::FileExplorer;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use ratatui_explorer::{File, FileExplorer};
 use structopt::StructOpt;
 
 use ratatui::Terminal;
-use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::event::{self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::crossterm::execute;
 use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode};
-use ratatui::layout::{Constraint, Layout};
+use ratatui::layout::{Constraint, Layout, Position, Rect};
 use ratatui::prelude::CrosstermBackend;
 use ratatui::style::Style;
-use ratatui::widgets::{Block, Borders, Paragraph, Tabs};
-use tui_input::Input;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Tabs, Wrap};
+use tui_input::{Input, InputRequest};
 use tui_input::backend::crossterm::EventHandler;
 
-use crate::decoder::Decoder;
-use crate::encoder::Encoder;
-use crate::errors::Error;
-use crate::utils::ByteMask;
+use stegnoapp::cover::CoverInfo;
+use stegnoapp::crypto::{KdfParams, MAX_KDF};
+use stegnoapp::decoder::{DecodeOutcome, Recovery};
+use stegnoapp::encoder::EncodeOutcome;
+use stegnoapp::header::{Comment, MAX_COMMENT_LEN};
+use stegnoapp::shard::{self, SHARD_HEADER_LEN, ShardHeader};
+use stegnoapp::utils::{self, Progress};
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, Padding, analysis, cover, decoder, encoder, qr};
+
+use crate::config::AppConfig;
+use crate::job::{Job, JobEvent, Pick, Probe};
+use crate::keymap::Action;
+use crate::passphrase::Passphrase;
+use crate::status::StatusLog;
+use crate::theme::Theme;
+
+#[derive(StructOpt, Default)]
+struct EncodeOptions {
+    /// Re-read the written file and check the secret comes back intact
+    #[structopt(long)]
+    verify: bool,
+    /// Print how many bytes changed and the PSNR between cover and output
+    #[structopt(long)]
+    report: bool,
+    /// What to write into the LSBs after the secret: none, zero or random
+    #[structopt(long, default_value = "random")]
+    padding: Padding,
+    /// Embed only into every Nth pixel, spreading the secret thinly
+    #[structopt(long)]
+    stride: Option<usize>,
+    /// Fill N pixels' red channels, then their green and blue, before the next N
+    #[structopt(long)]
+    interleave: Option<usize>,
+    /// Write the format the output's extension names instead of the cover's
+    #[structopt(long)]
+    convert: bool,
+    /// Convert a palette (indexed) cover to true-color instead of refusing it
+    #[structopt(long)]
+    truecolor: bool,
+    /// Embed only inside the rectangle at X,Y of size W,H (in pixels)
+    #[structopt(long, use_delimiter = true, number_of_values = 4, value_names = &["X", "Y", "W", "H"])]
+    region: Vec<u32>,
+    /// Embed only into the most textured pixels the secret needs
+    #[structopt(long)]
+    adaptive: bool,
+    /// Encrypt the secret with AES-256-CTR under a key derived from this
+    /// passphrase, and tag it with an HMAC so tampering is detected on decode
+    #[structopt(long, parse(from_str = passphrase::from_arg))]
+    passphrase: Option<Passphrase>,
+    /// Read the passphrase from this file, or set STEGNOAPP_PASSPHRASE, to keep it out of shell history
+    #[structopt(long, parse(from_os_str), conflicts_with = "passphrase")]
+    passphrase_file: Option<PathBuf>,
+    /// Prompt for the passphrase when no other source gives one
+    #[structopt(long)]
+    ask_passphrase: bool,
+    /// Add the secret after the messages already in the cover, keeping them
+    #[structopt(long)]
+    append: bool,
+    /// Repeat every bit over N pixels picked by the passphrase, surviving some noise
+    #[structopt(long)]
+    spread: Option<usize>,
+    /// Leave a short note `info` prints without the passphrase
+    #[structopt(long)]
+    comment: Option<String>,
+    /// Encrypt the comment with the payload instead, needs --passphrase
+    #[structopt(long)]
+    seal_comment: bool,
+    /// Check and embed everything, then print what would be written instead of writing it
+    #[structopt(long)]
+    dry_run: bool,
+    /// Encode over hidden data already in the cover without asking, losing it
+    #[structopt(long, conflicts_with = "append")]
+    force: bool,
+    #[structopt(skip)]
+    kdf: KdfParams,
+    #[structopt(skip)]
+    buffer_len: Option<usize>,
+    #[structopt(skip)]
+    cancel: Option<Arc<AtomicBool>>,
+    #[structopt(skip)]
+    progress: Option<Progress>,
+}
 
 #[derive(StructOpt)]
 enum Command {
     Encode {
         #[structopt(parse(from_os_str))]
         image: PathBuf,
+        /// File to hide, or a directory to pack into a tar archive and hide whole
         #[structopt(parse(from_os_str))]
         secret: PathBuf,
         #[structopt(parse(from_os_str))]
         output: PathBuf,
+        #[structopt(flatten)]
+        options: EncodeOptions,
     },
     Decode {
         #[structopt(parse(from_os_str))]
         image: PathBuf,
+        /// Output file, the directory to unpack an archived one into, or `-` to write the extracted secret to stdout
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// Passphrase the secret was encoded with, asked for when needed and not given
+        #[structopt(long, parse(from_str = passphrase::from_arg))]
+        passphrase: Option<Passphrase>,
+        /// Read the passphrase from this file, or set STEGNOAPP_PASSPHRASE
+        #[structopt(long, parse(from_os_str), conflicts_with = "passphrase")]
+        passphrase_file: Option<PathBuf>,
+        /// Which message to extract when the image holds several, as `info` numbers them
+        #[structopt(long, default_value = "0")]
+        message: usize,
+        /// Extract every message the passphrase opens into the output directory
+        #[structopt(long)]
+        all: bool,
+        /// Write whatever part of the secret a cropped or damaged image still holds
+        #[structopt(long, conflicts_with = "all")]
+        lenient: bool,
+    },
+    /// Encode every file in `secret_dir` into the images of `cover_dir`.
+    /// Only the top level of both directories is processed.
+    BatchEncode {
+        #[structopt(parse(from_os_str))]
+        cover_dir: PathBuf,
+        #[structopt(parse(from_os_str))]
+        secret_dir: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output_dir: PathBuf,
+        /// Encode over hidden data already in the covers without asking, losing it
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Split the secret across as many of the covers as it needs
+    EncodeMulti {
+        #[structopt(parse(from_os_str))]
+        secret: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output_dir: PathBuf,
+        #[structopt(parse(from_os_str), required = true)]
+        covers: Vec<PathBuf>,
+        /// Encode over hidden data already in the covers without asking, losing it
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Hide a secret in the low bits of a 16-bit PCM WAV file
+    EncodeAudio {
+        #[structopt(parse(from_os_str))]
+        audio: PathBuf,
+        #[structopt(parse(from_os_str))]
+        secret: PathBuf,
         #[structopt(parse(from_os_str))]
         output: PathBuf,
+        #[structopt(flatten)]
+        options: EncodeOptions,
+    },
+    /// Extract a secret hidden by `encode-audio`
+    DecodeAudio {
+        #[structopt(parse(from_os_str))]
+        audio: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// Passphrase the secret was encoded with, asked for when needed and not given
+        #[structopt(long, parse(from_str = passphrase::from_arg))]
+        passphrase: Option<Passphrase>,
+        /// Read the passphrase from this file, or set STEGNOAPP_PASSPHRASE
+        #[structopt(long, parse(from_os_str), conflicts_with = "passphrase")]
+        passphrase_file: Option<PathBuf>,
+    },
+    /// Reassemble a secret from the shards written by `encode-multi`
+    DecodeMulti {
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        #[structopt(parse(from_os_str), required = true)]
+        images: Vec<PathBuf>,
+    },
+    /// Hide a small secret as a QR code, which survives some corruption
+    EncodeQr {
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+        #[structopt(parse(from_os_str))]
+        secret: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// Encode over hidden data already in the cover without asking, losing it
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Extract a secret hidden by `encode-qr`
+    DecodeQr {
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+        /// Output file, or `-` to write the secret to stdout
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Print how many secret bytes the cover can hold at the given bit depth
+    Capacity {
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+        /// Print a size like "1.2 MiB" instead of a plain byte count
+        #[structopt(long)]
+        human: bool,
+        /// Count only every Nth pixel, as encode --stride does
+        #[structopt(long, default_value = "1")]
+        stride: usize,
+    },
+    /// Summarize the embedded header without extracting the secret
+    Info {
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+    },
+    /// Score how likely the cover is to hold LSB-embedded data (chi-square)
+    Analyze {
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+    },
+    /// Write an image showing which channels differ between cover and stego
+    Diff {
+        #[structopt(parse(from_os_str))]
+        cover: PathBuf,
+        #[structopt(parse(from_os_str))]
+        stego: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Scramble the low bits of every pixel, destroying any hidden payload
+    Sanitize {
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// Clear the low bits instead of filling them with noise
+        #[structopt(long)]
+        zero: bool,
     }
 }
 
+const STDOUT_PATH: &str = "-";
+
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Key handling slower than a frame is logged in debug builds, the real work
+// belongs on a job.
+#[cfg(debug_assertions)]
+const SLOW_KEY: Duration = Duration::from_millis(16);
+
+// The main menu tabs, in order, and the screen each one opens.
+const MENU: [(&str, Screen); 5] = [
+    ("Encode", Screen::Encode),
+    ("Decode", Screen::Decode),
+    ("Settings", Screen::Settings),
+    ("Help", Screen::Help),
+    ("Quit", Screen::Quit),
+];
+
+// The Settings choices, up to the most a decode accepts.
+const KDF_MEMORY_MIB: [u32; 5] = [19, 46, 64, 128, MAX_KDF.memory_kib / 1024];
+
+// Paths in status messages are cut to this many characters, keeping the
+// file name, so a deep one doesn't push the rest of the message off screen.
+const STATUS_PATH_LEN: usize = 60;
+
+const COVER_EXTENSIONS: [&str; 10] = ["png", "bmp", "tif", "tiff", "gif", "webp", "tga", "pnm", "ppm", "wav"];
+
+// What commands report on stderr besides errors, which --quiet leaves out.
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if !logger::quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
 #[derive(StructOpt)]
 #[structopt(
     name = "stegnoapp",
     about = "Picture secret stegnography encoder/decoder"
 )]
 struct Opt {
+    /// LSB bits per channel, either one depth for all or e.g. "R:1,G:3,B:2".
+    /// Decoding reads them from the header and warns when they differ
     #[structopt(short = "b", long = "bits", default_value = "2")]
-    bits: u8,
+    bits: ChannelByteMask,
+    /// Channels to embed in, e.g. rgb, rb or g. Decoding reads them from the
+    /// header, a decoder guessing them wrong would only read garbage
+    #[structopt(long, default_value = "rgb")]
+    channels: String,
+    /// Log each step to stderr, or the status history in the TUI; -vv for more.
+    /// RUST_LOG directives, e.g. RUST_LOG=stegnoapp::decoder=trace, apply on top
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    verbose: u8,
+    /// Print only errors, leaving the exit code to tell how a command went.
+    /// A payload decoded to "-" still goes to stdout, as do the reports of
+    /// capacity, info and analyze. Needs a subcommand
+    #[structopt(short = "q", long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// KiB of payload to embed or extract per batch. Larger batches are
+    /// fewer and can run on several threads, smaller ones use less memory
+    /// and show progress more often
+    #[structopt(long = "buffer-kib", default_value = "256")]
+    buffer_kib: usize,
+    /// Without a subcommand, open the TUI with this file picked, for
+    /// decoding if it holds a stego header and as the cover otherwise
+    #[structopt(parse(from_os_str))]
+    path: Option<PathBuf>,
     #[structopt(subcommand)]
-    cmd: Command,
+    cmd: Option<Command>,
 }
 
 
@@ -64,6 +348,7 @@ enum Screen {
     Help,
     Quit,
     FileExplorer,
+    History,
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -75,20 +360,113 @@ enum Purpose {
     DecodeOutput
 }
 
+// The masked entry shown before an encrypting encode or a protected decode
+// on `screen`, see handle_passphrase_events. It keeps its own buffer rather
+// than a tui_input one, which can't be zeroed and is copied to be read.
+struct PassphrasePrompt {
+    passphrase: Passphrase,
+    // In characters, like tui_input's.
+    cursor: usize,
+    screen: Screen,
+    revealed: bool,
+}
+
+impl PassphrasePrompt {
+    fn new(screen: Screen) -> Self {
+        PassphrasePrompt { passphrase: Passphrase::default(), cursor: 0, screen, revealed: false }
+    }
+    
+    fn byte_at(&self, cursor: usize) -> usize {
+        self.passphrase.char_indices().nth(cursor).map_or(self.passphrase.len(), |(i, _)| i)
+    }
+    
+    fn insert(&mut self, text: &str) {
+        let at = self.byte_at(self.cursor);
+        passphrase::insert(&mut self.passphrase, at, text);
+        self.cursor += text.chars().count();
+    }
+    
+    // Removing shifts the rest down within the buffer, the bytes left past
+    // the end are zeroed with the others when it's dropped.
+    fn remove(&mut self, cursor: usize) {
+        if cursor < self.passphrase.chars().count() {
+            let at = self.byte_at(cursor);
+            self.passphrase.remove(at);
+        }
+    }
+    
+    fn handle_key(&mut self, key: KeyEvent) {
+        let len = self.passphrase.chars().count();
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => self.insert(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.remove(self.cursor);
+            }
+            KeyCode::Delete => self.remove(self.cursor),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(len),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = len,
+            _ => {}
+        }
+    }
+}
+
 struct App {
     curr_screen: Screen,
     prev_screen: Option<Screen>,
-    encode_image_input: Option<PathBuf>,
-    encode_secret_input: Option<PathBuf>,
-    encode_output_input: Option<PathBuf>,
-    encode_bits: u8,
-    decode_image_input: Option<PathBuf>,
+    // Paths a job is handed are shared with it rather than copied on the
+    // UI thread.
+    encode_image_input: Option<Arc<Path>>,
+    encode_secret_input: Option<Arc<Path>>,
+    encode_output_input: Option<Arc<Path>>,
+    encode_image_len: Option<usize>,
+    encode_image_info: Option<CoverInfo>,
+    encode_secret_len: Option<u64>,
+    encode_verify: bool,
+    // The cover already holds a stego header, see check_cover_unused.
+    encode_cover_used: bool,
+    decode_image_input: Option<Arc<Path>>,
     decode_output_input: Option<PathBuf>,
-    decode_bits: u8,
-    status: String,
+    decode_bits: Option<ChannelByteMask>,
+    // The last decoded payload, shown as a preview until it's saved.
+    decode_payload: Option<Vec<u8>>,
+    // Whether accepting the output path being typed saves the payload.
+    decode_save_pending: bool,
+    output_edit: Option<Input>,
+    // The path an output edit started from. Its text is lossy when the
+    // path isn't UTF-8, so accepting it unchanged keeps the real one.
+    output_edit_from: Option<PathBuf>,
+    bits: u8,
+    status: StatusLog,
+    history_scroll: usize,
+    error_modal: Option<String>,
+    // What a dry run found, shown until a key is pressed.
+    plan_modal: Option<String>,
+    // The cover already holds hidden data, and encoding waits for a yes.
+    reuse_modal: bool,
+    // An existing file picked as the output, set once overwriting it is
+    // confirmed.
+    overwrite_modal: Option<PathBuf>,
     menu_index: usize,
     file_explorer: Option<FileExplorer>,
     explorer_purpose: Option<Purpose>,
+    // The output whose name template the settings screen is editing.
+    name_edit: Option<Purpose>,
+    comment_edit: bool,
+    encode_comment: String,
+    encode_encrypt: bool,
+    decode_protected: bool,
+    // The payload is a packed directory, saved by unpacking it.
+    decode_archive: bool,
+    passphrase_prompt: Option<PassphrasePrompt>,
+    explorer_filter: bool,
+    job: Option<Job>,
+    job_progress: Option<(u64, u64)>,
+    // What the last successful encode or decode wrote, to show on request.
+    last_output: Option<PathBuf>,
+    config: AppConfig,
 }
 
 impl Default for App {
@@ -98,51 +476,420 @@ impl Default for App {
             prev_screen: None,
             encode_image_input: None,
             encode_secret_input: None,
-            encode_output_input: Some(PathBuf::from("stego.png")),
-            encode_bits: 2,
+            encode_output_input: Some(Path::new("stego.png").into()),
+            encode_image_len: None,
+            encode_image_info: None,
+            encode_secret_len: None,
+            encode_verify: false,
+            encode_cover_used: false,
             decode_image_input: None,
-            decode_output_input: Some(PathBuf::from("extracted.txt")),
-            decode_bits: 2,
-            status: "Ready | Use Tab/Arrows to navigate, Enter to select".to_string(),
+            decode_output_input: None,
+            decode_bits: None,
+            decode_payload: None,
+            decode_save_pending: false,
+            output_edit: None,
+            output_edit_from: None,
+            name_edit: None,
+            comment_edit: false,
+            encode_comment: String::new(),
+            encode_encrypt: false,
+            decode_protected: false,
+            decode_archive: false,
+            passphrase_prompt: None,
+            bits: 2,
+            status: StatusLog::new("Ready | Use Tab/Arrows to navigate, Enter to select, 'H' for history"),
+            history_scroll: 0,
+            error_modal: None,
+            plan_modal: None,
+            reuse_modal: false,
+            overwrite_modal: None,
             menu_index: 0,
             file_explorer: None,
             explorer_purpose: None,
+            explorer_filter: false,
+            job: None,
+            job_progress: None,
+            last_output: None,
+            config: AppConfig::default(),
+        }
+    }
+}
+
+impl App {
+    fn new(config: AppConfig) -> Self {
+        let mut app = App::default();
+        
+        if let Some(dir) = &config.output_dir {
+            app.encode_output_input = Some(dir.join(config.encode_output_name(None)).into());
+            app.decode_output_input = Some(dir.join(config.decode_output_name(None)));
+        }
+        app.bits = config.bits;
+        for problem in config.keys.problems() {
+            app.status.set(format!("Keymap: {}", problem));
+        }
+        app.config = config;
+        
+        app
+    }
+    
+    // The bit depths that leave something of the encode screen's cover: an
+    // 8-bit sample written with 8 bits is all payload. 16-bit images and
+    // audio carry the payload in the low byte of each sample and keep the
+    // high one whatever the depth, as does any depth before a cover is
+    // picked.
+    fn bits_range(&self) -> RangeInclusive<u8> {
+        match &self.encode_image_info {
+            Some(CoverInfo::Image { color, .. }) if color.bytes_per_pixel() == color.channel_count() => 1..=7,
+            _ => 1..=8,
+        }
+    }
+    
+    // Steps the current screen's bit depth by `delta`, stopping at the ends
+    // of the valid range rather than wrapping around to the other one. The
+    // default in the settings isn't tied to a cover and only gets clamped
+    // once one is picked.
+    fn adjust_bits(&mut self, delta: i8) {
+        let settings = self.curr_screen == Screen::Settings;
+        let (current, range) = if settings {
+            (self.config.bits, 1..=8)
+        } else {
+            (self.bits, self.bits_range())
+        };
+        
+        let wanted = current as i16 + delta as i16;
+        let bits = wanted.clamp(*range.start() as i16, *range.end() as i16) as u8;
+        if settings {
+            self.config.bits = bits;
+        } else {
+            if wanted > *range.end() as i16 {
+                self.status.set(format!("At most {} bits for this cover, more would leave nothing of it", range.end()));
+            }
+            self.bits = bits;
+        }
+    }
+    
+    // Starts on the screen a file given on the command line belongs to. A
+    // stego image is set up for decoding and any other usable cover for
+    // encoding, anything else leaves the main menu up and says why.
+    fn open_path(&mut self, path: PathBuf) {
+        let info = match CoverInfo::probe(&path) {
+            Ok(info) => info,
+            Err(e) => {
+                self.status.set(format!("{} is not a usable image or audio file: {}", shown(&path), e));
+                return;
+            }
+        };
+        
+        spawn_probe(self, Pick::Either(info), path);
+    }
+    
+    // The encode mask at `bits`, on the channels the settings pick.
+    fn mask_at(&self, bits: u8) -> Result<ChannelByteMask, Error> {
+        ChannelByteMask::uniform(bits)?.with_channels(&self.config.channels)
+    }
+    
+    // Picks the fewest bits the secret fits at, which changes the cover the
+    // least, within the range the cover allows.
+    fn fit_bits(&mut self) {
+        let (Some(image_len), Some(secret_len)) = (self.encode_image_len, self.encode_secret_len) else {
+            self.status.set("Select a cover image and secret first");
+            return;
+        };
+        
+        // Skipped channels only ever ask for more bits than all three would.
+        let max_bits = *self.bits_range().end();
+        let fitting = encoder::fitting_bits(secret_len, image_len, max_bits).and_then(|fewest| {
+            (fewest..=max_bits)
+                .find(|&bits| self.mask_at(bits).is_ok_and(|mask| encoder::fits(secret_len, image_len, mask, 1)))
+                .ok_or(Error::SecretTooLarge)
+        });
+        match fitting {
+            Ok(bits) => {
+                self.bits = bits;
+                self.status.set(format!("Fits at {} bits, the fewest that hold the secret", bits));
+            }
+            Err(e) => self.status.set(format!("Error: {}", e)),
         }
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // let opt = Opt::from_args();
-    
-    // let mask = ByteMask::new(opt.bits)?;
-    
-    // match opt.cmd {
-    //     Command::Encode { 
-    //         image, 
-    //         secret, 
-    //         output 
-    //     } => encode(image, secret, output, mask)?,
-    //     Command::Decode { 
-    //         image, 
-    //         output 
-    //     } => decode(image, output, mask)?,
-    // }
-    // 
+    let matches = Opt::clap().get_matches();
+    let opt = Opt::from_clap(&matches);
+    logger::init(opt.verbose, opt.quiet);
+    
+    if let Some(cmd) = opt.cmd {
+        let mask = opt.bits.with_channels(&opt.channels)?;
+        let depth_given = (matches.occurrences_of("bits") > 0) || (matches.occurrences_of("channels") > 0);
+        let buffer_len = opt.buffer_kib.saturating_mul(1024);
+        
+        match cmd {
+            Command::Encode { 
+                image, 
+                secret, 
+                output,
+                options
+            } => {
+                if let Ok(CoverInfo::Image { color, .. }) = CoverInfo::probe(&image) && color.has_alpha() {
+                    note!("warning: the cover's alpha channel is not carried over to the output");
+                }
+                check_cover_unused(&image, &options)?;
+                let passphrase = encode_passphrase(&options)?;
+                let dry_run = options.dry_run;
+                let outcome = progress::with_bar("Encoding", |progress| {
+                    let kdf = AppConfig::load().kdf_params();
+                    let options = EncodeOptions { passphrase, kdf, buffer_len: Some(buffer_len), progress, ..options };
+                    encode(image, secret, output, mask, &options)
+                })?;
+                if dry_run {
+                    note!("Dry run, nothing written: {}", outcome);
+                } else {
+                    note!("Secret encoded: {}", outcome);
+                }
+            }
+            Command::Decode { 
+                image, 
+                output,
+                passphrase,
+                passphrase_file,
+                message,
+                all,
+                lenient
+            } => {
+                let passphrase = passphrase::resolve(passphrase, passphrase_file.as_deref())?;
+                let passphrase = passphrase.as_deref().map(String::as_str);
+                if depth_given {
+                    warn_depth_mismatch(&image, message, mask);
+                }
+                if lenient {
+                    let (recovery, output) = recover(image, output, passphrase, message)?;
+                    let verified = match recovery.verified {
+                        Some(true) => "verified",
+                        Some(false) => "failed its integrity check",
+                        None => "not verifiable, it carries no tag",
+                    };
+                    note!("Recovered {} of {} payload bytes, {}", recovery.recovered, recovery.declared, verified);
+                    if output != Path::new(STDOUT_PATH) {
+                        note!("Secret extracted to {}", output.display());
+                    }
+                } else if all {
+                    let outputs = decode_all(image, output, passphrase)?;
+                    note!("{} message(s) extracted", outputs.len());
+                    for output in outputs {
+                        note!("  {}", output.display());
+                    }
+                } else {
+                    let outcome = if output == Path::new(STDOUT_PATH) {
+                        decode(image, output, passphrase, message, buffer_len, None)?
+                    } else {
+                        progress::with_bar("Decoding", |progress| {
+                            decode(image, output, passphrase, message, buffer_len, progress)
+                        })?
+                    };
+                    if outcome.output != Path::new(STDOUT_PATH) {
+                        note!("Secret extracted: {}", outcome);
+                    }
+                }
+            }
+            Command::BatchEncode { 
+                cover_dir, 
+                secret_dir, 
+                output_dir,
+                force
+            } => batch_encode(cover_dir, secret_dir, output_dir, mask, force)?,
+            Command::EncodeMulti { 
+                secret, 
+                output_dir, 
+                covers,
+                force
+            } => {
+                check_covers_unused(covers.iter().map(PathBuf::as_path), force, "pass --force to overwrite it")?;
+                let outputs = encode_multi(covers, secret, output_dir, mask)?;
+                note!("Secret split into {} shards", outputs.len());
+                for output in outputs {
+                    note!("  {}", output.display());
+                }
+            }
+            Command::EncodeAudio { 
+                audio, 
+                secret, 
+                output,
+                options
+            } => {
+                if !cover::is_wav(&audio) || !cover::is_wav(&output) {
+                    return Err(Error::UnsupportedAudio.into());
+                }
+                check_cover_unused(&audio, &options)?;
+                let passphrase = encode_passphrase(&options)?;
+                let dry_run = options.dry_run;
+                let outcome = progress::with_bar("Encoding", |progress| {
+                    let kdf = AppConfig::load().kdf_params();
+                    let options = EncodeOptions { passphrase, kdf, buffer_len: Some(buffer_len), progress, ..options };
+                    encode(audio, secret, output, mask, &options)
+                })?;
+                if dry_run {
+                    note!("Dry run, nothing written: {}", outcome);
+                } else {
+                    note!("Secret encoded: {}", outcome);
+                }
+            }
+            Command::DecodeAudio { 
+                audio, 
+                output,
+                passphrase,
+                passphrase_file
+            } => {
+                if !cover::is_wav(&audio) {
+                    return Err(Error::UnsupportedAudio.into());
+                }
+                let passphrase = passphrase::resolve(passphrase, passphrase_file.as_deref())?;
+                let passphrase = passphrase.as_deref().map(String::as_str);
+                if depth_given {
+                    warn_depth_mismatch(&audio, 0, mask);
+                }
+                let outcome = if output == Path::new(STDOUT_PATH) {
+                    decode(audio, output, passphrase, 0, buffer_len, None)?
+                } else {
+                    progress::with_bar("Decoding", |progress| {
+                        decode(audio, output, passphrase, 0, buffer_len, progress)
+                    })?
+                };
+                if outcome.output != Path::new(STDOUT_PATH) {
+                    note!("Secret extracted: {}", outcome);
+                }
+            }
+            Command::EncodeQr { image, secret, output, force } => {
+                check_covers_unused([image.as_path()], force, "pass --force to overwrite it")?;
+                let payload = qr::encode(&std::fs::read(secret)?)?;
+                let output = Encoder::from_bytes(image, payload, mask)?.save(output)?;
+                note!("QR code encoded into {}", output.display());
+            }
+            Command::DecodeQr { image, output } => {
+                let secret = qr::decode(&Decoder::new(image)?.read_to_vec()?)?;
+                if output == Path::new(STDOUT_PATH) {
+                    io::stdout().lock().write_all(&secret)?;
+                } else {
+                    std::fs::write(&output, secret)?;
+                    note!("Secret extracted to {}", output.display());
+                }
+            }
+            Command::DecodeMulti { 
+                output, 
+                images 
+            } => {
+                decode_multi(images, output.clone())?;
+                if output != Path::new(STDOUT_PATH) {
+                    note!("Secret reassembled into {}", output.display());
+                }
+            }
+            Command::Capacity { 
+                image, 
+                human,
+                stride
+            } => {
+                if stride == 0 {
+                    return Err(Error::InvalidStride.into());
+                }
+                let capacity = encoder::capacity(cover::carrier_len(&image)?, mask, stride);
+                if human {
+                    println!("{}", human_size(capacity));
+                } else {
+                    println!("{}", capacity);
+                }
+            }
+            Command::Info { image } => {
+                let headers = decoder::read_headers(&image)?;
+                println!("stego image, {} message(s)", headers.len());
+                for (index, header) in headers.iter().enumerate() {
+                    println!(
+                        "message {}: version {}, {}{} LSB bits, stride {}, payload {} bytes{}{}{}{}{}{}",
+                        index,
+                        header.version,
+                        if header.grayscale { "grayscale, " } else { "" },
+                        ChannelByteMask::new(header.bits)?,
+                        header.stride,
+                        header.length,
+                        if header.interleave > 1 {
+                            format!(", interleaved every {} pixels", header.interleave)
+                        } else {
+                            String::new()
+                        },
+                        header.region
+                            .map(|r| format!(", region {}x{} at {},{}", r.width, r.height, r.x, r.y))
+                            .unwrap_or_default(),
+                        header.threshold
+                            .map(|t| format!(", adaptive (texture threshold {})", t))
+                            .unwrap_or_default(),
+                        header.spread
+                            .map(|factor| format!(", spread over {} bytes per bit", factor))
+                            .unwrap_or_default(),
+                        header.protection
+                            .map(|p| format!(", encrypted (Argon2id {} KiB, {} passes)", p.kdf.memory_kib, p.kdf.iterations))
+                            .unwrap_or_default(),
+                        if header.sealed_comment {
+                            ", comment sealed with the payload".to_string()
+                        } else if header.comment.is_empty() {
+                            String::new()
+                        } else {
+                            format!(", comment {:?}", header.comment.as_str())
+                        }
+                    );
+                }
+            }
+            Command::Analyze { image } => {
+                let analysis = analysis::chi_square(Cover::open(&image)?.bytes());
+                for (name, score) in ["R", "G", "B"].iter().zip(analysis.channels) {
+                    println!("{}: {:.3}", name, score);
+                }
+                let verdict = if analysis.suspicious() { "LSB embedding likely" } else { "no sign of LSB embedding" };
+                println!("overall: {:.3} ({})", analysis.overall, verdict);
+                if analysis.suspicious() {
+                    println!("run `sanitize` to scrub the low bits");
+                }
+            }
+            Command::Diff { cover, stego, output } => {
+                let heatmap = analysis::heatmap(&image::open(&cover)?.into_rgb8(), &image::open(&stego)?.into_rgb8())?;
+                let changed = heatmap.iter().filter(|&&c| c != 0).count();
+                heatmap.save(&output)?;
+                note!("{} of {} channels differ, heatmap written to {}", changed, heatmap.len(), output.display());
+            }
+            Command::Sanitize { image, output, zero } => {
+                let mut cover = Cover::open(&image)?;
+                let padding = if zero { Padding::Zero } else { Padding::Random };
+                let altered = encoder::sanitize(cover.bytes_mut(), mask, padding);
+                cover.save(&output)?;
+                note!("Sanitized {} of {} bytes into {}", altered, cover.bytes().len(), output.display());
+            }
+        }
+        
+        return Ok(());
+    }
+    
+    if opt.quiet {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--quiet needs a subcommand, the TUI has no quiet mode").into());
+    }
+    
+    logger::capture();
     enable_raw_mode()?;
     let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     
-    let mut app = App::default();
+    let mut app = App::new(AppConfig::load());
+    if let Some(path) = opt.path {
+        app.open_path(path);
+    }
     let res = run_app(&mut terminal, &mut app);
     
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste)?;
     terminal.show_cursor()?;
     
     if let Err(err) = res {
         println!("{:?}", err);
+    } else if let Err(err) = app.config.save() {
+        eprintln!("{}", err);
     }
     
     Ok(())
@@ -152,191 +899,1171 @@ fn encode(
     image: PathBuf,
     secret: PathBuf,
     output: PathBuf,
-    mask: ByteMask
-) -> Result<(), Error> {
-    let mut encoder = Encoder::new(image, secret, mask)?;
-    encoder.save(output)?;
-    Ok(())
+    mask: ChannelByteMask,
+    options: &EncodeOptions
+) -> Result<EncodeOutcome, Error> {
+    let mut encoder = if options.truecolor {
+        Encoder::new_truecolor(image, secret, mask)?
+    } else {
+        Encoder::new(image, secret, mask)?
+    };
+    encoder.set_append(options.append)?;
+    if let Some(factor) = options.spread {
+        encoder.set_spread(factor)?;
+    }
+    encoder.set_padding(options.padding);
+    if let Some(stride) = options.stride {
+        encoder.set_stride(stride)?;
+    }
+    if let Some(run) = options.interleave {
+        encoder.set_interleave(run)?;
+    }
+    if let [x, y, width, height] = options.region[..] {
+        encoder.set_region(x, y, width, height)?;
+    }
+    encoder.set_adaptive(options.adaptive);
+    encoder.set_preserve_format(!options.convert);
+    if let Some(passphrase) = &options.passphrase {
+        encoder.set_passphrase(passphrase);
+        encoder.set_kdf_params(options.kdf);
+    }
+    if let Some(comment) = &options.comment {
+        encoder.set_comment(comment)?;
+    }
+    encoder.set_seal_comment(options.seal_comment);
+    encoder.set_verify(options.verify);
+    if let Some(cancel) = &options.cancel {
+        encoder.set_cancel_flag(Arc::clone(cancel));
+    }
+    if let Some(progress) = &options.progress {
+        encoder.set_progress(Arc::clone(progress));
+    }
+    encoder.set_report(options.report);
+    if let Some(len) = options.buffer_len {
+        encoder.set_buffer_len(len);
+    }
+    if options.dry_run {
+        encoder.dry_run(output)
+    } else {
+        encoder.save_with_outcome(output)
+    }
 }
 
 fn decode(
     image: PathBuf, 
-    output: PathBuf, 
-    mask: ByteMask
+    output: PathBuf,
+    passphrase: Option<&str>,
+    message: usize,
+    buffer_len: usize,
+    progress: Option<Progress>
+) -> Result<DecodeOutcome, Error> {
+    let mut decoder = Decoder::new(image)?;
+    decoder.select(message)?;
+    decoder.set_buffer_len(buffer_len);
+    match passphrase {
+        Some(passphrase) => decoder.set_passphrase(passphrase),
+        None if decoder.headers()[message].protection.is_some() => ask_passphrase(&mut decoder)?,
+        None => {}
+    }
+    if let Some(progress) = progress {
+        decoder.set_progress(progress);
+    }
+    if output == Path::new(STDOUT_PATH) {
+        let payload_len = decoder.write_to(io::stdout().lock())?;
+        Ok(DecodeOutcome { output, payload_len, mask: decoder.mask(), timings: None })
+    } else {
+        decoder.save_with_outcome(output)
+    }
+}
+
+// A decode that reads as much as the image holds and writes it, however
+// much of the secret that is.
+fn recover(image: PathBuf, output: PathBuf, passphrase: Option<&str>, message: usize) -> Result<(Recovery, PathBuf), Error> {
+    let mut decoder = Decoder::new_lenient(image)?;
+    decoder.select(message)?;
+    match passphrase {
+        Some(passphrase) => decoder.set_passphrase(passphrase),
+        None if decoder.headers()[message].protection.is_some() => ask_passphrase(&mut decoder)?,
+        None => {}
+    }
+    let recovery = decoder.recover()?;
+    let output = if output == Path::new(STDOUT_PATH) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&recovery.secret)?;
+        stdout.flush()?;
+        output
+    } else {
+        decoder::save_secret(&recovery.secret, output)?
+    };
+    
+    Ok((recovery, output))
+}
+
+// Decoding always uses the depth the header records, which is read at a
+// single LSB whatever the payload used. A --bits or --channels that says
+// otherwise is most likely a mix-up, so it's pointed out rather than
+// silently ignored.
+fn warn_depth_mismatch(image: &Path, message: usize, given: ChannelByteMask) {
+    let Ok(headers) = decoder::read_headers(image) else {
+        return;
+    };
+    if let Some(Ok(embedded)) = headers.get(message).map(|h| ChannelByteMask::new(h.bits)) && embedded != given {
+        note!(
+            "warning: this image appears to use {} bits, not {}, decoding at the depth its header records",
+            embedded,
+            given
+        );
+    }
+}
+
+// Encoding over a message already in the cover overwrites its header, so
+// the output only holds the new one. --append keeps it and --force goes
+// ahead without asking, otherwise the user is asked, or without a
+// terminal to ask on, told which to pass. A dry run loses nothing.
+fn check_cover_unused(cover: &Path, options: &EncodeOptions) -> io::Result<()> {
+    if options.append || options.dry_run {
+        return Ok(());
+    }
+    
+    check_covers_unused([cover], options.force, "pass --append to keep it or --force to overwrite it")
+}
+
+// The same check for the commands that write several outputs or can't
+// append. Each cover holding a message is asked about in turn, before
+// anything is written.
+fn check_covers_unused<'a>(covers: impl IntoIterator<Item = &'a Path>, force: bool, hint: &str) -> io::Result<()> {
+    if force {
+        return Ok(());
+    }
+    for cover in covers {
+        if decoder::read_header(cover).is_err() {
+            continue;
+        }
+        if !confirm(&format!("{} already contains hidden data; continue?", cover.display()))? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} already contains hidden data, {}", cover.display(), hint)
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+// Asks on stderr and reads the answer from stdin. Anything but y or yes is
+// a no, and so is having no terminal to ask on.
+fn confirm(question: &str) -> io::Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+// Only asks on a terminal. Without one the decode goes on and fails with
+// Error::PassphraseRequired as it would have.
+fn ask_passphrase(decoder: &mut Decoder) -> io::Result<()> {
+    if let Some(passphrase) = passphrase::prompt("Passphrase")? {
+        decoder.set_passphrase(&passphrase);
+    }
+    Ok(())
+}
+
+// --passphrase or --passphrase-file, then STEGNOAPP_PASSPHRASE, then the
+// prompt if --ask-passphrase is on. A typo would lock the secret away, so
+// the prompt asks twice.
+fn encode_passphrase(options: &EncodeOptions) -> io::Result<Option<Passphrase>> {
+    let passphrase = passphrase::resolve(options.passphrase.clone(), options.passphrase_file.as_deref())?;
+    if passphrase.is_some() || !options.ask_passphrase {
+        return Ok(passphrase);
+    }
+    
+    let Some(first) = passphrase::prompt("Passphrase")? else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--ask-passphrase needs a terminal to ask on"));
+    };
+    if passphrase::prompt("Repeat the passphrase")?.as_ref() != Some(&first) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The passphrases don't match"));
+    }
+    Ok(Some(first))
+}
+
+// Every message goes to `output_dir` as message-<index>, with an extension
+// guessed from its contents.
+fn decode_all(image: PathBuf, output_dir: PathBuf, passphrase: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+    let mut decoder = Decoder::new(image)?;
+    match passphrase {
+        Some(passphrase) => decoder.set_passphrase(passphrase),
+        None if decoder.headers().iter().any(|h| h.protection.is_some()) => ask_passphrase(&mut decoder)?,
+        None => {}
+    }
+    
+    std::fs::create_dir_all(&output_dir)?;
+    let mut outputs = Vec::new();
+    for (index, secret) in decoder.read_all()? {
+        let output = output_dir.join(format!("message-{}.{}", index, decoder::infer_extension(&secret)));
+        std::fs::write(&output, secret)?;
+        outputs.push(output);
+    }
+    
+    Ok(outputs)
+}
+
+// Reads the payload into memory for the decode screen, which shows it
+// before anything is written.
+fn decode_payload(
+    image: PathBuf,
+    passphrase: Option<&str>,
+    cancel: Arc<AtomicBool>,
+    progress: Progress
+) -> Result<(ChannelByteMask, Vec<u8>), Error> {
+    let mut decoder = Decoder::new(image)?;
+    if let Some(passphrase) = passphrase {
+        decoder.set_passphrase(passphrase);
+    }
+    decoder.set_cancel_flag(cancel);
+    decoder.set_progress(progress);
+    
+    Ok((decoder.mask(), decoder.read_to_vec()?))
+}
+
+// Fails after the summary when any secret couldn't be encoded, so the
+// exit code says whether the whole batch went through.
+fn batch_encode(
+    cover_dir: PathBuf,
+    secret_dir: PathBuf,
+    output_dir: PathBuf,
+    mask: ChannelByteMask,
+    force: bool
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut covers = Vec::new();
+    for path in list_files(&cover_dir)? {
+        if image::ImageFormat::from_path(&path).is_ok() || cover::is_wav(&path) {
+            covers.push(path);
+        } else {
+            note!("warning: skipping {}, not an image or WAV cover", path.display());
+        }
+    }
+    let secrets = list_files(&secret_dir)?;
+    
+    if covers.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("No cover images found in {}", cover_dir.display())).into());
+    }
+    // Checked once up front rather than failing every secret in turn.
+    std::fs::create_dir_all(&output_dir)?;
+    utils::check_writable(&output_dir.join(".probe"))?;
+    let used = covers.iter().take(secrets.len()).map(PathBuf::as_path);
+    check_covers_unused(used, force, "pass --force to overwrite it")?;
+    if secrets.len() > covers.len() {
+        note!(
+            "warning: {} secrets but only {} covers, covers will be reused",
+            secrets.len(),
+            covers.len()
+        );
+    } else if secrets.len() < covers.len() {
+        note!(
+            "warning: {} covers but only {} secrets, extra covers are left unused",
+            covers.len(),
+            secrets.len()
+        );
+    }
+    
+    let mut encoded = Vec::new();
+    let mut failed = Vec::new();
+    
+    for (secret, cover) in secrets.iter().zip(covers.iter().cycle()) {
+        let mut name = secret.file_name().unwrap_or_default().to_os_string();
+        // Covers that aren't still images get their own extension back.
+        name.push(".png");
+        let output = output_dir.join(name);
+        
+        match encode(cover.clone(), secret.clone(), output, mask, &EncodeOptions::default()) {
+            Ok(outcome) => encoded.push(outcome.output),
+            Err(Error::SecretTooLarge) => {
+                note!(
+                    "warning: skipping {}, too large for {}",
+                    secret.display(),
+                    cover.display()
+                );
+                failed.push((secret.clone(), Error::SecretTooLarge));
+            }
+            Err(e) => failed.push((secret.clone(), e)),
+        }
+    }
+    
+    note!("Encoded {} of {} secrets", encoded.len(), secrets.len());
+    for output in &encoded {
+        note!("  ok     {}", output.display());
+    }
+    for (secret, e) in &failed {
+        eprintln!("  failed {}: {}", secret.display(), e);
+    }
+    if !failed.is_empty() {
+        return Err(io::Error::other(format!("{} of {} secrets failed to encode", failed.len(), secrets.len())).into());
+    }
+    
+    Ok(())
+}
+
+fn encode_multi(
+    covers: Vec<PathBuf>,
+    secret: PathBuf,
+    output_dir: PathBuf,
+    mask: ChannelByteMask
+) -> Result<Vec<PathBuf>, Error> {
+    let secret = std::fs::read(secret)?;
+    
+    let mut shards = Vec::new();
+    let mut remaining = secret.as_slice();
+    // An empty secret still goes into one shard, so it decodes back empty
+    // instead of leaving nothing to decode.
+    for cover in covers {
+        if remaining.is_empty() && !shards.is_empty() {
+            break;
+        }
+        let image = Cover::open(&cover)?;
+        let room = encoder::capacity(image.bytes().len(), mask, 1).saturating_sub(SHARD_HEADER_LEN);
+        if room == 0 {
+            note!("warning: {} is too small to hold a shard", cover.display());
+            continue;
+        }
+        let (data, rest) = remaining.split_at(room.min(remaining.len()));
+        remaining = rest;
+        shards.push((cover, image, data));
+    }
+    
+    if !remaining.is_empty() || shards.is_empty() {
+        return Err(Error::SecretTooLarge);
+    }
+    
+    let group = rand::random::<[u8; 4]>();
+    let total = u16::try_from(shards.len()).map_err(|_| Error::InvalidShard)?;
+    let mut outputs = Vec::new();
+    
+    for (index, (cover, image, data)) in shards.into_iter().enumerate() {
+        let header = ShardHeader { group, index: index as u16, total };
+        let mut payload = header.to_bytes().to_vec();
+        payload.extend_from_slice(data);
+        
+        let stem = cover.file_stem().unwrap_or_default().to_string_lossy();
+        let output = output_dir.join(format!("{}_shard{}.png", stem, index));
+        
+        let mut encoder = Encoder::from_cover(image, payload, mask)?;
+        outputs.push(encoder.save(output)?);
+    }
+    
+    Ok(outputs)
+}
+
+fn decode_multi(
+    images: Vec<PathBuf>,
+    output: PathBuf
 ) -> Result<(), Error> {
-    let decoder = Decoder::new(image, mask)?;
-    decoder.save(output)?;
+    let mut shards = Vec::new();
+    for image in images {
+        let mut payload = Decoder::new(image)?.read_to_vec()?;
+        let header = ShardHeader::from_bytes(&payload)?;
+        shards.push((header, payload.split_off(SHARD_HEADER_LEN)));
+    }
+    
+    let secret = shard::join_shards(shards)?;
+    if output == Path::new(STDOUT_PATH) {
+        io::stdout().lock().write_all(&secret)?;
+    } else {
+        std::fs::write(output, secret)?;
+    }
+    
     Ok(())
 }
 
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    
+    Ok(files)
+}
+
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App 
 ) -> io::Result<()> {
     loop {
-        terminal.draw(|f| ui(f, app))?;
+        poll_job(app);
+        for line in logger::drain() {
+            app.status.log(line);
+        }
+        let theme = Theme::by_name(&app.config.theme);
+        terminal.draw(|f| ui(f, app, &theme))?;
         
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                match app.curr_screen {
-                    Screen::MainMenu => handle_main_menu_events(app, key.code),
-                    Screen::Encode => handle_encode_events(app, key.code)?,
-                    Screen::Decode => handle_decode_events(app, key.code)?,
-                    Screen::FileExplorer => handle_file_explorer_events(app, key.code)?,
-                    _ => {}
-                }
-                if app.curr_screen == Screen::Quit {
-                    return Ok(());
+        // While a job runs only Esc is handled, and it cancels the job
+        // instead of quitting. Resizes still reflow the progress bar.
+        if let Some(job) = &app.job {
+            if !event::poll(JOB_POLL_INTERVAL)? {
+                continue;
+            }
+            match event::read()? {
+                Event::Key(key) if (key.kind == KeyEventKind::Press) && (key.code == KeyCode::Esc) => {
+                    job.cancel();
+                    app.status.set("Cancelling...");
                 }
-                if key.code == KeyCode::Esc || key.code == KeyCode::Char('q') {
-                    return Ok(());
+                Event::Resize(..) => terminal.clear()?,
+                _ => {}
+            }
+            continue;
+        }
+        
+        let evt = event::read()?;
+        // The next draw picks the new size up, clearing makes it repaint
+        // every cell rather than diff against a buffer of the old size.
+        if let Event::Resize(..) = evt {
+            terminal.clear()?;
+        } else if let Event::Paste(text) = &evt {
+            if app.passphrase_prompt.is_some() {
+                handle_passphrase_events(app, evt);
+            } else if let Some(input) = app.output_edit.as_mut() {
+                paste_into(input, text);
+            }
+        } else if let Event::Mouse(mouse) = evt {
+            let size = terminal.size()?;
+            handle_mouse_events(app, mouse, Rect::new(0, 0, size.width, size.height))?;
+            if app.curr_screen == Screen::Quit {
+                return Ok(());
+            }
+        } else if let Event::Key(key) = evt && key.kind == KeyEventKind::Press {
+            if app.error_modal.take().is_some() || app.plan_modal.take().is_some() {
+                continue;
+            }
+            if app.reuse_modal {
+                app.reuse_modal = false;
+                if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                    run_encode(app);
+                } else {
+                    app.status.set("Encode cancelled, the cover's hidden data is left alone");
+                }
+                continue;
+            }
+            if let Some(path) = app.overwrite_modal.take() {
+                if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                    set_output(app, path);
+                } else {
+                    app.decode_save_pending = false;
+                    app.status.set("Output path unchanged");
+                }
+                continue;
+            }
+            if app.passphrase_prompt.is_some() {
+                handle_passphrase_events(app, evt);
+                continue;
+            }
+            if app.output_edit.is_some() {
+                handle_output_edit_events(app, evt);
+                continue;
+            }
+            let can_open_history = !matches!(app.curr_screen, Screen::History | Screen::FileExplorer);
+            if (key.code == app.config.keys.key(Action::History)) && can_open_history {
+                app.prev_screen = Some(app.curr_screen);
+                app.curr_screen = Screen::History;
+                app.history_scroll = 0;
+                continue;
+            }
+            #[cfg(debug_assertions)]
+            let started = std::time::Instant::now();
+            match app.curr_screen {
+                Screen::MainMenu => handle_main_menu_events(app, key.code),
+                Screen::Encode => handle_encode_events(app, key.code)?,
+                Screen::Decode => handle_decode_events(app, key.code)?,
+                Screen::Settings => handle_settings_events(app, key.code),
+                Screen::FileExplorer => handle_file_explorer_events(app, key.code)?,
+                Screen::History => handle_history_events(app, key.code),
+                _ => {}
+            }
+            #[cfg(debug_assertions)]
+            if started.elapsed() > SLOW_KEY {
+                log::debug!("Handling {:?} took {:?}", key.code, started.elapsed());
+            }
+            if app.curr_screen == Screen::Quit {
+                return Ok(());
+            }
+            if (key.code == KeyCode::Esc) || (key.code == app.config.keys.key(Action::Quit)) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn poll_job(app: &mut App) {
+    while let Some(event) = app.job.as_ref().and_then(Job::poll) {
+        let took = app.job.as_ref().map(Job::elapsed).unwrap_or_default();
+        match event {
+            JobEvent::Progress(stage) => app.status.set(stage),
+            JobEvent::Advanced(done, total) => app.job_progress = Some((done, total)),
+            JobEvent::Encoded(result) => {
+                app.job = None;
+                match result {
+                    Ok(outcome) => {
+                        let verified = if app.encode_verify { ", output verified" } else { "" };
+                        let report = outcome.report.map(|r| format!(" ({})", r)).unwrap_or_default();
+                        let message = format!(
+                            "Encode successful{} in {:.1?}! Saved to {}{}",
+                            verified,
+                            took,
+                            shown(&outcome.output),
+                            report
+                        );
+                        finished_with(app, message, outcome.output);
+                    }
+                    Err(Error::Cancelled) => app.status.set("Encode cancelled"),
+                    Err(e) => {
+                        app.status.set("Encode failed");
+                        app.error_modal = Some(format!("Encode failed: {}", e));
+                    }
+                }
+            }
+            JobEvent::Planned(result) => {
+                app.job = None;
+                match result {
+                    Ok(outcome) => {
+                        app.status.set(format!("Dry run done in {:.1?}, nothing written", took));
+                        app.plan_modal = Some(format!("The encode would write {}", outcome));
+                    }
+                    Err(Error::Cancelled) => app.status.set("Dry run cancelled"),
+                    Err(e) => {
+                        app.status.set("Dry run failed");
+                        app.error_modal = Some(format!("The encode would fail: {}", e));
+                    }
+                }
+            }
+            JobEvent::Decoded(result) => {
+                app.job = None;
+                match result {
+                    Ok((mask, secret)) => {
+                        let run = app.config.keys.label(Action::Run);
+                        app.status.set(format!(
+                            "Decoded {} bytes in {:.1?}, nothing written yet ({} to save)",
+                            secret.len(),
+                            took,
+                            run
+                        ));
+                        app.decode_bits = Some(mask);
+                        app.decode_payload = Some(secret);
+                    }
+                    Err(Error::Cancelled) => app.status.set("Decode cancelled"),
+                    Err(e) => {
+                        app.decode_bits = None;
+                        app.decode_payload = None;
+                        app.status.set("Decode failed");
+                        app.error_modal = Some(format!("Decode failed: {}", e));
+                    }
                 }
             }
+            JobEvent::Probed(pick, path, probed) => {
+                app.job = None;
+                finish_probe(app, pick, path, probed);
+            }
+            JobEvent::Lost => {
+                app.job = None;
+                app.status.set("Operation failed");
+                app.error_modal = Some("The worker thread stopped unexpectedly".to_string());
+            }
         }
     }
+    if app.job.is_none() {
+        app.job_progress = None;
+    }
+}
+
+// Remembers what a job wrote and shows it right away when the settings ask
+// for that, or says which key does.
+fn finished_with(app: &mut App, message: String, output: PathBuf) {
+    app.last_output = Some(output);
+    if app.config.reveal_output {
+        app.status.set(message);
+        reveal_output(app);
+    } else {
+        app.status.set(format!("{} ({} to show it)", message, app.config.keys.label(Action::RevealOutput)));
+    }
+}
+
+fn reveal_output(app: &mut App) {
+    let Some(output) = &app.last_output else {
+        app.status.set("Nothing written yet, run an encode or decode first");
+        return;
+    };
+    
+    app.status.set(match reveal::reveal(output) {
+        Ok(true) => format!("Showing {} in the file manager", shown(output)),
+        Ok(false) => format!("No desktop to open a file manager on, the output is at {}", shown(output)),
+        Err(e) => format!("Could not open a file manager: {}", e),
+    });
 }
 
-fn ui(f: &mut ratatui::Frame, app: &App) {
-    let chunks = Layout::default()
+fn app_layout(area: Rect) -> std::rc::Rc<[Rect]> {
+    Layout::default()
         .direction(ratatui::layout::Direction::Vertical)
         .constraints([Constraint::Length(3), Constraint::Min(1), Constraint::Length(1)])
-        .split(f.area());
+        .split(area)
+}
+
+fn ui(f: &mut ratatui::Frame, app: &App, theme: &Theme) {
+    let chunks = app_layout(f.area());
     
-    let menu_titles = vec!["Encode", "Decode", "Settings", "Help", "Quit"];
-    let tabs = Tabs::new(menu_titles.iter().cloned().map(|s| s.to_string()).collect::<Vec<_>>())
-        .block(Block::default().title("Stegnoapp").borders(Borders::ALL))
+    let tabs = Tabs::new(MENU.iter().map(|(title, _)| title.to_string()).collect::<Vec<_>>())
+        .block(Block::default().title("Stegnoapp").borders(Borders::ALL).border_style(theme.border()))
         .select(app.menu_index)
-        .highlight_style(Style::default().fg(ratatui::style::Color::Yellow));
+        .highlight_style(theme.highlight());
     f.render_widget(tabs, chunks[0]);
     
     match app.curr_screen {
         Screen::MainMenu => {
             let welcome = Paragraph::new("Select an option from the menu above.\nPress Enter to confirm")
-                .block(Block::default().borders(Borders::ALL).title("Main Menu"));
+                .block(Block::default().borders(Borders::ALL).border_style(theme.border()).title("Main Menu"));
             f.render_widget(welcome, chunks[1]);
         }
         Screen::Encode => {
             let sub_chunks = Layout::default()
                 .direction(ratatui::layout::Direction::Vertical)
-                .constraints([Constraint::Percentage(25), Constraint::Percentage(25), Constraint::Percentage(25), Constraint::Percentage(25)])
+                .constraints([Constraint::Percentage(20), Constraint::Percentage(20), Constraint::Percentage(20), Constraint::Percentage(20), Constraint::Percentage(20)])
                 .split(chunks[1]);
             
-            let image_path_str = app.encode_image_input.as_ref().map(|p| p.display().to_string()).unwrap_or("Not selected (press 'i' to select)".to_string());
-            let image_input = Paragraph::new(image_path_str)
-                .block(Block::default().title("Cover Image Path").borders(Borders::ALL));
+            let keys = &app.config.keys;
+            let image_path_str = app.encode_image_input.as_ref().map(|p| field_path(p, sub_chunks[0])).unwrap_or_else(|| format!("Not selected (press {} to select)", keys.label(Action::SelectImage)));
+            let image_info = app.encode_image_info
+                .as_ref()
+                .map(|info| Line::from(info.to_string()))
+                .unwrap_or_default();
+            let image_input = Paragraph::new(vec![Line::from(image_path_str), image_info])
+                .block(Block::default().title("Cover Image/Audio Path").borders(Borders::ALL).border_style(theme.border()));
             f.render_widget(image_input, sub_chunks[0]);
             
-            let secret_path_str = app.encode_secret_input.as_ref().map(|p| p.display().to_string()).unwrap_or("Not selected (press 's' to select)".to_string());
-            let secret_input = Paragraph::new(secret_path_str)
-                .block(Block::default().title("Secret File Path").borders(Borders::ALL));
+            let secret_path_str = app.encode_secret_input.as_ref().map(|p| field_path(p, sub_chunks[1])).unwrap_or_else(|| format!("Not selected (press {} to select)", keys.label(Action::SelectSecret)));
+            let encrypt = match (app.encode_encrypt, passphrase::from_env()) {
+                (false, _) => "off",
+                (true, Some(_)) => "on, with STEGNOAPP_PASSPHRASE",
+                (true, None) => "on, the passphrase is asked for before encoding",
+            };
+            let encrypt = format!("Encrypt: {} ({} to toggle)", encrypt, keys.label(Action::ToggleEncrypt));
+            let secret_input = Paragraph::new(vec![Line::from(secret_path_str), Line::from(encrypt)])
+                .block(Block::default().title("Secret File Path").borders(Borders::ALL).border_style(theme.border()));
             f.render_widget(secret_input, sub_chunks[1]);
             
-            let output_path_str = app.encode_output_input.as_ref().map(|p| p.display().to_string()).unwrap_or("Not selected (press 'o' to select)".to_string());
-            let output_input = Paragraph::new(output_path_str)
-                .block(Block::default().title("Output Path").borders(Borders::ALL));
-            f.render_widget(output_input, sub_chunks[2]);
+            render_output_path(f, app, app.encode_output_input.as_deref(), sub_chunks[2], theme);
             
-            let bits_display = Paragraph::new(format!("Bits: {}", app.encode_bits))
-                .block(Block::default().title("LSB Bits (Up/Down to change)").borders(Borders::ALL));
-            f.render_widget(bits_display, sub_chunks[3]);
+            let verify = if app.encode_verify { "on" } else { "off" };
+            let bits_block = Block::default().borders(Borders::ALL).border_style(theme.border());
+            match &app.output_edit {
+                Some(input) if app.comment_edit => {
+                    render_input(f, input, bits_block.title("Comment, readable without decoding (Enter to accept, Esc to cancel)"), sub_chunks[3]);
+                }
+                _ => {
+                    let comment = if app.encode_comment.is_empty() { "none" } else { &app.encode_comment };
+                    let bits_display = Paragraph::new(format!("Bits: {}\nVerify after encode: {}\nComment: {}", app.bits, verify, comment))
+                        .block(bits_block.title(format!(
+                            "LSB Bits ({}/{} to change, {} to fit the secret, {} toggles verify, {} to comment)",
+                            keys.label(Action::MoreBits),
+                            keys.label(Action::FewerBits),
+                            keys.label(Action::FitBits),
+                            keys.label(Action::ToggleVerify),
+                            keys.label(Action::EditComment)
+                        )));
+                    f.render_widget(bits_display, sub_chunks[3]);
+                }
+            }
+            
+            let capacity_gauge = match (app.job_progress, encode_fit(app)) {
+                (Some(progress), _) => progress_gauge("Encoding", progress, theme),
+                (None, Some((secret_len, capacity))) => {
+                    let ratio = secret_len as f64 / capacity.max(1) as f64;
+                    let color = if ratio > 1.0 { theme.overflows } else { theme.fits };
+                    Gauge::default()
+                        .ratio(ratio.min(1.0))
+                        .label(format!("{} / {} bytes ({:.1}%)", secret_len, capacity, ratio * 100.0))
+                        .gauge_style(Style::default().fg(color))
+                }
+                (None, None) => Gauge::default().ratio(0.0).label("Select a cover image and secret"),
+            };
+            let capacity_title = format!("Capacity ({} for a dry run)", keys.label(Action::Preview));
+            f.render_widget(capacity_gauge.block(Block::default().title(capacity_title).borders(Borders::ALL).border_style(theme.border())), sub_chunks[4]);
         }
         Screen::Decode => {
             let sub_chunks = Layout::default()
                 .direction(ratatui::layout::Direction::Vertical)
-                .constraints([Constraint::Percentage(33), Constraint::Percentage(33), Constraint::Percentage(33)])
+                .constraints([Constraint::Percentage(20), Constraint::Percentage(20), Constraint::Percentage(20), Constraint::Percentage(40)])
                 .split(chunks[1]);
             
-            let image_path_str = app.decode_image_input.as_ref().map(|p| p.display().to_string()).unwrap_or("Not selected (press 'i' to select)".to_string());
-            let image_input = Paragraph::new(image_path_str)
-                .block(Block::default().title("Stego Image Path").borders(Borders::ALL));
+            let keys = &app.config.keys;
+            let image_path_str = app.decode_image_input.as_ref().map(|p| field_path(p, sub_chunks[0])).unwrap_or_else(|| format!("Not selected (press {} to select)", keys.label(Action::SelectImage)));
+            let protected = if app.decode_protected { "Encrypted, the passphrase is asked for before decoding" } else { "" };
+            let archive = if app.decode_archive { "A directory, unpacked into the output path" } else { "" };
+            let image_input = Paragraph::new(vec![Line::from(image_path_str), Line::from(protected), Line::from(archive)])
+                .block(Block::default().title("Stego Image/Audio Path").borders(Borders::ALL).border_style(theme.border()));
             f.render_widget(image_input, sub_chunks[0]);
-            
-            let output_path_str = app.decode_output_input.as_ref().map(|p| p.display().to_string()).unwrap_or("Not selected (press 'o' to select)".to_string());
-            let output_input = Paragraph::new(output_path_str)
-                .block(Block::default().title("Output Path").borders(Borders::ALL));
-           f.render_widget(output_input, sub_chunks[1]);
+           
+           render_output_path(f, app, app.decode_output_input.as_deref(), sub_chunks[1], theme);
+          
+          if let Some(progress) = app.job_progress {
+              let gauge = progress_gauge("Decoding", progress, theme);
+              f.render_widget(gauge.block(Block::default().title("Progress").borders(Borders::ALL).border_style(theme.border())), sub_chunks[2]);
+          } else {
+              let detected = app.decode_bits.map(|b| b.to_string()).unwrap_or("Unknown, no stego header found yet".to_string());
+              let bits_display = Paragraph::new(format!("Bits: {}", detected))
+                  .block(Block::default().title("Detected LSB Bits").borders(Borders::ALL).border_style(theme.border()));
+              f.render_widget(bits_display, sub_chunks[2]);
+          }
           
-          let bits_display = Paragraph::new(format!("Bits: {}", app.decode_bits))
-              .block(Block::default().title("LSB Bits (Up/Down to Change)").borders(Borders::ALL));
-          f.render_widget(bits_display, sub_chunks[2]);
+          let (preview_title, preview_text) = match &app.decode_payload {
+              Some(secret) => {
+                  let shown = &secret[..secret.len().min(preview::PREVIEW_LEN)];
+                  (
+                      format!("Preview (first {} of {} bytes, {} to save)", shown.len(), secret.len(), keys.label(Action::Run)),
+                      preview::render(shown),
+                  )
+              }
+              None => ("Preview".to_string(), format!("Press {} to decode and look at the payload before saving it", keys.label(Action::Run))),
+          };
+          let preview_panel = Paragraph::new(preview_text)
+              .block(Block::default().title(preview_title).borders(Borders::ALL).border_style(theme.border()));
+          f.render_widget(preview_panel, sub_chunks[3]);
+        }
+        Screen::Settings => {
+            let keys = &app.config.keys;
+            let config_path = AppConfig::path()
+                .map(|p| p.display().to_string())
+                .unwrap_or("Unavailable on this platform".to_string());
+            let output_dir = app.config.output_dir
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or("Current directory".to_string());
+            let settings = Paragraph::new(format!(
+                "Default bits: {} ({}/{} to change)\nDefault output directory: {}\nEncode output name: {} ({} to edit)\nDecode output name: {} ({} to edit)\nChannels: {}\nTheme: {} ({} to change)\nPassphrase key derivation: Argon2id, {} MiB ({} to change), {} passes ({} to change)\nOpen the output folder after each run: {} ({} to toggle)\n\nConfig file: {}",
+                app.config.bits,
+                keys.label(Action::MoreBits),
+                keys.label(Action::FewerBits),
+                output_dir,
+                app.config.encode_output_name,
+                keys.label(Action::EditEncodeName),
+                app.config.decode_output_name,
+                keys.label(Action::EditDecodeName),
+                app.config.channels,
+                app.config.theme,
+                keys.label(Action::CycleTheme),
+                app.config.kdf_memory_kib / 1024,
+                keys.label(Action::CycleKdfMemory),
+                app.config.kdf_iterations,
+                keys.label(Action::CycleKdfPasses),
+                if app.config.reveal_output { "on" } else { "off" },
+                keys.label(Action::ToggleReveal),
+                config_path
+            ))
+            .block(Block::default().title("Settings").borders(Borders::ALL).border_style(theme.border()));
+            
+            if let Some(input) = &app.output_edit {
+                let sub_chunks = Layout::default()
+                    .direction(ratatui::layout::Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(3)])
+                    .split(chunks[1]);
+                f.render_widget(settings, sub_chunks[0]);
+                let block = Block::default().borders(Borders::ALL).border_style(theme.border());
+                render_input(f, input, block.title("Output Name, {name} for the file's stem (Enter to accept, Esc to cancel)"), sub_chunks[1]);
+            } else {
+                f.render_widget(settings, chunks[1]);
+            }
+        }
+        Screen::History => {
+            let items = app.status.entries()
+                .rev()
+                .map(|e| ListItem::new(format!("[{}] {}", e.time, e.message)))
+                .collect::<Vec<_>>();
+            let history = List::new(items)
+                .block(Block::default().title("Status History (UTC, Up/Down to scroll, Backspace to return)").borders(Borders::ALL).border_style(theme.border()))
+                .highlight_style(theme.highlight());
+            let mut state = ListState::default().with_selected(Some(app.history_scroll));
+            f.render_stateful_widget(history, chunks[1], &mut state);
         }
         Screen::FileExplorer => {
             if let Some(explorer) = &app.file_explorer {
-                let widget = explorer.widget();
-                f.render_widget(&widget, chunks[1]);
+                if app.explorer_filter {
+                    let visible = |file: &&File| explorer_shows(app, file);
+                    let items = explorer.files()
+                        .iter()
+                        .filter(visible)
+                        .map(|file| ListItem::new(file.name().to_string()))
+                        .collect::<Vec<_>>();
+                    let selected = explorer.files()
+                        .iter()
+                        .take(explorer.selected_idx())
+                        .filter(visible)
+                        .count();
+                    let list = List::new(items)
+                        .block(Block::default()
+                            .title(format!(
+                                "{} [covers only, {} to show all]",
+                                explorer.cwd().display(),
+                                app.config.keys.label(Action::ToggleFilter)
+                            ))
+                            .borders(Borders::ALL).border_style(theme.border()))
+                        .highlight_style(theme.highlight());
+                    let mut state = ListState::default().with_selected(Some(selected));
+                    f.render_stateful_widget(list, chunks[1], &mut state);
+                } else {
+                    let widget = explorer.widget();
+                    f.render_widget(&widget, chunks[1]);
+                }
             }
         }
         _ => {}
     }
     
-    let status_bar = Paragraph::new(app.status.as_str())
-        .style(Style::default().bg(ratatui::style::Color::Blue).fg(ratatui::style::Color::White));
+    let status_bar = Paragraph::new(app.status.current())
+        .style(theme.status());
     f.render_widget(status_bar, chunks[2]);
+    
+    if let Some(prompt) = &app.passphrase_prompt {
+        let area = centered_rect(60, 20, f.area());
+        let title = format!(
+            "Passphrase to {} (Enter to accept, Tab to {}, Esc to cancel)",
+            if prompt.screen == Screen::Encode { "encrypt with" } else { "decrypt" },
+            if prompt.revealed { "hide" } else { "reveal" }
+        );
+        let block = Block::default().title(title).borders(Borders::ALL).border_style(theme.border());
+        f.render_widget(Clear, area);
+        if prompt.revealed {
+            render_field(f, &prompt.passphrase, prompt.cursor, block, area);
+        } else {
+            render_field(f, &passphrase::masked(&prompt.passphrase), prompt.cursor, block, area);
+        }
+    }
+    if let Some(message) = &app.error_modal {
+        let area = centered_rect(60, 40, f.area());
+        let modal = Paragraph::new(format!("{}\n\nPress any key to dismiss", message))
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("Error").borders(Borders::ALL).border_style(theme.border().fg(theme.overflows)));
+        f.render_widget(Clear, area);
+        f.render_widget(modal, area);
+    }
+    if app.reuse_modal {
+        let area = centered_rect(60, 40, f.area());
+        let modal = Paragraph::new("This cover already contains hidden data, which the output won't keep; continue?\n\nPress y to encode anyway, any other key to cancel")
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("Cover Already Used").borders(Borders::ALL).border_style(theme.border().fg(theme.overflows)));
+        f.render_widget(Clear, area);
+        f.render_widget(modal, area);
+    }
+    if let Some(path) = &app.overwrite_modal {
+        let area = centered_rect(60, 40, f.area());
+        let modal = Paragraph::new(format!("{} already exists and will be overwritten; continue?\n\nPress y to use it anyway, any other key to keep the current output", shown(path)))
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("Overwrite File").borders(Borders::ALL).border_style(theme.border().fg(theme.overflows)));
+        f.render_widget(Clear, area);
+        f.render_widget(modal, area);
+    }
+    if let Some(plan) = &app.plan_modal {
+        let area = centered_rect(60, 40, f.area());
+        let modal = Paragraph::new(format!("{}\n\nPress any key to dismiss", plan))
+            .wrap(Wrap { trim: true })
+            .block(Block::default().title("Dry Run").borders(Borders::ALL).border_style(theme.border()));
+        f.render_widget(Clear, area);
+        f.render_widget(modal, area);
+    }
+}
+
+// A running job's share of the payload done, fed by the library's
+// progress callback through JobEvent::Advanced.
+fn progress_gauge(label: &str, (done, total): (u64, u64), theme: &Theme) -> Gauge<'static> {
+    let ratio = if total == 0 { 1.0 } else { done as f64 / total as f64 };
+    Gauge::default()
+        .ratio(ratio.min(1.0))
+        .label(format!("{}: {} / {}", label, human_size(done as usize), human_size(total as usize)))
+        .gauge_style(Style::default().fg(theme.fits))
+}
+
+// While the path is being edited the field shows the input, scrolled so the
+// cursor stays visible, instead of the committed path.
+fn render_output_path(f: &mut ratatui::Frame, app: &App, output: Option<&Path>, area: Rect, theme: &Theme) {
+    let block = Block::default().borders(Borders::ALL).border_style(theme.border());
+    
+    if let Some(input) = app.output_edit.as_ref().filter(|_| !app.comment_edit) {
+        render_input(f, input, block.title("Output Path (Enter to accept, Esc to cancel)"), area);
+    } else {
+        let (edit, browse) = (app.config.keys.label(Action::EditOutput), app.config.keys.label(Action::BrowseOutput));
+        let output_path_str = output.map(|p| field_path(p, area)).unwrap_or_else(|| format!("Not selected (press {} to type or {} to browse)", edit, browse));
+        let field = Paragraph::new(output_path_str)
+            .block(block.title(format!("Output Path ({} to edit, {} to browse)", edit, browse)));
+        f.render_widget(field, area);
+    }
+}
+
+// A path as it fits on one line of a bordered field in `area`.
+fn field_path(path: &Path, area: Rect) -> String {
+    utils::shorten_path(path, area.width.saturating_sub(2) as usize)
+}
+
+// A path as it's shown in the status bar.
+fn shown(path: &Path) -> String {
+    utils::shorten_path(path, STATUS_PATH_LEN)
+}
+
+// A one-line text field in `block`, scrolled to keep the cursor in view.
+fn render_input(f: &mut ratatui::Frame, input: &Input, block: Block, area: Rect) {
+    render_field(f, input.value(), input.cursor(), block, area);
+}
+
+// A one-line field showing `value`, scrolled like tui_input scrolls so the
+// cursor, `cursor` characters in, stays in view.
+fn render_field(f: &mut ratatui::Frame, value: &str, cursor: usize, block: Block, area: Rect) {
+    let width = area.width.saturating_sub(2) as usize;
+    let at = value.char_indices().nth(cursor).map_or(value.len(), |(i, _)| i);
+    let before = Span::raw(&value[..at]).width();
+    let mut scroll = 0;
+    for (i, c) in value.char_indices() {
+        if scroll >= before.saturating_sub(width) {
+            break;
+        }
+        scroll += Span::raw(&value[i..i + c.len_utf8()]).width();
+    }
+    
+    let field = Paragraph::new(value)
+        .scroll((0, scroll as u16))
+        .block(block);
+    f.render_widget(field, area);
+    f.set_cursor_position(Position::new(area.x + 1 + before.saturating_sub(scroll) as u16, area.y + 1));
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([Constraint::Percentage((100 - percent_y) / 2), Constraint::Percentage(percent_y), Constraint::Percentage((100 - percent_y) / 2)])
+        .split(area);
+    
+    Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints([Constraint::Percentage((100 - percent_x) / 2), Constraint::Percentage(percent_x), Constraint::Percentage((100 - percent_x) / 2)])
+        .split(vertical[1])[1]
 }
 
 fn handle_main_menu_events(app: &mut App, code: KeyCode) {
     match code {
-        KeyCode::Left => app.menu_index = app.menu_index.saturating_sub(1),
-        KeyCode::Right => if app.menu_index < 4 { app.menu_index += 1 },
+        KeyCode::Left => app.menu_index = (app.menu_index + MENU.len() - 1) % MENU.len(),
+        KeyCode::Right => app.menu_index = (app.menu_index + 1) % MENU.len(),
         KeyCode::Enter => {
-            app.curr_screen = match app.menu_index {
-                0 => Screen::Encode,
-                1 => Screen::Decode,
-                2 => Screen::Settings,
-                3 => Screen::Help,
-                4 => Screen::Quit,
-                _ => Screen::MainMenu,
-            };
-            app.status = format!("Entered {}", format!("{:?}", app.curr_screen));
+            app.curr_screen = MENU.get(app.menu_index).map_or(Screen::MainMenu, |&(_, screen)| screen);
+            app.status.set(format!("Entered {:?}", app.curr_screen));
         }
         _ => {},
     }
 } 
 
-fn handle_encode_events(app: &mut App, code: KeyCode) -> io::Result<()> {    
+fn handle_mouse_events(app: &mut App, mouse: MouseEvent, area: Rect) -> io::Result<()> {
+    if app.output_edit.is_some() || app.overwrite_modal.is_some() {
+        return Ok(());
+    }
+    
+    let chunks = app_layout(area);
+    let position = Position::new(mouse.column, mouse.row);
+    
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) if chunks[0].contains(position) => {
+            if let Some(index) = tab_at(chunks[0], mouse.column) {
+                app.menu_index = index;
+                app.file_explorer = None;
+                app.explorer_purpose = None;
+                handle_main_menu_events(app, KeyCode::Enter);
+            }
+        }
+        MouseEventKind::Down(MouseButton::Left) if chunks[1].contains(position) && app.curr_screen == Screen::FileExplorer => {
+            click_explorer_row(app, chunks[1], mouse.row)?;
+        }
+        MouseEventKind::ScrollDown if app.curr_screen == Screen::FileExplorer => {
+            handle_file_explorer_events(app, KeyCode::Down)?;
+        }
+        MouseEventKind::ScrollUp if app.curr_screen == Screen::FileExplorer => {
+            handle_file_explorer_events(app, KeyCode::Up)?;
+        }
+        _ => {}
+    }
+    
+    Ok(())
+}
+
+// Mirrors the Tabs widget layout: a border, then " title " per tab
+// separated by a one-column divider.
+fn tab_at(area: Rect, column: u16) -> Option<usize> {
+    let mut x = area.x + 1;
+    
+    for (index, (title, _)) in MENU.iter().enumerate() {
+        let width = title.len() as u16 + 2;
+        if (column >= x) && (column < x + width) {
+            return Some(index);
+        }
+        x += width + 1;
+    }
+    
+    None
+}
+
+// The explorer list is rendered with a fresh ListState every frame, so its
+// scroll offset is exactly what keeps the selection on the last visible row.
+fn click_explorer_row(app: &mut App, area: Rect, row: u16) -> io::Result<()> {
+    let Some(explorer) = app.file_explorer.as_ref() else {
+        return Ok(());
+    };
+    if (row <= area.y) || (row >= area.bottom().saturating_sub(1)) {
+        return Ok(());
+    }
+    
+    let visible = explorer.files()
+        .iter()
+        .enumerate()
+        .filter(|(_, file)| explorer_shows(app, file))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let Some(selected) = visible.iter().position(|&index| index == explorer.selected_idx()) else {
+        return Ok(());
+    };
+    
+    let height = area.height.saturating_sub(2) as usize;
+    let offset = (selected + 1).saturating_sub(height);
+    let clicked = offset + (row - area.y - 1) as usize;
+    
+    if clicked >= visible.len() {
+        return Ok(());
+    }
+    if clicked == selected {
+        return handle_file_explorer_events(app, KeyCode::Enter);
+    }
+    
+    let code = if clicked > selected { KeyCode::Down } else { KeyCode::Up };
+    for _ in 0..clicked.abs_diff(selected) {
+        handle_file_explorer_events(app, code)?;
+    }
+    
+    Ok(())
+}
+
+fn handle_history_events(app: &mut App, code: KeyCode) {
     match code {
-        KeyCode::Char('i') => {
+        KeyCode::Up => app.history_scroll = app.history_scroll.saturating_sub(1),
+        KeyCode::Down if app.history_scroll + 1 < app.status.entries().len() => {
+            app.history_scroll += 1;
+        }
+        code if matches!(app.config.keys.action(&[Action::Back], code), Some(Action::Back | Action::History)) => {
+            app.curr_screen = app.prev_screen.unwrap_or(Screen::MainMenu);
+        }
+        _ => {}
+    }
+}
+
+fn handle_settings_events(app: &mut App, code: KeyCode) {
+    match app.config.keys.action(&keymap::SETTINGS, code) {
+        Some(Action::MoreBits) => app.adjust_bits(1),
+        Some(Action::FewerBits) => app.adjust_bits(-1),
+        Some(Action::CycleTheme) => app.config.theme = Theme::by_name(&app.config.theme).next().name.to_string(),
+        Some(Action::CycleKdfMemory) => {
+            let next = KDF_MEMORY_MIB.iter().find(|&&m| m * 1024 > app.config.kdf_memory_kib);
+            app.config.kdf_memory_kib = next.unwrap_or(&KDF_MEMORY_MIB[0]) * 1024;
+        }
+        Some(Action::CycleKdfPasses) => app.config.kdf_iterations = (app.config.kdf_iterations % MAX_KDF.iterations) + 1,
+        Some(Action::ToggleReveal) => app.config.reveal_output = !app.config.reveal_output,
+        Some(Action::EditEncodeName) => return start_name_edit(app, Purpose::EncodeOutput),
+        Some(Action::EditDecodeName) => return start_name_edit(app, Purpose::DecodeOutput),
+        Some(Action::Back) => {
+            app.curr_screen = Screen::MainMenu;
+            return;
+        }
+        _ => return,
+    }
+    
+    app.bits = app.config.bits.min(*app.bits_range().end());
+    app.status.set(match app.config.save() {
+        Ok(()) => "Settings saved".to_string(),
+        Err(e) => format!("Error: {}", e),
+    });
+}
+
+fn handle_encode_events(app: &mut App, code: KeyCode) -> io::Result<()> {    
+    match app.config.keys.action(&keymap::ENCODE, code) {
+        Some(Action::SelectImage) => {
             app.prev_screen = Some(Screen::Encode);
             app.curr_screen = Screen::FileExplorer;
             app.explorer_purpose = Some(Purpose::EncodeImage);
-            app.file_explorer = Some(FileExplorer::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
-            app.status = "Navigate and press Enter to select file, Backspace to cancel".to_string();
+            app.explorer_filter = true;
+            app.file_explorer = Some(new_explorer(&app.config, Purpose::EncodeImage)?);
+            app.status.set("Navigate and press Enter to select file, Backspace to cancel".to_string());
         }
-        KeyCode::Char('s') => {
+        Some(Action::SelectSecret) => {
             app.prev_screen = Some(Screen::Encode);
             app.curr_screen = Screen::FileExplorer;
             app.explorer_purpose = Some(Purpose::EncodeSecret);
-            app.file_explorer = Some(FileExplorer::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
-            app.status = "Navigate and press Enter to select file, Backspace to cancel".to_string();
+            app.explorer_filter = false;
+            app.file_explorer = Some(new_explorer(&app.config, Purpose::EncodeSecret)?);
+            app.status.set("Navigate and press Enter to select file, Backspace to cancel".to_string());
         }
-        KeyCode::Char('o') => {
+        Some(Action::EditOutput) => start_output_edit(app, app.encode_output_input.as_deref().map(Path::to_path_buf)),
+        Some(Action::BrowseOutput) => {
             app.prev_screen = Some(Screen::Encode);
             app.curr_screen = Screen::FileExplorer;
             app.explorer_purpose = Some(Purpose::EncodeOutput);
-            app.file_explorer = Some(FileExplorer::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
-            app.status = "Navugate and press Enter to select file, Backspace to cancel".to_string();
+            app.explorer_filter = false;
+            app.file_explorer = Some(new_explorer(&app.config, Purpose::EncodeOutput)?);
+            app.status.set("Navugate and press Enter to select file, Backspace to cancel".to_string());
         }
-        KeyCode::Up => app.encode_bits = (app.encode_bits % 8) + 1,
-        KeyCode::Down => app.encode_bits = if app.encode_bits > 1 { app.encode_bits - 1 } else { 8 },
-        KeyCode::Enter => {
-            if let (Some(image), Some(secret), Some(output)) = (&app.encode_image_input, &app.encode_secret_input, &app.encode_output_input) {
-                let mask = match ByteMask::new(app.encode_bits) {
-                    Ok(m) => m,
-                    Err(e) => {
-                        app.status = format!("Error: {}", e);
-                        return Ok(());
-                    }
-                };
-                if let Err(e) = encode(image.clone(), secret.clone(), output.clone(), mask) {
-                    app.status = format!("Encode failed: {}", e);
-                } else {
-                    app.status = "Encode successful!".to_string();
-                }
+        Some(Action::ToggleVerify) => app.encode_verify = !app.encode_verify,
+        Some(Action::ToggleEncrypt) => app.encode_encrypt = !app.encode_encrypt,
+        Some(Action::EditComment) => {
+            app.output_edit = Some(Input::new(app.encode_comment.clone()));
+            app.comment_edit = true;
+            app.status.set(format!("Type a comment of at most {} bytes, Enter to accept, Esc to cancel", MAX_COMMENT_LEN));
+        }
+        Some(Action::MoreBits) => app.adjust_bits(1),
+        Some(Action::FewerBits) => app.adjust_bits(-1),
+        Some(Action::FitBits) => app.fit_bits(),
+        Some(Action::RevealOutput) => reveal_output(app),
+        Some(Action::Run) => {
+            if let Some((secret_len, capacity)) = encode_fit(app) && secret_len > capacity as u64 {
+                app.status.set(format!("Secret does not fit at {} bits, raise the bit depth or pick a larger cover", app.bits));
+                return Ok(());
+            }
+            if app.encode_image_input.is_none() || app.encode_secret_input.is_none() || app.encode_output_input.is_none() {
+                app.status.set("Please select all paths first".to_string());
+            } else if app.encode_cover_used {
+                app.reuse_modal = true;
             } else {
-                app.status = "Please select all paths first".to_string();
+                run_encode(app);
+            }
+        }
+        Some(Action::Preview) => {
+            if app.encode_image_input.is_none() || app.encode_secret_input.is_none() || app.encode_output_input.is_none() {
+                app.status.set("Please select all paths first".to_string());
+                return Ok(());
             }
+            // The passphrase changes the sealed bytes but not how many there
+            // are, so a preview seals with an empty one instead of asking.
+            spawn_encode(app, app.encode_encrypt.then(Passphrase::default), true);
         }
-        KeyCode::Backspace => app.curr_screen = Screen::MainMenu,
+        Some(Action::Back) => app.curr_screen = Screen::MainMenu,
         _ => {}
     }
     
@@ -344,78 +2071,439 @@ fn handle_encode_events(app: &mut App, code: KeyCode) -> io::Result<()> {
 }
 
 fn handle_decode_events(app: &mut App, code: KeyCode) -> io::Result<()> {
-    match code {
-        KeyCode::Char('i') => {
+    match app.config.keys.action(&keymap::DECODE, code) {
+        Some(Action::SelectImage) => {
             app.prev_screen = Some(Screen::Decode);
             app.curr_screen = Screen::FileExplorer;
             app.explorer_purpose = Some(Purpose::DecodeImage);
-            app.file_explorer = Some(FileExplorer::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
-            app.status = "Navigate and press Enter to select the file, Backspace to cancel".to_string();
+            app.explorer_filter = true;
+            app.file_explorer = Some(new_explorer(&app.config, Purpose::DecodeImage)?);
+            app.status.set("Navigate and press Enter to select the file, Backspace to cancel".to_string());
         }
-        KeyCode::Char('o') => {
+        Some(Action::EditOutput) => start_output_edit(app, app.decode_output_input.clone()),
+        Some(Action::BrowseOutput) => {
             app.prev_screen = Some(Screen::Decode);
             app.curr_screen = Screen::FileExplorer;
             app.explorer_purpose = Some(Purpose::DecodeOutput);
-            app.file_explorer = Some(FileExplorer::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
-            app.status = "Navigate and press Enter to select location (file or dir), Backspace to cancel".to_string();
+            app.explorer_filter = false;
+            app.file_explorer = Some(new_explorer(&app.config, Purpose::DecodeOutput)?);
+            app.status.set("Navigate and press Enter to select location (file or dir), Backspace to cancel".to_string());
         }
-        KeyCode::Up => app.decode_bits = (app.decode_bits % 8) + 1,
-        KeyCode::Down => app.decode_bits = if app.decode_bits > 1 { app.decode_bits - 1 } else { 8 },
-        KeyCode::Enter => {
-            if let (Some(image), Some(output)) = (&app.decode_image_input, &app.decode_output_input) {
-                let mask = match ByteMask::new(app.decode_bits) {
-                    Ok(m) => m,
-                    Err(e) => {
-                        app.status = format!("Error: {}", e);
-                        return Ok(());
-                    }
-                };
-                if let Err(e) = decode(image.clone(), output.clone(), mask) {
-                    app.status = format!("Decode failed: {}", e);
-                } else {
-                    app.status = "Please select all paths first".to_string();
-                }
+        // Decode first and save once the payload has been looked at.
+        Some(Action::Run) if app.decode_payload.is_some() => save_decoded(app),
+        Some(Action::Run | Action::Preview) => {
+            if app.decode_image_input.is_none() {
+                app.status.set("Please select a stego image first".to_string());
+            } else if app.decode_protected {
+                ask_then_run(app, Screen::Decode);
+            } else {
+                spawn_decode(app, None);
             }
         }
-        KeyCode::Backspace => app.curr_screen = Screen::MainMenu,
+        Some(Action::RevealOutput) => reveal_output(app),
+        Some(Action::Back) => app.curr_screen = Screen::MainMenu,
         _ => {}
     }
     
     Ok(())
 }
 
+// The paths are checked and any reuse of the cover confirmed by now.
+fn run_encode(app: &mut App) {
+    if app.encode_encrypt {
+        ask_then_run(app, Screen::Encode);
+    } else {
+        spawn_encode(app, None, false);
+    }
+}
+
+// STEGNOAPP_PASSPHRASE is used as is, as it is by the CLI, otherwise the
+// passphrase is asked for and the job runs once it's entered.
+fn ask_then_run(app: &mut App, screen: Screen) {
+    match passphrase::from_env() {
+        Some(passphrase) if screen == Screen::Encode => spawn_encode(app, Some(passphrase), false),
+        Some(passphrase) => spawn_decode(app, Some(passphrase)),
+        None => {
+            app.passphrase_prompt = Some(PassphrasePrompt::new(screen));
+            app.status.set("Type the passphrase, Enter to accept, Esc to cancel");
+        }
+    }
+}
+
+// The paths are checked before this is called. The passphrase is zeroed
+// when the job drops it, once the encode is done with it.
+// A dry run embeds the same way but only reports what it would write.
+fn spawn_encode(app: &mut App, passphrase: Option<Passphrase>, dry_run: bool) {
+    let (Some(image), Some(secret), Some(output)) = (&app.encode_image_input, &app.encode_secret_input, &app.encode_output_input) else {
+        return;
+    };
+    let mask = match app.mask_at(app.bits) {
+        Ok(m) => m,
+        Err(e) => {
+            app.status.set(format!("Error: {}", e));
+            return;
+        }
+    };
+    let (image, secret, output) = (Arc::clone(image), Arc::clone(secret), Arc::clone(output));
+    let verify = app.encode_verify;
+    let comment = Some(app.encode_comment.clone()).filter(|c| !c.is_empty());
+    let kdf = app.config.kdf_params();
+    app.job = Some(Job::spawn(move |cancel, events| {
+        let progress = Some(Job::progress(&events));
+        let options = EncodeOptions {
+            verify,
+            report: true,
+            comment,
+            passphrase,
+            kdf,
+            dry_run,
+            cancel: Some(cancel),
+            progress,
+            ..EncodeOptions::default()
+        };
+        let _ = events.send(JobEvent::Progress("Encoding... press Esc to cancel"));
+        let encoded = encode(image.to_path_buf(), secret.to_path_buf(), output.to_path_buf(), mask, &options);
+        let _ = events.send(if dry_run { JobEvent::Planned(encoded) } else { JobEvent::Encoded(encoded) });
+    }));
+}
+
+fn spawn_decode(app: &mut App, passphrase: Option<Passphrase>) {
+    let Some(image) = app.decode_image_input.clone() else {
+        return;
+    };
+    app.job = Some(Job::spawn(move |cancel, events| {
+        let _ = events.send(JobEvent::Progress("Decoding... press Esc to cancel"));
+        let decoded = decode_payload(image.to_path_buf(), passphrase.as_deref().map(String::as_str), cancel, Job::progress(&events));
+        let _ = events.send(JobEvent::Decoded(decoded));
+    }));
+}
+
+// The masked entry in front of an encrypting encode or a protected decode.
+// Pastes arrive whole. The passphrase is only held here until it's
+// accepted, and zeroed with the prompt if it's cancelled.
+fn handle_passphrase_events(app: &mut App, evt: Event) {
+    let Some(prompt) = app.passphrase_prompt.as_mut() else {
+        return;
+    };
+    
+    match evt {
+        Event::Key(key) if key.code == KeyCode::Esc => {
+            app.passphrase_prompt = None;
+            app.status.set("Cancelled, nothing was run");
+        }
+        Event::Key(key) if key.code == KeyCode::Tab => prompt.revealed = !prompt.revealed,
+        Event::Key(key) if key.code == KeyCode::Enter => {
+            let Some(prompt) = app.passphrase_prompt.take() else {
+                return;
+            };
+            if prompt.screen == Screen::Encode {
+                spawn_encode(app, Some(prompt.passphrase), false);
+            } else {
+                spawn_decode(app, Some(prompt.passphrase));
+            }
+        }
+        Event::Paste(text) => {
+            let text = Passphrase::new(text);
+            for c in text.chars().filter(|c| !c.is_control()) {
+                prompt.insert(c.encode_utf8(&mut [0; 4]));
+            }
+        }
+        Event::Key(key) => prompt.handle_key(key),
+        _ => {}
+    }
+}
+
+// Bracketed paste hands the text over whole. Line breaks would end a
+// one-line field, so control characters are left out.
+fn paste_into(input: &mut Input, text: &str) {
+    for c in text.chars().filter(|c| !c.is_control()) {
+        input.handle(InputRequest::InsertChar(c));
+    }
+}
+
+// Writes the decoded payload to the output path, asking for one first if
+// none is set yet.
+fn save_decoded(app: &mut App) {
+    let (Some(secret), Some(mask)) = (&app.decode_payload, app.decode_bits) else {
+        return;
+    };
+    let Some(output) = app.decode_output_input.clone() else {
+        start_output_edit(app, None);
+        app.decode_save_pending = true;
+        app.status.set("Type where to save the payload, Enter to save, Esc to cancel");
+        return;
+    };
+    
+    match decoder::save_payload(secret, output, app.decode_archive) {
+        Ok(output) => {
+            let message = format!("Decode successful! {} bytes from {} LSB bits saved to {}", secret.len(), mask, shown(&output));
+            finished_with(app, message, output);
+        }
+        Err(e) => {
+            app.status.set("Save failed");
+            app.error_modal = Some(format!("Save failed: {}", e));
+        }
+    }
+}
+
+// The encode would fail on overlapping paths anyway, this just says so as
+// soon as they're picked.
+fn warn_same_paths(app: &mut App) {
+    let paths = [
+        ("cover", &app.encode_image_input),
+        ("secret", &app.encode_secret_input),
+        ("output", &app.encode_output_input),
+    ];
+    for (i, (a, first)) in paths.iter().enumerate() {
+        for (b, second) in &paths[i + 1..] {
+            if let (Some(first), Some(second)) = (first, second) && utils::same_file(first, second) {
+                app.status.set(format!("The {} and the {} are the same file, pick another", a, b));
+                return;
+            }
+        }
+    }
+}
+
+fn start_output_edit(app: &mut App, current: Option<PathBuf>) {
+    let value = current.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+    app.output_edit = Some(Input::new(value));
+    app.output_edit_from = current;
+    app.status.set("Type the output path, Enter to accept, Esc to cancel");
+}
+
+fn start_name_edit(app: &mut App, purpose: Purpose) {
+    let template = if purpose == Purpose::EncodeOutput {
+        &app.config.encode_output_name
+    } else {
+        &app.config.decode_output_name
+    };
+    app.output_edit = Some(Input::new(template.clone()));
+    app.name_edit = Some(purpose);
+    app.status.set("Type the output name, {name} stands for the cover's or stego image's stem");
+}
+
+// Edits the output path of whichever screen is showing, the encode comment,
+// or on the settings screen an output name template. A path is only accepted once its
+// directory exists and can be written to, so the encode or decode can't fail
+// late on a typo, and see choose_output for one naming an existing file.
+fn handle_output_edit_events(app: &mut App, evt: Event) {
+    let Some(input) = app.output_edit.as_mut() else {
+        return;
+    };
+    
+    match evt {
+        Event::Key(key) if key.code == KeyCode::Esc => {
+            app.output_edit = None;
+            app.name_edit = None;
+            app.decode_save_pending = false;
+            app.status.set(if std::mem::take(&mut app.comment_edit) { "Comment unchanged" } else { "Output path unchanged" });
+        }
+        Event::Key(key) if key.code == KeyCode::Enter && app.comment_edit => {
+            let comment = input.value().trim().to_string();
+            if let Err(e) = Comment::new(&comment) {
+                app.status.set(format!("Error: {}", e));
+                return;
+            }
+            
+            app.status.set(if comment.is_empty() { "Comment removed".to_string() } else { format!("Comment set to {:?}", comment) });
+            app.encode_comment = comment;
+            app.output_edit = None;
+            app.comment_edit = false;
+        }
+        Event::Key(key) if key.code == KeyCode::Enter && app.name_edit.is_some() => {
+            let name = input.value().trim().to_string();
+            if name.is_empty() || name.contains(['/', '\\']) {
+                app.status.set("Please enter a file name, the output directory is chosen separately");
+                return;
+            }
+            
+            if app.name_edit.take() == Some(Purpose::EncodeOutput) {
+                app.config.encode_output_name = name;
+            } else {
+                app.config.decode_output_name = name;
+            }
+            app.output_edit = None;
+            app.status.set(match app.config.save() {
+                Ok(()) => "Settings saved".to_string(),
+                Err(e) => format!("Error: {}", e),
+            });
+        }
+        Event::Key(key) if key.code == KeyCode::Enter => {
+            let typed = input.value().trim();
+            let path = match app.output_edit_from.take() {
+                Some(from) if from.display().to_string() == typed => from,
+                _ => PathBuf::from(typed),
+            };
+            if path.file_name().is_none() {
+                app.status.set("Please enter an output file name");
+                return;
+            }
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty() && !p.is_dir()) {
+                app.status.set(format!("Directory {} does not exist", shown(parent)));
+                return;
+            }
+            if let Err(e) = utils::check_writable(&path) {
+                app.status.set(format!("{}, pick another", e));
+                return;
+            }
+            
+            app.output_edit = None;
+            choose_output(app, path);
+        }
+        _ => {
+            input.handle_event(&evt);
+        }
+    }
+}
+
+// An existing file other than the output already set is only used once
+// the user confirms overwriting it.
+fn choose_output(app: &mut App, path: PathBuf) {
+    let current = if app.curr_screen == Screen::Decode { app.decode_output_input.as_deref() } else { app.encode_output_input.as_deref() };
+    if path.is_file() && current != Some(path.as_path()) {
+        app.status.set(format!("{} already exists", shown(&path)));
+        app.overwrite_modal = Some(path);
+        return;
+    }
+    
+    set_output(app, path);
+}
+
+// Sets the output path of whichever screen is showing, and saves a decoded
+// payload that was waiting on one.
+fn set_output(app: &mut App, path: PathBuf) {
+    app.status.set(format!("Output path set to {}", shown(&path)));
+    if app.curr_screen == Screen::Decode {
+        app.decode_output_input = Some(path);
+    } else {
+        app.encode_output_input = Some(path.into());
+        warn_same_paths(app);
+    }
+    if std::mem::take(&mut app.decode_save_pending) {
+        save_decoded(app);
+    }
+}
+
+// Each kind of file gets its own remembered directory, covers, secrets and
+// outputs tend to live in different places.
+fn explorer_key(purpose: Purpose) -> &'static str {
+    match purpose {
+        Purpose::EncodeImage | Purpose::DecodeImage => "cover",
+        Purpose::EncodeSecret => "secret",
+        Purpose::EncodeOutput | Purpose::DecodeOutput => "output",
+    }
+}
+
+// Opens where the explorer was last left for `purpose`, if that directory
+// is still there.
+fn new_explorer(config: &AppConfig, purpose: Purpose) -> io::Result<FileExplorer> {
+    let mut explorer = FileExplorer::new().map_err(io::Error::other)?;
+    if let Some(dir) = config.explorer_dirs.get(explorer_key(purpose)).filter(|dir| dir.is_dir()) {
+        explorer.set_cwd(dir)?;
+    }
+    
+    Ok(explorer)
+}
+
 fn handle_file_explorer_events(app: &mut App, code: KeyCode) -> io::Result<()> {
+    if app.config.keys.action(&keymap::EXPLORER, code) == Some(Action::ToggleFilter) {
+        app.explorer_filter = !app.explorer_filter;
+        app.status.set(if app.explorer_filter {
+            "Showing image and audio covers only"
+        } else {
+            "Showing all files"
+        });
+    }
+    
+    let filter = app.explorer_filter;
     if let Some(explorer) = app.file_explorer.as_mut() {
+        let cwd = explorer.cwd().to_path_buf();
+        if let (KeyCode::Enter | KeyCode::Backspace, Some(purpose)) = (code, app.explorer_purpose) {
+            app.config.explorer_dirs.insert(explorer_key(purpose).to_string(), cwd);
+        }
+        
         let evt = Event::Key(event::KeyEvent::from(code));
         if let Err(e) = explorer.handle(&evt) {
-            app.status = format!("Error: {}", e);
+            app.status.set(format!("Error: {}", e));
+        }
+        if filter {
+            skip_filtered(explorer, matches!(code, KeyCode::Up | KeyCode::Char('k') | KeyCode::PageUp | KeyCode::End))?;
         }
         
         if code == KeyCode::Enter {
             let selected = explorer.current().path().to_path_buf();
             let is_dir = explorer.current().is_dir();
             if let Some(purpose) = app.explorer_purpose {
-                let path = if is_dir {
-                    match purpose {
-                        Purpose::EncodeOutput => selected.join("stego.png"),
-                        Purpose::DecodeOutput => selected.join("extracted.txt"),
-                        _ => {
-                            app.status = "Please select a file, not a directory".to_string();
-                            return Ok(());
-                        }
+                // A directory picked for an output gets a file name typed
+                // in it, starting from the name template, and a file picked
+                // is only overwritten once confirmed.
+                if matches!(purpose, Purpose::EncodeOutput | Purpose::DecodeOutput) {
+                    let path = if !is_dir {
+                        selected.clone()
+                    } else if purpose == Purpose::EncodeOutput {
+                        selected.join(app.config.encode_output_name(app.encode_image_input.as_deref()))
+                    } else {
+                        selected.join(app.config.decode_output_name(app.decode_image_input.as_deref()))
+                    };
+                    
+                    // A missing or read-only directory would only fail after
+                    // the whole encode or decode.
+                    if let Err(e) = utils::check_writable(&path) {
+                        app.status.set(format!("{}, pick another", e));
+                        return Ok(());
                     }
-                } else {
-                    match purpose {
-                        Purpose::EncodeImage | Purpose::EncodeSecret | Purpose::DecodeImage => selected,
-                        Purpose::EncodeOutput | Purpose::DecodeOutput => selected,
+                    
+                    if let Some(prev) = app.prev_screen {
+                        app.curr_screen = prev;
                     }
+                    app.file_explorer = None;
+                    app.explorer_purpose = None;
+                    if is_dir {
+                        start_output_edit(app, Some(path));
+                        app.status.set(format!("Name the output file in {}, Enter to accept, Esc to cancel", shown(&selected)));
+                    } else {
+                        choose_output(app, path);
+                    }
+                    return Ok(());
+                }
+                if is_dir {
+                    app.status.set("Please select a file, not a directory".to_string());
+                    return Ok(());
+                }
+                let path = selected;
+                
+                // Covers are probed now rather than failing at encode time,
+                // and an unusable one leaves the explorer open to pick again.
+                let info = match purpose {
+                    Purpose::EncodeImage | Purpose::DecodeImage => match CoverInfo::probe(&path) {
+                        Ok(info) => Some(info),
+                        Err(e) => {
+                            app.status.set(format!("{} is not a usable image or audio file: {}", shown(&path), e));
+                            if purpose == Purpose::EncodeImage {
+                                app.encode_image_input = None;
+                                app.encode_image_len = None;
+                                app.encode_image_info = None;
+                            } else {
+                                app.decode_image_input = None;
+                                app.decode_bits = None;
+                                app.decode_payload = None;
+                            }
+                            return Ok(());
+                        }
+                    },
+                    _ => None,
                 };
                 match purpose {
-                    Purpose::EncodeImage => app.encode_image_input = Some(path),
-                    Purpose::EncodeSecret => app.encode_secret_input = Some(path),
-                    Purpose::EncodeOutput => app.encode_output_input = Some(path),
-                    Purpose::DecodeImage => app.decode_image_input = Some(path),
-                    Purpose::DecodeOutput => app.decode_output_input = Some(path)
+                    Purpose::EncodeImage => select_encode_image(app, path, info),
+                    Purpose::EncodeSecret => {
+                        app.encode_secret_len = std::fs::metadata(&path).ok().map(|m| m.len());
+                        app.encode_secret_input = Some(path.into());
+                    }
+                    Purpose::DecodeImage => select_decode_image(app, path),
+                    Purpose::EncodeOutput | Purpose::DecodeOutput => unreachable!("outputs are chosen above"),
+                }
+                if matches!(purpose, Purpose::EncodeImage | Purpose::EncodeSecret) {
+                    warn_same_paths(app);
                 }
                 if let Some(prev) = app.prev_screen  {
                     app.curr_screen = prev;
@@ -434,3 +2522,122 @@ fn handle_file_explorer_events(app: &mut App, code: KeyCode) -> io::Result<()> {
     
     Ok(())
 }
+
+fn encode_fit(app: &App) -> Option<(u64, usize)> {
+    let mask = app.mask_at(app.bits).ok()?;
+    let capacity = encoder::capacity(app.encode_image_len?, mask, 1);
+    
+    Some((app.encode_secret_len?, capacity))
+}
+
+fn is_cover_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| COVER_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn explorer_shows(app: &App, file: &File) -> bool {
+    !app.explorer_filter || file.is_dir() || is_cover_path(file.path())
+}
+
+fn select_encode_image(app: &mut App, path: PathBuf, info: Option<CoverInfo>) {
+    app.encode_image_len = None;
+    app.encode_image_info = info;
+    app.encode_cover_used = false;
+    app.encode_image_input = Some(path.clone().into());
+    app.adjust_bits(0);
+    spawn_probe(app, Pick::Cover, path);
+}
+
+fn select_decode_image(app: &mut App, path: PathBuf) {
+    app.decode_protected = false;
+    app.decode_archive = false;
+    app.decode_bits = None;
+    app.decode_payload = None;
+    app.decode_image_input = Some(path.clone().into());
+    spawn_probe(app, Pick::Stego, path);
+}
+
+fn spawn_probe(app: &mut App, pick: Pick, path: PathBuf) {
+    app.job = Some(Job::spawn(move |cancel, events| {
+        let _ = events.send(JobEvent::Progress("Reading the file... press Esc to cancel"));
+        let probed = Probe::open(&path, &cancel);
+        let _ = events.send(JobEvent::Probed(pick, path, probed));
+    }));
+}
+
+// Fills in what the probe found on the screen the file was picked for. A
+// file given on the command line opens on the decode screen when it holds
+// hidden data and on the encode one otherwise. A probe that failed or was
+// cancelled leaves the file unpicked.
+fn finish_probe(app: &mut App, pick: Pick, path: PathBuf, probed: Result<Probe, Error>) {
+    let probe = match probed {
+        Ok(probe) => probe,
+        Err(e) => {
+            match e {
+                Error::Cancelled => app.status.set(format!("Cancelled, {} was not picked", shown(&path))),
+                e => app.status.set(format!("{} is not a usable image or audio file: {}", shown(&path), e)),
+            }
+            match pick {
+                Pick::Cover => {
+                    app.encode_image_input = None;
+                    app.encode_image_info = None;
+                }
+                Pick::Stego => app.decode_image_input = None,
+                Pick::Either(_) => {}
+            }
+            return;
+        }
+    };
+    
+    let cover = match pick {
+        Pick::Cover => true,
+        Pick::Stego => false,
+        Pick::Either(info) => {
+            let (screen, picked) = if probe.used {
+                (Screen::Decode, "picked for decoding")
+            } else {
+                app.encode_image_info = Some(info);
+                (Screen::Encode, "picked as the cover")
+            };
+            app.status.set(format!("{} {}", shown(&path), picked));
+            app.curr_screen = screen;
+            app.menu_index = MENU.iter().position(|&(_, s)| s == screen).unwrap_or_default();
+            !probe.used
+        }
+    };
+    if cover {
+        app.encode_image_len = Some(probe.carrier_len);
+        app.encode_cover_used = probe.used;
+        if probe.used {
+            app.status.set(format!("{} already contains hidden data, encoding over it loses that", shown(&path)));
+        }
+        app.encode_image_input = Some(path.into());
+        app.adjust_bits(0);
+    } else {
+        if let Err(e) = &probe.header {
+            app.status.set(format!("{}: {}", shown(&path), e));
+        }
+        let header = probe.header.ok();
+        app.decode_protected = header.is_some_and(|h| h.protection.is_some());
+        app.decode_archive = header.is_some_and(|h| h.archive);
+        app.decode_bits = header.and_then(|h| ChannelByteMask::new(h.bits).ok());
+        app.decode_image_input = Some(path.into());
+    }
+}
+
+fn skip_filtered(explorer: &mut FileExplorer, backwards: bool) -> io::Result<()> {
+    let code = if backwards { KeyCode::Up } else { KeyCode::Down };
+    let step = Event::Key(event::KeyEvent::from(code));
+    
+    for _ in 0..explorer.files().len() {
+        let current = explorer.current();
+        if current.is_dir() || is_cover_path(current.path()) {
+            break;
+        }
+        explorer.handle(&step)?;
+    }
+    
+    Ok(())
+}
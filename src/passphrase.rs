@@ -0,0 +1,119 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use zeroize::Zeroizing;
+
+pub const ENV_VAR: &str = "STEGNOAPP_PASSPHRASE";
+
+const BULLET: char = '•';
+
+// Room for a typed passphrase before its buffer first has to grow.
+const TYPED_CAPACITY: usize = 64;
+
+// Passphrases are held as Zeroizing strings from wherever they're read
+// until the job using them is dropped, which zeroes their whole buffer.
+pub type Passphrase = Zeroizing<String>;
+
+// For structopt, a --passphrase given on the command line.
+pub fn from_arg(arg: &str) -> Passphrase {
+    Zeroizing::new(arg.to_string())
+}
+
+// Set but empty counts as unset, like `STEGNOAPP_PASSPHRASE= stegnoapp ...`.
+pub fn from_env() -> Option<Passphrase> {
+    env::var(ENV_VAR).ok().map(Zeroizing::new).filter(|p| !p.is_empty())
+}
+
+// The file holds the passphrase alone. The newline editors end it with
+// isn't part of it, and is cut off in place.
+pub fn from_file(path: &Path) -> io::Result<Passphrase> {
+    let mut text = Zeroizing::new(fs::read_to_string(path)?);
+    let len = text.strip_suffix('\n').map_or(text.len(), |t| t.strip_suffix('\r').unwrap_or(t).len());
+    text.truncate(len);
+    
+    Ok(text)
+}
+
+// Inserts `text` at byte `at`. A String that outgrows its buffer leaves
+// the old one behind unzeroed, so the copy is made here into a Zeroizing
+// buffer twice the size and the old one is dropped, and zeroed, after it.
+pub fn insert(passphrase: &mut Passphrase, at: usize, text: &str) {
+    let needed = passphrase.len() + text.len();
+    if needed > passphrase.capacity() {
+        let mut grown = Zeroizing::new(String::with_capacity(needed.max(TYPED_CAPACITY) * 2));
+        grown.push_str(passphrase);
+        *passphrase = grown;
+    }
+    passphrase.insert_str(at, text);
+}
+
+// --passphrase or --passphrase-file, which can't both be given, then the
+// environment. Prompting is up to the caller, it's only worth it when the
+// passphrase is known to be needed.
+pub fn resolve(given: Option<Passphrase>, file: Option<&Path>) -> io::Result<Option<Passphrase>> {
+    match (given, file) {
+        (Some(passphrase), _) => Ok(Some(passphrase)),
+        (None, Some(path)) => from_file(path).map(Some),
+        (None, None) => Ok(from_env()),
+    }
+}
+
+// Asks on stderr and reads the reply from the terminal, echoing a bullet
+// per character. None when stdin isn't a terminal to ask on, and an
+// Interrupted error on Esc or Ctrl-C.
+pub fn prompt(message: &str) -> io::Result<Option<Passphrase>> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "{}: ", message)?;
+    stderr.flush()?;
+    enable_raw_mode()?;
+    let read = read_masked(&mut stderr);
+    disable_raw_mode()?;
+    writeln!(stderr)?;
+    
+    read.map(Some)
+}
+
+fn read_masked(echo: &mut impl Write) -> io::Result<Passphrase> {
+    let mut passphrase = Zeroizing::new(String::with_capacity(TYPED_CAPACITY));
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        
+        match key.code {
+            KeyCode::Enter => return Ok(passphrase),
+            KeyCode::Esc => return Err(io::ErrorKind::Interrupted.into()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            KeyCode::Backspace => {
+                if passphrase.pop().is_some() {
+                    write!(echo, "\u{8} \u{8}")?;
+                }
+            }
+            KeyCode::Char(c) => {
+                let end = passphrase.len();
+                insert(&mut passphrase, end, c.encode_utf8(&mut [0; 4]));
+                write!(echo, "{}", BULLET)?;
+            }
+            _ => continue,
+        }
+        echo.flush()?;
+    }
+}
+
+// What a passphrase looks like on screen, one bullet per character.
+pub fn masked(passphrase: &str) -> String {
+    passphrase.chars().map(|_| BULLET).collect()
+}
@@ -0,0 +1,42 @@
+use std::str;
+
+pub const PREVIEW_LEN: usize = 256;
+
+const ROW_LEN: usize = 16;
+
+// The bytes as text when they read as UTF-8, otherwise as a hex dump with an
+// ASCII column.
+pub fn render(bytes: &[u8]) -> String {
+    match as_text(bytes) {
+        Some(text) => text.to_string(),
+        None => hex_dump(bytes),
+    }
+}
+
+// A character cut in half by the end of the preview still counts as text,
+// control characters other than whitespace don't.
+fn as_text(bytes: &[u8]) -> Option<&str> {
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => str::from_utf8(&bytes[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    
+    text.chars().all(|c| !c.is_control() || c.is_whitespace()).then_some(text)
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(ROW_LEN)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex = chunk.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || (b == b' ') { b as char } else { '.' })
+                .collect::<String>();
+            format!("{:04x}  {:<width$}  |{}|", row * ROW_LEN, hex, ascii, width = ROW_LEN * 3 - 1)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,42 @@
+use std::io::{self, IsTerminal};
+use std::sync::Arc;
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use stegnoapp::utils::Progress;
+
+use crate::logger;
+
+// A one-line bar on stderr for CLI encodes and decodes, with the bytes done
+// and an ETA. None when stderr isn't a terminal, so batch jobs and pipes
+// don't fill their logs with redraws, and under --quiet.
+fn stderr_bar(label: &'static str) -> Option<ProgressBar> {
+    if !io::stderr().is_terminal() || logger::quiet() {
+        return None;
+    }
+    
+    let style = ProgressStyle::with_template("{msg} [{bar:30}] {binary_bytes} / {binary_total_bytes}, ETA {eta}")
+        .expect("the template is valid")
+        .progress_chars("#-");
+    let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+        .with_style(style)
+        .with_message(label);
+    Some(bar)
+}
+
+// Runs `work` with a bar's callback when one can be shown, and clears the
+// bar after it however it went, so later messages start on a clean line.
+pub fn with_bar<T>(label: &'static str, work: impl FnOnce(Option<Progress>) -> T) -> T {
+    let bar = stderr_bar(label);
+    let callback = bar.clone().map(|bar| -> Progress {
+        Arc::new(move |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        })
+    });
+    let result = work(callback);
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    
+    result
+}
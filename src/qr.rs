@@ -0,0 +1,58 @@
+use qrcode::types::Color;
+use qrcode::{EcLevel, QrCode};
+
+use crate::errors::Error;
+
+// The reader wants a light margin around the code and more than one pixel
+// per module, so the matrix is redrawn with both before decoding.
+const QUIET_ZONE: usize = 4;
+const SCALE: usize = 4;
+
+/// Renders `secret` as a QR code at the highest error correction level and
+/// packs its modules for embedding: the side length, then one bit per
+/// module row by row, set for dark. Fails with [`Error::QrTooLarge`] if the
+/// secret doesn't fit in a QR code.
+pub fn encode(secret: &[u8]) -> Result<Vec<u8>, Error> {
+    let code = QrCode::with_error_correction_level(secret, EcLevel::H).map_err(|_| Error::QrTooLarge)?;
+    let width = code.width();
+    
+    let mut packed = vec![0; 1 + (width * width).div_ceil(8)];
+    packed[0] = width as u8;
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            packed[1 + i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    
+    Ok(packed)
+}
+
+/// Reads back a secret packed by [`encode`], correcting damaged modules as
+/// far as the code allows. Fails with [`Error::InvalidQr`] past that or
+/// when the bytes aren't a packed QR code.
+pub fn decode(packed: &[u8]) -> Result<Vec<u8>, Error> {
+    let (&width, modules) = packed.split_first().ok_or(Error::InvalidQr)?;
+    let width = width as usize;
+    if (width == 0) || (modules.len() * 8 < width * width) {
+        return Err(Error::InvalidQr);
+    }
+    
+    let dark = |x: usize, y: usize| {
+        let i = y * width + x;
+        (modules[i / 8] & (0x80 >> (i % 8))) != 0
+    };
+    let code = QUIET_ZONE..QUIET_ZONE + width;
+    let side = (width + 2 * QUIET_ZONE) * SCALE;
+    let mut image = rqrr::PreparedImage::prepare_from_greyscale(side, side, |x, y| {
+        let (x, y) = (x / SCALE, y / SCALE);
+        let inside = code.contains(&x) && code.contains(&y);
+        if inside && dark(x - QUIET_ZONE, y - QUIET_ZONE) { 0 } else { 255 }
+    });
+    
+    let grids = image.detect_grids();
+    let grid = grids.first().ok_or(Error::InvalidQr)?;
+    let mut secret = Vec::new();
+    grid.decode_to(&mut secret).map_err(|_| Error::InvalidQr)?;
+    
+    Ok(secret)
+}
@@ -0,0 +1,25 @@
+use std::env;
+use std::io;
+use std::path::Path;
+
+// Over SSH, or on a Unix desktop-less session, a file manager would open on
+// some other screen or not at all.
+fn headless() -> bool {
+    let ssh = env::var_os("SSH_CONNECTION").is_some() || env::var_os("SSH_TTY").is_some();
+    let display = env::var_os("DISPLAY").is_some() || env::var_os("WAYLAND_DISPLAY").is_some();
+    
+    ssh || (cfg!(all(unix, not(target_os = "macos"))) && !display)
+}
+
+// Shows `path` selected in the OS file manager. Where the file manager
+// can't select it, opener falls back to opening the folder holding it.
+// Ok(false) when there's no desktop to show it on, which isn't worth an
+// error.
+pub fn reveal(path: &Path) -> io::Result<bool> {
+    if headless() {
+        return Ok(false);
+    }
+    
+    opener::reveal(path.canonicalize()?).map_err(io::Error::other)?;
+    Ok(true)
+}
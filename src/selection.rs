@@ -0,0 +1,112 @@
+use crate::header::Region;
+use crate::utils::ChannelByteMask;
+
+/// Pixels either side of the one being scored.
+pub const RADIUS: usize = 2;
+
+const WINDOW: u64 = 2 * RADIUS as u64 + 1;
+
+// Sum of a pixel's channels with the payload bits masked off, which is all
+// embedding leaves alone and so all the decoder can rely on.
+fn level(body: &[u8], mask: ChannelByteMask, pixel: usize) -> u64 {
+    body[pixel * 3..]
+        .iter()
+        .take(3)
+        .enumerate()
+        .map(|(c, &b)| (b & !mask.channel(c).mask) as u64)
+        .sum()
+}
+
+/// How busy the neighbourhood of `pixel` is: the variance of the levels of
+/// the pixels around it, times the window size squared to stay in integers.
+/// Flat areas score 0.
+pub fn texture(body: &[u8], mask: ChannelByteMask, pixel: usize) -> u32 {
+    let last = body.len().div_ceil(3) - 1;
+    let (sum, squares) = (0..WINDOW as usize)
+        .map(|k| level(body, mask, (pixel + k).saturating_sub(RADIUS).min(last)))
+        .fold((0, 0), |(sum, squares), v| (sum + v, squares + v * v));
+    
+    (WINDOW * squares - sum * sum) as u32
+}
+
+// The body pixels inside `region`, if any, then every `stride`th of those.
+// The body starts `origin` pixels into the image, right after its header.
+fn candidates(body_len: usize, origin: usize, stride: usize, region: Option<Region>) -> impl Iterator<Item = usize> {
+    (0..body_len.div_ceil(3))
+        .filter(move |&p| region.is_none_or(|r| r.contains(origin + p)))
+        .step_by(stride)
+}
+
+/// How many pixels of a body starting `origin` pixels into the image are
+/// inside `region`, or all of them without one.
+pub fn region_pixels(body_len: usize, origin: usize, region: Option<Region>) -> usize {
+    candidates(body_len, origin, 1, region).count()
+}
+
+/// The highest texture threshold that still selects `needed` of the pixels
+/// picked by `stride` and `region`, so the payload lands in the busiest ones.
+pub fn threshold(
+    body: &[u8],
+    origin: usize,
+    mask: ChannelByteMask,
+    stride: usize,
+    region: Option<Region>,
+    needed: usize
+) -> u32 {
+    let mut scores: Vec<u32> = candidates(body.len(), origin, stride, region)
+        .map(|p| texture(body, mask, p))
+        .collect();
+    
+    match needed {
+        0 => u32::MAX,
+        n if n > scores.len() => 0,
+        n => *scores.select_nth_unstable_by(n - 1, |a, b| b.cmp(a)).1,
+    }
+}
+
+/// Indices of the carrier bytes holding the payload, in order: those of
+/// every `stride`th pixel in `region`, or the whole image without one, and
+/// of those only the pixels at least as busy as `threshold` when it's set.
+pub fn carrier_bytes(
+    body: &[u8],
+    origin: usize,
+    mask: ChannelByteMask,
+    stride: usize,
+    region: Option<Region>,
+    threshold: Option<u32>
+) -> impl Iterator<Item = usize> + '_ {
+    candidates(body.len(), origin, stride, region)
+        .filter(move |&p| threshold.is_none_or(|t| texture(body, mask, p) >= t))
+        .flat_map(|p| p * 3..(p * 3 + 3).min(body.len()))
+}
+
+/// Where the `slot`th payload byte goes among `len` carrier bytes taken
+/// three channels to a pixel, when the payload fills `run` pixels' red
+/// channels, then their green, then their blue, before the next `run`
+/// pixels. The last group is whatever pixels are left and the bytes of a
+/// trailing partial pixel keep their place. A `run` of 1 is plain buffer
+/// order. The encoder and decoder both go through this, so every run maps
+/// the same way on both sides.
+pub fn interleaved(slot: usize, len: usize, run: usize) -> usize {
+    let pixels = len / 3;
+    if slot >= pixels * 3 {
+        return slot;
+    }
+    
+    let full = pixels / run * run;
+    let (first, run, slot) = if slot < full * 3 {
+        (0, run, slot)
+    } else {
+        (full, pixels - full, slot - full * 3)
+    };
+    let (group, within) = (slot / (3 * run), slot % (3 * run));
+    
+    (first + group * run + within % run) * 3 + within / run
+}
+
+/// `chosen` in the order [`interleaved`] fills it for `run`.
+pub fn interleave(chosen: &[usize], run: usize) -> Vec<usize> {
+    (0..chosen.len())
+        .map(|slot| chosen[interleaved(slot, chosen.len(), run)])
+        .collect()
+}
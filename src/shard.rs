@@ -0,0 +1,66 @@
+use crate::errors::Error;
+
+// The marker lets an ordinary stego payload be told apart from a shard.
+const SHARD_MARKER: u8 = b'#';
+
+pub const SHARD_HEADER_LEN: usize = 9;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShardHeader {
+    pub group: [u8; 4],
+    pub index: u16,
+    pub total: u16,
+}
+
+impl ShardHeader {
+    pub fn to_bytes(&self) -> [u8; SHARD_HEADER_LEN] {
+        let mut bytes = [0; SHARD_HEADER_LEN];
+        bytes[0] = SHARD_MARKER;
+        bytes[1..5].copy_from_slice(&self.group);
+        bytes[5..7].copy_from_slice(&self.index.to_be_bytes());
+        bytes[7..9].copy_from_slice(&self.total.to_be_bytes());
+        
+        bytes
+    }
+    
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if (bytes.len() < SHARD_HEADER_LEN) || (bytes[0] != SHARD_MARKER) {
+            return Err(Error::InvalidShard);
+        }
+        
+        let header = ShardHeader {
+            group: [bytes[1], bytes[2], bytes[3], bytes[4]],
+            index: u16::from_be_bytes([bytes[5], bytes[6]]),
+            total: u16::from_be_bytes([bytes[7], bytes[8]]),
+        };
+        
+        if header.index >= header.total {
+            Err(Error::InvalidShard)
+        } else {
+            Ok(header)
+        }
+    }
+}
+
+pub fn join_shards(mut shards: Vec<(ShardHeader, Vec<u8>)>) -> Result<Vec<u8>, Error> {
+    let Some((first, _)) = shards.first() else {
+        return Err(Error::InvalidShard);
+    };
+    let (group, total) = (first.group, first.total);
+    
+    if shards.iter().any(|(h, _)| h.group != group || h.total != total) {
+        return Err(Error::ShardGroupMismatch);
+    }
+    
+    shards.sort_by_key(|(h, _)| h.index);
+    
+    let in_order = shards
+        .iter()
+        .enumerate()
+        .all(|(i, (h, _))| h.index as usize == i);
+    if !in_order || (shards.len() != total as usize) {
+        return Err(Error::InvalidShard);
+    }
+    
+    Ok(shards.into_iter().flat_map(|(_, data)| data).collect())
+}
@@ -0,0 +1,73 @@
+//! LSB masking a whole payload byte at a time, for the common case of a
+//! uniform depth that divides a byte. Every payload byte then fills a fixed
+//! run of carrier bytes, so the loops work on fixed-width arrays the
+//! compiler vectorizes instead of tracking a bit offset per carrier byte.
+//! Only built with the `simd` feature, and stable Rust, no `std::simd`.
+
+use crate::utils::ChannelByteMask;
+
+/// The depth of `mask` when the whole-byte loops can take it: 1, 2, 4 or 8
+/// bits in every channel.
+pub fn whole_bytes(mask: ChannelByteMask) -> Option<u8> {
+    match mask.bits() {
+        [bits, g, b] if (bits == g) && (g == b) && matches!(bits, 1 | 2 | 4 | 8) => Some(bits),
+        _ => None,
+    }
+}
+
+/// Same result as [`crate::encoder::embed_sequential`] at a uniform depth
+/// of `bits`, which has to be one [`whole_bytes`] accepts.
+pub fn embed(image: &mut [u8], secret: &[u8], bits: u8) {
+    match bits {
+        1 => embed_at::<1, 8>(image, secret),
+        2 => embed_at::<2, 4>(image, secret),
+        4 => embed_at::<4, 2>(image, secret),
+        8 => embed_at::<8, 1>(image, secret),
+        _ => unreachable!("not a whole-byte depth: {}", bits),
+    }
+}
+
+/// The payload bytes `payload`'s carrier bytes hold at a uniform depth of
+/// `bits`. A payload byte only partly there at the end is left out.
+pub fn extract(payload: &[u8], bits: u8) -> Vec<u8> {
+    match bits {
+        1 => extract_at::<1, 8>(payload),
+        2 => extract_at::<2, 4>(payload),
+        4 => extract_at::<4, 2>(payload),
+        8 => extract_at::<8, 1>(payload),
+        _ => unreachable!("not a whole-byte depth: {}", bits),
+    }
+}
+
+// LANES is 8 / BITS, the carrier bytes per payload byte. A run cut short
+// at the end, and carrier bytes past the end of the secret, go through
+// one byte at a time, the latter getting zeros as read_bits gives them.
+fn embed_at<const BITS: u32, const LANES: usize>(image: &mut [u8], secret: &[u8]) {
+    let mask = ((1u16 << BITS) - 1) as u8;
+    let whole = secret.len().min(image.len() / LANES);
+    let (head, rest) = image.split_at_mut(whole * LANES);
+    for (carrier, &s) in head.chunks_exact_mut(LANES).zip(secret) {
+        let carrier: &mut [u8; LANES] = carrier.try_into().expect("chunks are LANES long");
+        for (j, p) in carrier.iter_mut().enumerate() {
+            let shift = 8 - BITS * (j as u32 + 1);
+            *p = (*p & !mask) | ((s >> shift) & mask);
+        }
+    }
+    
+    for (i, p) in rest.iter_mut().enumerate() {
+        let s = secret.get(whole + i / LANES).copied().unwrap_or(0);
+        let shift = 8 - BITS * ((i % LANES) as u32 + 1);
+        *p = (*p & !mask) | ((s >> shift) & mask);
+    }
+}
+
+fn extract_at<const BITS: u32, const LANES: usize>(payload: &[u8]) -> Vec<u8> {
+    let mask = ((1u16 << BITS) - 1) as u8;
+    payload
+        .chunks_exact(LANES)
+        .map(|carrier| {
+            let carrier: &[u8; LANES] = carrier.try_into().expect("chunks are LANES long");
+            carrier.iter().fold(0u16, |acc, &b| (acc << BITS) | (b & mask) as u16) as u8
+        })
+        .collect()
+}
@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HISTORY_LEN: usize = 200;
+
+pub struct StatusEntry {
+    pub time: String,
+    pub message: String,
+    // Mirrored from the log, kept in the history but never shown as the
+    // current status.
+    pub logged: bool,
+}
+
+pub struct StatusLog {
+    entries: VecDeque<StatusEntry>,
+}
+
+impl StatusLog {
+    pub fn new(message: &str) -> Self {
+        let mut log = StatusLog { entries: VecDeque::with_capacity(HISTORY_LEN) };
+        log.set(message);
+        
+        log
+    }
+    
+    pub fn set(&mut self, message: impl Into<String>) {
+        self.push(message.into(), false);
+    }
+    
+    pub fn log(&mut self, message: impl Into<String>) {
+        self.push(message.into(), true);
+    }
+    
+    fn push(&mut self, message: String, logged: bool) {
+        if self.entries.len() == HISTORY_LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(StatusEntry { time: timestamp(), message, logged });
+    }
+    
+    pub fn current(&self) -> &str {
+        self.entries.iter().rev().find(|e| !e.logged).map(|e| e.message.as_str()).unwrap_or_default()
+    }
+    
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &StatusEntry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+}
+
+// UTC wall-clock time; good enough to order events without a date crate.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    
+    format!("{:02}:{:02}:{:02}", secs / 3600 % 24, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,67 @@
+use ratatui::style::{Color, Style};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    pub highlight: Color,
+    pub status_bg: Color,
+    pub status_fg: Color,
+    pub border: Color,
+    pub fits: Color,
+    pub overflows: Color,
+}
+
+pub const DARK: Theme = Theme {
+    name: "dark",
+    highlight: Color::Yellow,
+    status_bg: Color::Blue,
+    status_fg: Color::White,
+    border: Color::Reset,
+    fits: Color::Green,
+    overflows: Color::Red,
+};
+
+pub const LIGHT: Theme = Theme {
+    name: "light",
+    highlight: Color::Blue,
+    status_bg: Color::Gray,
+    status_fg: Color::Black,
+    border: Color::DarkGray,
+    fits: Color::Green,
+    overflows: Color::Red,
+};
+
+pub const HIGH_CONTRAST: Theme = Theme {
+    name: "high-contrast",
+    highlight: Color::LightYellow,
+    status_bg: Color::Black,
+    status_fg: Color::White,
+    border: Color::White,
+    fits: Color::LightGreen,
+    overflows: Color::LightRed,
+};
+
+pub const THEMES: [Theme; 3] = [DARK, LIGHT, HIGH_CONTRAST];
+
+impl Theme {
+    pub fn by_name(name: &str) -> Theme {
+        THEMES.iter().find(|t| t.name == name).copied().unwrap_or(DARK)
+    }
+    
+    pub fn next(&self) -> Theme {
+        let index = THEMES.iter().position(|t| t == self).unwrap_or(0);
+        THEMES[(index + 1) % THEMES.len()]
+    }
+    
+    pub fn border(&self) -> Style {
+        Style::default().fg(self.border)
+    }
+    
+    pub fn highlight(&self) -> Style {
+        Style::default().fg(self.highlight)
+    }
+    
+    pub fn status(&self) -> Style {
+        Style::default().bg(self.status_bg).fg(self.status_fg)
+    }
+}
@@ -1,74 +1,336 @@
-use crate::errors::Error;
-
-#[derive(Clone, Copy)]
-pub struct ByteMask {
-    pub bits: u8,
-    pub mask: u8,
-    pub chunks: u8,
-    padded: bool,
-    byte: u8,
-    step: u8, 
-}
-
-impl ByteMask {
-    pub fn new(bits: u8) -> Result<Self, Error> {
-        if (bits == 0) || (bits > 8) {
-            Err(Error::InvalidNumberOfBits)
-        } else {
-            let mask = (u16::pow(2, bits as u32) - 1) as u8;
-            let chunks = f32::ceil(8f32 / bits as f32) as u8;
-            let padded = 8 < (chunks * bits);
-            
-            Ok(ByteMask { 
-                bits, 
-                mask, 
-                chunks, 
-                padded, 
-                byte: 0, 
-                step: 0 
-            })
-        }
-    }
-    
-    pub fn set_byte(&mut self, byte: u8) -> Self {
-        self.byte = byte;
-        self.step = 0;
-        
-        *self 
-    }
-    
-    pub fn join_chunks<'a, T>(self, chunks: &'a T) -> u8
-    where
-        &'a T: IntoIterator<Item = &'a u8>,
-    {
-        let mut byte = 0;
-        let mut shift = 8_u8;
-        
-        for chunk in chunks {
-            shift = shift.saturating_sub(self.bits);
-            byte |= chunk << shift;
-        }
-        
-        byte 
-    }
-}
-
-impl Iterator for ByteMask {
-    type Item = u8;
-    
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.step >= self.chunks {
-            return None;
-        }
-        
-        self.step += 1;
-        
-        if self.padded && (self.step == self.chunks) {
-            let shift = self.bits * self.step - 8;
-            Some(self.byte & (self.mask >> shift))
-        } else {
-            let shift = 8 - self.bits * self.step;
-            Some((self.byte >> shift) & self.mask)
-        }
-    }
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use sha2::{Digest, Sha256};
+
+use crate::errors::Error;
+
+const CHANNEL_NAMES: [&str; 3] = ["R", "G", "B"];
+
+/// Called as an encode or decode works through the payload, with how many
+/// of its bytes are done and how many there are in all. See
+/// [`crate::Encoder::set_progress`] and [`crate::Decoder::set_progress`].
+pub type Progress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Payload bytes an encode reads from the secret, or a decode extracts, in
+/// one batch, see [`crate::Encoder::set_buffer_len`].
+pub const DEFAULT_BUFFER_LEN: usize = 256 * 1024;
+
+/// Carrier bytes a batch of `buffer_len` payload bytes takes at `mask`,
+/// rounded up to whole groups of 8 pixels. Those always carry whole payload
+/// bytes, so no batch splits one with the next.
+pub fn batch_len(buffer_len: usize, mask: ChannelByteMask) -> usize {
+    mask.carrier_len(buffer_len.max(1) * 8).next_multiple_of(3 * 8)
+}
+
+/// How long each stage of an encode or decode took, see
+/// [`crate::encoder::EncodeOutcome`]. Only measured while debug logging is
+/// on, otherwise the clock is never read.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Timings {
+    /// Opening and decoding the cover or stego file.
+    pub load: Duration,
+    /// Embedding or extracting the payload, sealing or opening it included.
+    pub lsb: Duration,
+    /// Writing the output file.
+    pub save: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.load + self.lsb + self.save
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "load {:.1?}, LSBs {:.1?}, save {:.1?}", self.load, self.lsb, self.save)
+    }
+}
+
+/// Starts timing a stage for [`Timings`], or None when debug logging is off.
+pub fn stopwatch() -> Option<Instant> {
+    log::log_enabled!(log::Level::Debug).then(Instant::now)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ByteMask {
+    pub bits: u8,
+    pub mask: u8,
+}
+
+impl ByteMask {
+    pub fn new(bits: u8) -> Result<Self, Error> {
+        if (bits == 0) || (bits > 8) {
+            Err(Error::InvalidNumberOfBits)
+        } else {
+            let mask = (u16::pow(2, bits as u32) - 1) as u8;
+            
+            Ok(ByteMask { bits, mask })
+        }
+    }
+    
+    /// Carrier bytes ("chunks") one payload byte takes at this depth,
+    /// counting the one it only partly fills. 0 for a skipped channel,
+    /// which never carries any.
+    pub fn chunks_per_byte(&self) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        8usize.div_ceil(self.bits as usize)
+    }
+}
+
+/// Per-channel LSB depths. Carrier bytes cycle through the channels in
+/// R, G, B order, so the payload is a plain bit stream spread over them at
+/// each channel's depth. A depth of 0 skips the channel, which is left as
+/// it is, but at least one has to carry the payload. The depths are stored
+/// in the header, so decoding always uses the ones the encode did.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelByteMask {
+    pub channels: [ByteMask; 3],
+}
+
+impl ChannelByteMask {
+    pub fn new(bits: [u8; 3]) -> Result<Self, Error> {
+        if bits == [0; 3] {
+            return Err(Error::InvalidNumberOfBits);
+        }
+        let channel = |bits| if bits == 0 { Ok(ByteMask { bits: 0, mask: 0 }) } else { ByteMask::new(bits) };
+        
+        Ok(ChannelByteMask {
+            channels: [channel(bits[0])?, channel(bits[1])?, channel(bits[2])?]
+        })
+    }
+    
+    /// Keeps only the channels named in `channels`, like "rgb", "rb" or
+    /// "g", skipping the others. Fails with [`Error::InvalidChannels`]
+    /// for an empty set or a letter that isn't r, g or b or is repeated.
+    pub fn with_channels(self, channels: &str) -> Result<Self, Error> {
+        let mut keep = [false; 3];
+        for c in channels.trim().chars() {
+            let index = CHANNEL_NAMES
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(c.encode_utf8(&mut [0; 4])))
+                .ok_or(Error::InvalidChannels)?;
+            if std::mem::replace(&mut keep[index], true) {
+                return Err(Error::InvalidChannels);
+            }
+        }
+        if keep == [false; 3] {
+            return Err(Error::InvalidChannels);
+        }
+        
+        let bits = self.bits();
+        ChannelByteMask::new([0, 1, 2].map(|i| if keep[i] { bits[i] } else { 0 }))
+    }
+    
+    pub fn uniform(bits: u8) -> Result<Self, Error> {
+        ChannelByteMask::new([bits; 3])
+    }
+    
+    pub fn bits(&self) -> [u8; 3] {
+        self.channels.map(|c| c.bits)
+    }
+    
+    pub fn channel(&self, index: usize) -> ByteMask {
+        self.channels[index % 3]
+    }
+    
+    pub fn group_bits(&self) -> usize {
+        self.channels.iter().map(|c| c.bits as usize).sum()
+    }
+    
+    // Position in the payload bit stream of the first bit carried by the
+    // carrier byte at `index`, which is also the number of bits before it.
+    pub fn bit_offset(&self, index: usize) -> usize {
+        let preceding: usize = self.channels[..index % 3]
+            .iter()
+            .map(|c| c.bits as usize)
+            .sum();
+        
+        (index / 3) * self.group_bits() + preceding
+    }
+    
+    /// Carrier bytes one payload byte takes, rounded up. That's exact for a
+    /// uniform mask and the average over a pixel for a per-channel one,
+    /// whose bytes straddle channels of different depths.
+    pub fn chunks_per_byte(&self) -> usize {
+        24usize.div_ceil(self.group_bits())
+    }
+    
+    /// Whole payload bytes the first `carrier_len` carrier bytes hold, a
+    /// trailing partial byte not counted. Both [`crate::encoder::capacity`]
+    /// and the decoder's length check size the payload with this.
+    pub fn payload_bytes_for(&self, carrier_len: usize) -> usize {
+        let (groups, rest) = (carrier_len / 3, carrier_len % 3);
+        let partial: usize = self.channels[..rest].iter().map(|c| c.bits as usize).sum();
+        // Whole bytes of eight groups first, so huge carriers can't wrap.
+        (groups / 8) * self.group_bits() + ((groups % 8) * self.group_bits() + partial) / 8
+    }
+    
+    pub fn carrier_len(&self, payload_bits: usize) -> usize {
+        let mut len = (payload_bits / self.group_bits()) * 3;
+        let mut remaining = payload_bits % self.group_bits();
+        
+        for channel in self.channels {
+            if remaining == 0 {
+                break;
+            }
+            remaining = remaining.saturating_sub(channel.bits as usize);
+            len += 1;
+        }
+        
+        len
+    }
+}
+
+impl fmt::Display for ChannelByteMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b] = self.bits();
+        
+        if (r == g) && (g == b) {
+            write!(f, "{}", r)
+        } else {
+            write!(f, "R:{},G:{},B:{}", r, g, b)
+        }
+    }
+}
+
+// Accepts either a single depth for every channel ("2") or a per-channel
+// spec ("R:1,G:3,B:2") naming each channel exactly once.
+impl FromStr for ChannelByteMask {
+    type Err = Error;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(bits) = s.trim().parse::<u8>() {
+            return ChannelByteMask::uniform(bits);
+        }
+        
+        let mut bits = [None; 3];
+        for part in s.split(',') {
+            let (name, value) = part.split_once(':').ok_or(Error::InvalidChannelSpec)?;
+            let index = CHANNEL_NAMES
+                .iter()
+                .position(|c| name.trim().eq_ignore_ascii_case(c))
+                .ok_or(Error::InvalidChannelSpec)?;
+            let value = value.trim().parse().map_err(|_| Error::InvalidChannelSpec)?;
+            
+            if bits[index].replace(value).is_some() {
+                return Err(Error::InvalidChannelSpec);
+            }
+        }
+        
+        match bits {
+            [Some(r), Some(g), Some(b)] => ChannelByteMask::new([r, g, b]),
+            _ => Err(Error::InvalidChannelSpec),
+        }
+    }
+}
+
+// Reads `bits` bits of `bytes` starting at bit `offset`, MSB first, with
+// anything past the end read as zero.
+pub fn read_bits(bytes: &[u8], offset: usize, bits: u8) -> u8 {
+    if bits == 0 {
+        return 0;
+    }
+    let at = |i: usize| bytes.get(i).copied().unwrap_or(0) as u16;
+    let window = (at(offset / 8) << 8) | at(offset / 8 + 1);
+    let shift = 16 - (offset % 8) as u16 - bits as u16;
+    
+    ((window >> shift) & ((1 << bits) - 1)) as u8
+}
+
+/// Whether `a` and `b` name the same file once `.`, `..` and symlinks are
+/// resolved. A path that doesn't exist yet, like an output about to be
+/// written, is resolved through its directory.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    let resolve = |path: &Path| path.canonicalize().ok().or_else(|| {
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        Some(dir.canonicalize().ok()?.join(path.file_name()?))
+    });
+    
+    match (resolve(a), resolve(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// `path` for showing to a user, at most `max` characters long. Whatever
+/// doesn't fit is cut from the front, keeping the file name, behind an
+/// ellipsis. Non-UTF-8 bytes show as U+FFFD, so this is only for display,
+/// never for opening the file again.
+pub fn shorten_path(path: &Path, max: usize) -> String {
+    let full = path.display().to_string();
+    let len = full.chars().count();
+    if len <= max {
+        return full;
+    }
+    
+    let tail: String = full.chars().skip(len + 1 - max.max(1)).collect();
+    format!("…{}", tail)
+}
+
+/// Fails with [`Error::OutputNotWritable`] unless `output`'s directory
+/// exists and a file can be created in it. Permissions alone don't say,
+/// with ACLs, read-only mounts and the like, so a scratch file is created
+/// and removed again.
+pub fn check_writable(output: &Path) -> Result<(), Error> {
+    let dir = output.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let probe = dir.join(format!(".stegnoapp-write-check-{}", process::id()));
+    
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            fs::remove_file(&probe)?;
+            Ok(())
+        }
+        Err(_) => Err(Error::OutputNotWritable(dir.to_path_buf())),
+    }
+}
+
+/// The seed a spread payload's positions are shuffled with, see
+/// [`spread`]. Without a passphrase every encode shares the same one.
+pub fn spread_seed(passphrase: Option<&str>) -> [u8; 32] {
+    Sha256::digest(passphrase.unwrap_or_default()).into()
+}
+
+// ChaCha8 rather than StdRng, whose algorithm may change between rand
+// releases and would scatter old payloads differently.
+pub fn shuffle_positions(positions: &mut [usize], seed: [u8; 32]) {
+    positions.shuffle(&mut ChaCha8Rng::from_seed(seed));
+}
+
+/// Writes every bit of `payload` into the LSB of `factor` consecutive
+/// carrier bytes of `positions`, which should already be shuffled so the
+/// copies land far apart.
+pub fn spread(carrier: &mut [u8], positions: &[usize], payload: &[u8], factor: usize) {
+    for (i, copies) in positions.chunks(factor).take(payload.len() * 8).enumerate() {
+        let bit = read_bits(payload, i, 1);
+        for &p in copies {
+            carrier[p] = (carrier[p] & !1) | bit;
+        }
+    }
+}
+
+/// Reads back `len` bytes written by [`spread`], taking each bit by majority
+/// vote over its copies so a few flipped LSBs don't change it. Ties read as
+/// zero, so odd factors are the safer choice.
+pub fn despread(carrier: &[u8], positions: &[usize], len: usize, factor: usize) -> Vec<u8> {
+    let mut payload = vec![0; len];
+    for (i, copies) in positions.chunks(factor).take(len * 8).enumerate() {
+        let ones = copies.iter().filter(|&&p| (carrier[p] & 1) != 0).count();
+        if ones * 2 > copies.len() {
+            payload[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    
+    payload
 }
\ No newline at end of file
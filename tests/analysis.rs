@@ -0,0 +1,67 @@
+use stegnoapp::header::HEADER_CARRIER_LEN;
+use image::{Rgb, RgbImage};
+use stegnoapp::{ChannelByteMask, Error, Padding, StegoHeader, analysis, encoder};
+
+// A smooth gradient keeps every value even, the opposite of what LSB
+// embedding leaves behind.
+fn gradient(len: usize) -> Vec<u8> {
+    (0..len).map(|i| ((i / 3) % 128 * 2) as u8).collect()
+}
+
+// Deterministic stand-in for random secret bytes.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn embedded_image_scores_higher_than_clean_one() {
+    let clean = gradient(256 * 256 * 3);
+    
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let secret = noise(encoder::capacity(clean.len(), mask, 1));
+    let header = StegoHeader::new(mask.bits(), secret.len() as u32);
+    let mut stego = clean.clone();
+    encoder::embed(&mut stego, &header, &secret, mask, Padding::Random);
+    
+    let clean_score = analysis::chi_square(&clean);
+    let stego_score = analysis::chi_square(&stego[HEADER_CARRIER_LEN..]);
+    
+    assert!(clean_score.overall < 0.1, "clean score {}", clean_score.overall);
+    assert!(stego_score.overall > 0.9, "stego score {}", stego_score.overall);
+    assert!(!clean_score.suspicious());
+    assert!(stego_score.suspicious());
+}
+
+#[test]
+fn psnr_is_infinite_for_identical_buffers() {
+    let cover = gradient(300);
+    assert!(analysis::psnr(&cover, &cover).is_infinite());
+    
+    let mut changed = cover.clone();
+    changed[0] ^= 1;
+    assert!(analysis::psnr(&cover, &changed).is_finite());
+}
+
+#[test]
+fn heatmap_marks_only_changed_channels() {
+    let cover = RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]));
+    let mut stego = cover.clone();
+    stego.put_pixel(1, 2, Rgb([11, 20, 30]));
+    stego.put_pixel(3, 0, Rgb([10, 20, 28]));
+    
+    let heatmap = analysis::heatmap(&cover, &stego).unwrap();
+    assert_eq!(heatmap.get_pixel(1, 2), &Rgb([255, 0, 0]));
+    assert_eq!(heatmap.get_pixel(3, 0), &Rgb([0, 0, 255]));
+    assert_eq!(heatmap.iter().filter(|&&c| c != 0).count(), 2);
+    
+    let smaller = RgbImage::new(4, 3);
+    assert!(matches!(analysis::heatmap(&cover, &smaller), Err(Error::DimensionMismatch)));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, cover};
+
+use common::{TempDir, payload, solid_cover};
+
+const SIZE: u32 = 32;
+
+// Full-size RGBA frames, then a 16x8 one offset into the canvas, each with
+// its own delay, looping twice. With `separate_default` a still image
+// that isn't part of the animation comes first.
+fn write_apng(path: &Path, separate_default: bool) {
+    let mut encoder = png::Encoder::new(File::create(path).unwrap(), SIZE, SIZE);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(3, 2).unwrap();
+    encoder.set_sep_def_img(separate_default).unwrap();
+    let mut writer = encoder.write_header().unwrap();
+    
+    let frame = |w: u32, h: u32, seed: u32| -> Vec<u8> {
+        (0..w * h * 4).map(|i| (i * 7 + seed * 31) as u8).collect()
+    };
+    if separate_default {
+        writer.write_image_data(&frame(SIZE, SIZE, 9)).unwrap();
+    }
+    for (i, delay) in [10, 20].into_iter().enumerate() {
+        writer.set_frame_delay(delay, 100).unwrap();
+        writer.write_image_data(&frame(SIZE, SIZE, i as u32)).unwrap();
+    }
+    writer.set_frame_dimension(16, 8).unwrap();
+    writer.set_frame_position(4, 12).unwrap();
+    writer.set_frame_delay(30, 100).unwrap();
+    writer.set_blend_op(png::BlendOp::Over).unwrap();
+    writer.write_image_data(&frame(16, 8, 2)).unwrap();
+    writer.finish().unwrap();
+}
+
+type Placement = (u32, u32, u32, u32, u16, u16, png::DisposeOp, png::BlendOp);
+
+// The loop count and every frame's size, position, delay and ops, in order.
+fn animation_of(path: &Path) -> (u32, Vec<Placement>) {
+    let mut reader = png::Decoder::new(BufReader::new(File::open(path).unwrap())).read_info().unwrap();
+    let plays = reader.info().animation_control.unwrap().num_plays;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap()];
+    let mut controls = Vec::new();
+    while reader.next_frame(&mut buffer).is_ok() {
+        controls.extend(reader.info().frame_control.map(|c| {
+            (c.width, c.height, c.x_offset, c.y_offset, c.delay_num, c.delay_den, c.dispose_op, c.blend_op)
+        }));
+    }
+    
+    (plays, controls)
+}
+
+#[test]
+fn round_trips_across_apng_frames() {
+    let dir = TempDir::new("apng");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    write_apng(&cover, false);
+    
+    // Too much for the first frame alone at 2 bits.
+    let secret = payload(1000);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    assert!(cover::is_apng(&written));
+    assert_eq!(animation_of(&cover).1.len(), 3);
+    assert_eq!(animation_of(&written), animation_of(&cover));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn keeps_a_separate_default_image() {
+    let dir = TempDir::new("apng-default");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    write_apng(&cover, true);
+    
+    let secret = payload(2000);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    assert_eq!(animation_of(&written), animation_of(&cover));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn apng_capacity_sums_the_frames() {
+    let dir = TempDir::new("apng-capacity");
+    let animated = dir.path().join("animated.png");
+    let still = dir.path().join("still.png");
+    write_apng(&animated, true);
+    solid_cover(&still, SIZE, SIZE);
+    
+    // Three bytes per pixel, alpha left out, over every frame.
+    let expected = ((3 * SIZE * SIZE + 16 * 8) * 3) as usize;
+    assert_eq!(cover::carrier_len(&animated).unwrap(), expected);
+    assert_eq!(Cover::open(&animated).unwrap().bytes().len(), expected);
+    assert!(!cover::is_apng(&still));
+}
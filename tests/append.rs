@@ -0,0 +1,92 @@
+mod common;
+
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+use stegnoapp::crypto::KdfParams;
+use stegnoapp::{ChannelByteMask, Decoder, Encoder, Error, decoder, encoder};
+
+use common::{TempDir, payload, solid_cover};
+
+const CHEAP_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1 };
+
+fn append(stego: &Path, secret: &[u8], passphrase: Option<&str>) -> Result<(), Error> {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(stego.to_path_buf(), secret.to_vec(), mask)?;
+    encoder.set_append(true)?;
+    if let Some(passphrase) = passphrase {
+        encoder.set_passphrase(passphrase);
+        encoder.set_kdf_params(CHEAP_KDF);
+    }
+    encoder.set_verify(true);
+    encoder.save(stego.to_path_buf())?;
+    Ok(())
+}
+
+#[test]
+fn appended_messages_read_back_independently() {
+    let dir = TempDir::new("append");
+    let cover = dir.path().join("cover.png");
+    let stego = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let (first, second, third) = (payload(300), b"for bob".to_vec(), b"for carol".to_vec());
+    Encoder::from_bytes(cover, first.clone(), mask).unwrap().save(stego.clone()).unwrap();
+    append(&stego, &second, Some("bob")).unwrap();
+    append(&stego, &third, Some("carol")).unwrap();
+    
+    let mut decoder = Decoder::new(stego.clone()).unwrap();
+    assert_eq!(decoder.headers().len(), 3);
+    assert_eq!(decoder.read_to_vec().unwrap(), first);
+    decoder.set_passphrase("carol");
+    decoder.select(2).unwrap();
+    assert_eq!(decoder.read_to_vec().unwrap(), third);
+    
+    let all = decoder.read_all().unwrap();
+    assert_eq!(all, vec![(0, first), (2, third)]);
+    assert!(matches!(decoder.select(3), Err(Error::MessageNotFound(3))));
+    assert_eq!(decoder::read_headers(&stego).unwrap().len(), 3);
+}
+
+#[test]
+fn appends_after_strided_region_and_adaptive_payloads() {
+    let dir = TempDir::new("append-selection");
+    let cover = dir.path().join("cover.png");
+    let stego = dir.path().join("stego.png");
+    RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 67 + y * 31) as u8, (x ^ y) as u8, (y * 13) as u8])).save(&cover).unwrap();
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let first = payload(100);
+    let mut encoder = Encoder::from_bytes(cover, first.clone(), mask).unwrap();
+    encoder.set_stride(2).unwrap();
+    encoder.set_region(0, 4, 64, 20).unwrap();
+    encoder.set_adaptive(true);
+    encoder.save(stego.clone()).unwrap();
+    
+    let second = payload(200);
+    let mut encoder = Encoder::from_bytes(stego.clone(), second.clone(), mask).unwrap();
+    encoder.set_append(true).unwrap();
+    encoder.set_adaptive(true);
+    encoder.save(stego.clone()).unwrap();
+    
+    let mut decoder = Decoder::new(stego).unwrap();
+    assert_eq!(decoder.read_to_vec().unwrap(), first);
+    decoder.select(1).unwrap();
+    assert_eq!(decoder.read_to_vec().unwrap(), second);
+}
+
+#[test]
+fn rejects_appending_without_room_or_a_message() {
+    let dir = TempDir::new("append-room");
+    let cover = dir.path().join("cover.png");
+    let stego = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    assert!(matches!(append(&cover, b"nothing to follow", None), Err(Error::NotAStegoImage)));
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let first = payload(encoder::capacity(32 * 32 * 3, mask, 1) - 40);
+    Encoder::from_bytes(cover, first, mask).unwrap().save(stego.clone()).unwrap();
+    assert!(matches!(append(&stego, &payload(100), None), Err(Error::SecretTooLarge)));
+}
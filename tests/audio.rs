@@ -0,0 +1,82 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use stegnoapp::shard::{self, SHARD_HEADER_LEN, ShardHeader};
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, cover};
+
+use common::{TempDir, payload};
+
+const SPEC: WavSpec = WavSpec {
+    channels: 2,
+    sample_rate: 8000,
+    bits_per_sample: 16,
+    sample_format: SampleFormat::Int,
+};
+
+// A stereo 16-bit PCM sweep, `len` samples across both channels.
+fn write_wav(path: &Path, len: usize) {
+    let mut writer = WavWriter::create(path, SPEC).unwrap();
+    for i in 0..len {
+        writer.write_sample((i as i32 * 613 - 20_000) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn samples_of(path: &Path) -> (WavSpec, Vec<i16>) {
+    let mut reader = WavReader::open(path).unwrap();
+    (reader.spec(), reader.samples::<i16>().map(Result::unwrap).collect())
+}
+
+#[test]
+fn round_trips_through_a_wav_cover() {
+    let dir = TempDir::new("wav");
+    let cover = dir.path().join("cover.wav");
+    let secret = dir.path().join("secret.bin");
+    write_wav(&cover, 16_000);
+    fs::write(&secret, payload(1500)).unwrap();
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let written = Encoder::new(cover.clone(), secret, mask).unwrap().save(dir.path().join("stego.wav")).unwrap();
+    
+    // Only the low byte of each sample may change.
+    let (spec, before) = samples_of(&cover);
+    let (written_spec, after) = samples_of(&written);
+    assert_eq!(written_spec, spec);
+    assert_eq!(after.len(), before.len());
+    assert!(before.iter().zip(&after).all(|(a, b)| (a ^ b) as u16 & 0xFF00 == 0));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), payload(1500));
+}
+
+#[test]
+fn wav_shards_keep_their_extension_and_rejoin() {
+    let dir = TempDir::new("wav-shards");
+    let covers = [dir.path().join("a.wav"), dir.path().join("b.wav")];
+    for cover in &covers {
+        write_wav(cover, 8_000);
+    }
+    
+    let secret = payload(2500);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let group = [1, 2, 3, 4];
+    let mut written = Vec::new();
+    for (index, (cover, data)) in covers.iter().zip(secret.chunks(1250)).enumerate() {
+        let mut payload = ShardHeader { group, index: index as u16, total: 2 }.to_bytes().to_vec();
+        payload.extend_from_slice(data);
+        let output = dir.path().join(format!("shard{}.png", index));
+        let mut encoder = Encoder::from_cover(Cover::open(cover).unwrap(), payload, mask).unwrap();
+        written.push(encoder.save(output).unwrap());
+    }
+    
+    let mut shards = Vec::new();
+    for path in written {
+        assert!(cover::is_wav(&path), "{} should be a .wav", path.display());
+        assert_eq!(samples_of(&path).0, SPEC);
+        let mut payload = Decoder::new(path).unwrap().read_to_vec().unwrap();
+        let header = ShardHeader::from_bytes(&payload).unwrap();
+        shards.push((header, payload.split_off(SHARD_HEADER_LEN)));
+    }
+    assert_eq!(shard::join_shards(shards).unwrap(), secret);
+}
@@ -0,0 +1,126 @@
+use proptest::prelude::*;
+
+use stegnoapp::utils::{ByteMask, read_bits};
+use stegnoapp::{ChannelByteMask, Padding, StegoHeader, decoder, encoder};
+#[cfg(feature = "simd")]
+use stegnoapp::simd;
+
+fn masks() -> impl Strategy<Value = ChannelByteMask> {
+    [1u8..=8, 1u8..=8, 1u8..=8].prop_map(|bits| ChannelByteMask::new(bits).unwrap())
+}
+
+#[test]
+fn pins_chunks_and_capacity_for_every_depth() {
+    // Carrier bytes per payload byte, and payload bytes per 24 carrier bytes.
+    let expected = [(1, 8, 3), (2, 4, 6), (3, 3, 9), (4, 2, 12), (5, 2, 15), (6, 2, 18), (7, 2, 21), (8, 1, 24)];
+    for (bits, chunks, bytes) in expected {
+        let mask = ChannelByteMask::uniform(bits).unwrap();
+        assert_eq!(ByteMask::new(bits).unwrap().chunks_per_byte(), chunks, "bits {}", bits);
+        assert_eq!(mask.chunks_per_byte(), chunks, "bits {}", bits);
+        assert_eq!(mask.payload_bytes_for(24), bytes, "bits {}", bits);
+        assert_eq!(mask.payload_bytes_for(mask.carrier_len(bytes * 8)), bytes, "bits {}", bits);
+    }
+    
+    let mixed: ChannelByteMask = "R:1,G:3,B:2".parse().unwrap();
+    assert_eq!(mixed.chunks_per_byte(), 4);
+    assert_eq!([1, 2, 3, 4].map(|len| mixed.payload_bytes_for(len)), [0, 0, 0, 0]);
+    assert_eq!(mixed.payload_bytes_for(6), 1);
+    assert_eq!(ChannelByteMask::uniform(8).unwrap().payload_bytes_for(usize::MAX), usize::MAX);
+}
+
+#[test]
+fn every_byte_round_trips_at_every_depth() {
+    let all: Vec<u8> = (0..=255).collect();
+    for bits in 1..=8 {
+        let mask = ChannelByteMask::uniform(bits).unwrap();
+        for byte in 0..=255u8 {
+            let mut image = vec![0xA5; 4096];
+            encoder::embed(&mut image, &StegoHeader::new(mask.bits(), 1), &[byte], mask, Padding::Random);
+            assert_eq!(decoder::extract(&image).unwrap(), [byte], "bits {}, byte {}", bits, byte);
+        }
+        
+        let mut image = vec![0x5A; 8192];
+        encoder::embed(&mut image, &StegoHeader::new(mask.bits(), 256), &all, mask, Padding::Random);
+        assert_eq!(decoder::extract(&image).unwrap(), all, "bits {}", bits);
+    }
+}
+
+proptest! {
+    #[test]
+    fn payload_bytes_for_matches_the_bit_stream(mask in masks(), carrier_len in 0usize..4096) {
+        prop_assert_eq!(mask.payload_bytes_for(carrier_len), mask.bit_offset(carrier_len) / 8);
+    }
+    
+    #[test]
+    fn every_byte_survives_embed_and_extract(mask in masks(), byte in any::<u8>()) {
+        let mut image = vec![0; 4096];
+        encoder::embed(&mut image, &StegoHeader::new(mask.bits(), 1), &[byte], mask, Padding::Random);
+        prop_assert_eq!(decoder::extract(&image).unwrap(), vec![byte]);
+    }
+    
+    #[test]
+    fn embedding_writes_each_chunk_into_its_channel_bits(
+        mask in masks(),
+        bytes in prop::collection::vec(any::<u8>(), 0..64),
+        fill in any::<u8>()
+    ) {
+        let mut image = vec![fill; mask.carrier_len(bytes.len() * 8)];
+        encoder::embed_sequential(&mut image, &bytes, mask);
+        for (i, &carrier) in image.iter().enumerate() {
+            let channel = mask.channel(i);
+            prop_assert_eq!(carrier & !channel.mask, fill & !channel.mask);
+            prop_assert_eq!(carrier & channel.mask, read_bits(&bytes, mask.bit_offset(i), channel.bits));
+        }
+    }
+    
+    #[test]
+    fn carrier_len_is_minimal(mask in masks(), payload_bits in 1usize..4096) {
+        let len = mask.carrier_len(payload_bits);
+        prop_assert!(mask.bit_offset(len) >= payload_bits);
+        prop_assert!(mask.bit_offset(len - 1) < payload_bits);
+    }
+    
+    #[test]
+    fn embed_then_extract_round_trips(
+        mask in masks(),
+        secret in prop::collection::vec(any::<u8>(), 0..256),
+        fill in any::<u8>()
+    ) {
+        let mut image = vec![fill; 4096];
+        let header = StegoHeader::new(mask.bits(), secret.len() as u32);
+        encoder::embed(&mut image, &header, &secret, mask, Padding::Random);
+        
+        prop_assert_eq!(decoder::extract(&image).unwrap(), secret);
+    }
+}
+
+#[cfg(feature = "simd")]
+proptest! {
+    #[test]
+    fn whole_byte_loops_match_the_scalar_ones(
+        bits in prop::sample::select(vec![1u8, 2, 4, 8]),
+        secret in prop::collection::vec(any::<u8>(), 0..256),
+        image in prop::collection::vec(any::<u8>(), 0..2048)
+    ) {
+        let mask = ChannelByteMask::uniform(bits).unwrap();
+        prop_assert_eq!(simd::whole_bytes(mask), Some(bits));
+        let mut scalar = image.clone();
+        encoder::embed_sequential(&mut scalar, &secret, mask);
+        let mut fast = image;
+        simd::embed(&mut fast, &secret, bits);
+        prop_assert_eq!(&fast, &scalar);
+        
+        // Carrier bytes past the secret hold zeros, as read_bits gives them.
+        let whole = fast.len() * bits as usize / 8;
+        let expected: Vec<u8> = secret.iter().copied().chain(std::iter::repeat(0)).take(whole).collect();
+        prop_assert_eq!(simd::extract(&fast, bits), expected);
+    }
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn leaves_other_depths_to_the_scalar_loops() {
+    for bits in [[3, 3, 3], [2, 2, 1], [8, 8, 0]] {
+        assert_eq!(simd::whole_bytes(ChannelByteMask::new(bits).unwrap()), None, "bits {:?}", bits);
+    }
+}
@@ -0,0 +1,38 @@
+// Each test crate compiles its own copy and uses only some of the helpers.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage};
+
+// A scratch directory unique to one test, removed again when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("stegnoapp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        
+        TempDir(path)
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn solid_cover(path: &Path, width: u32, height: u32) {
+    RgbImage::from_pixel(width, height, Rgb([120, 200, 40])).save(path).unwrap();
+}
+
+pub fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 37 + 11) as u8).collect()
+}
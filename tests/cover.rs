@@ -0,0 +1,81 @@
+mod common;
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use image::ColorType;
+use stegnoapp::cover::CoverInfo;
+use stegnoapp::{ChannelByteMask, Decoder, Encoder, Error};
+
+use common::{TempDir, payload, solid_cover};
+
+fn palette_png(path: &Path, width: u32, height: u32) {
+    let mut encoder = png::Encoder::new(File::create(path).unwrap(), width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(vec![0, 0, 0, 255, 255, 255, 120, 200, 40]);
+    let indices: Vec<u8> = (0..width * height).map(|i| (i % 3) as u8).collect();
+    encoder.write_header().unwrap().write_image_data(&indices).unwrap();
+}
+
+#[test]
+fn probes_image_dimensions_and_size() {
+    let dir = TempDir::new("probe");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 800, 600);
+    
+    let info = CoverInfo::probe(&cover).unwrap();
+    assert_eq!(info.byte_len(), 800 * 600 * 3);
+    assert_eq!(info.to_string(), "800x600, RGB8, 1,440,000 bytes");
+}
+
+#[test]
+fn probing_a_non_image_fails() {
+    let dir = TempDir::new("probe-text");
+    let path = dir.path().join("notes.png");
+    fs::write(&path, "not an image").unwrap();
+    
+    assert!(CoverInfo::probe(&path).is_err());
+}
+
+#[test]
+fn probing_a_palettized_bmp_fails() {
+    let dir = TempDir::new("probe-bmp");
+    let path = dir.path().join("indexed.bmp");
+    let mut header = vec![0; 54];
+    header[..2].copy_from_slice(b"BM");
+    header[28] = 8;
+    fs::write(&path, header).unwrap();
+    
+    assert!(matches!(CoverInfo::probe(&path), Err(Error::IndexedImageUnsupported)));
+}
+
+#[test]
+fn refuses_palette_pngs_unless_asked_to_convert() {
+    let dir = TempDir::new("palette-png");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    palette_png(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    assert!(matches!(CoverInfo::probe(&cover), Err(Error::IndexedImageUnsupported)));
+    assert!(matches!(Encoder::from_bytes(cover.clone(), payload(50), mask), Err(Error::IndexedImageUnsupported)));
+    
+    assert!(matches!(Encoder::new_truecolor(cover.clone(), cover.clone(), mask), Err(Error::SamePath)));
+    
+    let secret = dir.path().join("secret.bin");
+    fs::write(&secret, payload(50)).unwrap();
+    let written = Encoder::new_truecolor(cover.clone(), secret, mask).unwrap().save(output).unwrap();
+    assert_eq!(image::open(&written).unwrap().color(), ColorType::Rgb8);
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), payload(50));
+    
+    // A directory is archived the same way it is without the conversion,
+    // tar's blocks need a larger cover.
+    let cover = dir.path().join("large.png");
+    palette_png(&cover, 128, 128);
+    let folder = dir.path().join("folder");
+    fs::create_dir(&folder).unwrap();
+    fs::write(folder.join("note.txt"), b"hi").unwrap();
+    let archived = Encoder::new_truecolor(cover, folder, mask).unwrap().save(dir.path().join("archived.png")).unwrap();
+    assert!(Decoder::new(archived).unwrap().headers()[0].archive);
+}
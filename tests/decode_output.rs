@@ -0,0 +1,151 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use stegnoapp::decoder::infer_extension;
+use stegnoapp::utils::shorten_path;
+use stegnoapp::{ChannelByteMask, Decoder, Encoder, Error};
+
+use common::{TempDir, payload, solid_cover};
+
+#[test]
+fn infers_extensions_from_magic_numbers() {
+    let mut tar = vec![0; 512];
+    tar[257..262].copy_from_slice(b"ustar");
+    
+    let cases: [(&[u8], &str); 7] = [
+        (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "png"),
+        (b"\xff\xd8\xff\xe0\0\x10JFIF", "jpg"),
+        (b"%PDF-1.7\n%\xe2\xe3\xcf\xd3", "pdf"),
+        (b"RIFF\x24\0\0\0WAVEfmt ", "wav"),
+        (&tar, "tar"),
+        (b"plain old text\n", "txt"),
+        (b"\0\x01\x02\x03", "bin"),
+    ];
+    for (bytes, extension) in cases {
+        assert_eq!(infer_extension(bytes), extension);
+    }
+}
+
+#[test]
+fn save_adds_an_extension_only_when_missing() {
+    let dir = TempDir::new("infer-extension");
+    let cover = dir.path().join("cover.png");
+    let stego = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mut secret = b"%PDF-1.4\n".to_vec();
+    secret.extend(payload(100));
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    Encoder::from_bytes(cover, secret.clone(), mask).unwrap().save(stego.clone()).unwrap();
+    
+    let decoder = Decoder::new(stego).unwrap();
+    let inferred = decoder.save(dir.path().join("extracted")).unwrap();
+    assert_eq!(inferred, dir.path().join("extracted.pdf"));
+    assert_eq!(std::fs::read(&inferred).unwrap(), secret);
+    
+    let kept = decoder.save(dir.path().join("extracted.dat")).unwrap();
+    assert_eq!(kept, dir.path().join("extracted.dat"));
+}
+
+#[test]
+fn encoder_defaults_to_png_and_rejects_unknown_extensions() {
+    let dir = TempDir::new("output-extension");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(50), mask).unwrap();
+    
+    let written = encoder.save(dir.path().join("stego")).unwrap();
+    assert_eq!(written, dir.path().join("stego.png"));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), payload(50));
+    
+    encoder.set_preserve_format(false);
+    let unknown = encoder.save(dir.path().join("stego.xyz"));
+    assert!(matches!(unknown, Err(Error::UnknownOutputFormat(ext)) if ext == "xyz"));
+    assert!(!dir.path().join("stego.xyz").exists());
+}
+
+#[test]
+fn encoder_keeps_the_cover_format_unless_told_otherwise() {
+    let dir = TempDir::new("preserve-format");
+    let bmp = dir.path().join("cover.bmp");
+    let jpg = dir.path().join("cover.jpg");
+    solid_cover(&bmp, 32, 32);
+    solid_cover(&jpg, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(bmp, payload(50), mask).unwrap();
+    assert_eq!(encoder.save(dir.path().join("stego.png")).unwrap(), dir.path().join("stego.bmp"));
+    encoder.set_preserve_format(false);
+    assert_eq!(encoder.save(dir.path().join("stego.png")).unwrap(), dir.path().join("stego.png"));
+    
+    let mut encoder = Encoder::from_bytes(jpg, payload(50), mask).unwrap();
+    assert!(matches!(encoder.save(dir.path().join("stego.png")), Err(Error::LossyOutputFormat(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn keeps_paths_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    
+    let dir = TempDir::new("non-utf8");
+    let cover = dir.path().join(OsStr::from_bytes(b"cover-\xff.png"));
+    let stego = dir.path().join(OsStr::from_bytes(b"stego-\xfe.png"));
+    let extracted = dir.path().join(OsStr::from_bytes(b"secret-\xfd"));
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = payload(100);
+    let written = Encoder::from_bytes(cover, secret.clone(), mask).unwrap().save(stego.clone()).unwrap();
+    assert_eq!(written, stego);
+    
+    let saved = Decoder::new(stego).unwrap().save(extracted.clone()).unwrap();
+    assert_eq!(saved, extracted.with_extension("bin"));
+    assert_eq!(std::fs::read(saved).unwrap(), secret);
+}
+
+#[test]
+fn shortens_long_paths_only_for_display() {
+    let path = Path::new("/a/very/long/directory/name/that/goes/on/secret.txt");
+    assert_eq!(shorten_path(path, 100), path.display().to_string());
+    
+    let short = shorten_path(path, 20);
+    assert_eq!(short.chars().count(), 20);
+    assert!(short.starts_with('…') && short.ends_with("/secret.txt"));
+}
+
+#[test]
+fn packs_a_directory_and_unpacks_it_on_decode() {
+    let dir = TempDir::new("archive");
+    let cover = dir.path().join("cover.png");
+    let stego = dir.path().join("stego.png");
+    let secret = dir.path().join("secret");
+    let output = dir.path().join("unpacked");
+    solid_cover(&cover, 128, 128);
+    fs::create_dir_all(secret.join("nested/deeper")).unwrap();
+    fs::write(secret.join("notes.txt"), b"top level").unwrap();
+    fs::write(secret.join("nested/deeper/data.bin"), payload(700)).unwrap();
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    Encoder::new(cover.clone(), secret.clone(), mask).unwrap().save(stego.clone()).unwrap();
+    let decoder = Decoder::new(stego).unwrap();
+    assert!(decoder.headers()[0].archive);
+    assert_eq!(decoder.save(output.clone()).unwrap(), output);
+    assert_eq!(fs::read(output.join("notes.txt")).unwrap(), b"top level");
+    assert_eq!(fs::read(output.join("nested/deeper/data.bin")).unwrap(), payload(700));
+    
+    // A plain file is still written as a file, not unpacked.
+    let plain = dir.path().join("plain.png");
+    Encoder::new(cover.clone(), secret.join("notes.txt"), mask).unwrap().save(plain.clone()).unwrap();
+    let decoder = Decoder::new(plain).unwrap();
+    assert!(!decoder.headers()[0].archive);
+    assert_eq!(fs::read(decoder.save(dir.path().join("notes.txt")).unwrap()).unwrap(), b"top level");
+    
+    let small = dir.path().join("small.png");
+    solid_cover(&small, 32, 32);
+    assert!(matches!(Encoder::new(small, secret, mask), Err(Error::SecretTooLarge)));
+}
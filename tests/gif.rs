@@ -0,0 +1,156 @@
+mod common;
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::Path;
+
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, cover};
+
+use common::{TempDir, payload};
+
+const SIZE: u16 = 64;
+
+// A 256-color ramp as the global palette, then full-size frames and a
+// 16x8 one offset into the canvas, each with its own delay, looping twice.
+fn write_gif(path: &Path, frames: usize) {
+    let palette: Vec<u8> = (0..=255u8).flat_map(|i| [i, 255 - i, i / 2]).collect();
+    let mut encoder = gif::Encoder::new(File::create(path).unwrap(), SIZE, SIZE, &palette).unwrap();
+    encoder.set_repeat(gif::Repeat::Finite(2)).unwrap();
+    
+    for i in 0..frames {
+        let (width, height) = if i == 2 { (16, 8) } else { (SIZE, SIZE) };
+        let indices: Vec<u8> = (0..width as u32 * height as u32).map(|p| (p * 7 + i as u32 * 31) as u8).collect();
+        let frame = gif::Frame {
+            width,
+            height,
+            left: if i == 2 { 4 } else { 0 },
+            top: if i == 2 { 12 } else { 0 },
+            delay: 10 * (i as u16 + 1),
+            buffer: Cow::Owned(indices),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame).unwrap();
+    }
+}
+
+// Three colors, the second of them transparent in every frame, with each
+// frame cycling through all three.
+fn write_small_palette_gif(path: &Path) {
+    let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+    let mut encoder = gif::Encoder::new(File::create(path).unwrap(), SIZE, SIZE, &palette).unwrap();
+    for i in 0..2 {
+        let frame = gif::Frame {
+            width: SIZE,
+            height: SIZE,
+            transparent: Some(1),
+            buffer: Cow::Owned((0..SIZE as u32 * SIZE as u32).map(|p| ((p / 3 + i) % 3) as u8).collect()),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame).unwrap();
+    }
+}
+
+// Every frame as the RGBA a viewer would show.
+fn rendered(path: &Path) -> Vec<Vec<u8>> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push(frame.buffer.to_vec());
+    }
+    
+    frames
+}
+
+type Placement = (u16, u16, u16, u16, u16);
+
+// The global palette, the loop count and every frame's size, position and
+// delay, in order.
+fn animation_of(path: &Path) -> (Vec<u8>, gif::Repeat, Vec<Placement>) {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+    let palette = decoder.global_palette().unwrap().to_vec();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push((frame.width, frame.height, frame.left, frame.top, frame.delay));
+    }
+    
+    (palette, decoder.repeat(), frames)
+}
+
+#[test]
+fn round_trips_across_gif_frames() {
+    let dir = TempDir::new("gif");
+    let cover = dir.path().join("cover.gif");
+    let output = dir.path().join("stego.gif");
+    write_gif(&cover, 3);
+    
+    // Too much for the first frame alone.
+    let secret = payload(700);
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    assert!(cover::is_gif(&written));
+    assert_eq!(animation_of(&cover).2.len(), 3);
+    assert_eq!(animation_of(&written), animation_of(&cover));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn round_trips_a_single_frame_gif() {
+    let dir = TempDir::new("gif-single");
+    let cover = dir.path().join("cover.gif");
+    let output = dir.path().join("stego.gif");
+    write_gif(&cover, 1);
+    
+    let secret = payload(200);
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    assert_eq!(animation_of(&written), animation_of(&cover));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn gif_capacity_sums_the_frames() {
+    let dir = TempDir::new("gif-capacity");
+    let cover = dir.path().join("cover.gif");
+    write_gif(&cover, 3);
+    
+    // One palette index per pixel, over every frame.
+    let expected = 2 * SIZE as usize * SIZE as usize + 16 * 8;
+    assert_eq!(cover::carrier_len(&cover).unwrap(), expected);
+    assert_eq!(Cover::open(&cover).unwrap().bytes().len(), expected);
+}
+
+#[test]
+fn small_palettes_and_transparency_look_the_same_after_embedding() {
+    let dir = TempDir::new("gif-palette");
+    let cover = dir.path().join("cover.gif");
+    let output = dir.path().join("stego.gif");
+    write_small_palette_gif(&cover);
+    
+    // Transparent pixels carry nothing, the others a bit each.
+    let opaque = 2 * (SIZE as usize * SIZE as usize) * 2 / 3;
+    assert!(cover::carrier_len(&cover).unwrap().abs_diff(opaque) <= 2);
+    
+    let secret = payload(500);
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    assert_eq!(rendered(&written), rendered(&cover));
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn gifs_take_only_one_bit() {
+    let dir = TempDir::new("gif-depth");
+    let cover = dir.path().join("cover.gif");
+    write_gif(&cover, 1);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let refused = Encoder::from_bytes(cover, payload(10), mask);
+    assert!(matches!(refused, Err(Error::GifBitDepth)));
+}
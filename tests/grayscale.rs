@@ -0,0 +1,71 @@
+mod common;
+
+use std::path::Path;
+
+use image::{ColorType, GrayImage, ImageBuffer, Luma};
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, cover, decoder};
+
+use common::{TempDir, payload};
+
+fn gray_cover(path: &Path, width: u32, height: u32) {
+    GrayImage::from_fn(width, height, |x, y| Luma([(x * 3 + y * 5) as u8])).save(path).unwrap();
+}
+
+#[test]
+fn round_trips_a_grayscale_cover_as_grayscale() {
+    let dir = TempDir::new("grayscale");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    gray_cover(&cover, 64, 64);
+    
+    let secret = payload(500);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    assert_eq!(image::open(&written).unwrap().color(), ColorType::L8);
+    assert!(decoder::read_header(&written).unwrap().grayscale);
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn round_trips_a_16_bit_grayscale_cover_as_16_bit_grayscale() {
+    let dir = TempDir::new("grayscale16");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    let original = ImageBuffer::<Luma<u16>, _>::from_fn(64, 64, |x, y| Luma([(x * 977 + y * 499) as u16]));
+    original.save(&cover).unwrap();
+    
+    assert_eq!(cover::carrier_len(&cover).unwrap(), 64 * 64);
+    let secret = payload(1500);
+    let mask = ChannelByteMask::uniform(8).unwrap();
+    let written = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap().save(output).unwrap();
+    
+    // Only the low byte of each sample may change.
+    let stego = image::open(&written).unwrap();
+    assert_eq!(stego.color(), ColorType::L16);
+    let samples = stego.into_luma16();
+    assert!(original.iter().zip(samples.iter()).all(|(a, b)| a >> 8 == b >> 8));
+    assert!(decoder::read_header(&written).unwrap().grayscale);
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn grayscale_covers_carry_one_byte_per_pixel() {
+    let dir = TempDir::new("grayscale-len");
+    let cover = dir.path().join("cover.png");
+    gray_cover(&cover, 40, 30);
+    
+    assert_eq!(cover::carrier_len(&cover).unwrap(), 40 * 30);
+    assert_eq!(Cover::open(&cover).unwrap().bytes().len(), 40 * 30);
+}
+
+#[test]
+fn rejects_regions_in_grayscale_covers() {
+    let dir = TempDir::new("grayscale-region");
+    let cover = dir.path().join("cover.png");
+    gray_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(20), mask).unwrap();
+    assert!(matches!(encoder.set_region(0, 32, 64, 32), Err(Error::RegionOutOfBounds)));
+}
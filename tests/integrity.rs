@@ -0,0 +1,211 @@
+mod common;
+
+use std::path::Path;
+
+use image::RgbImage;
+
+use stegnoapp::header::{HEADER_CARRIER_LEN, MAX_COMMENT_LEN};
+use stegnoapp::crypto::{KdfParams, Keys, MAX_KDF};
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, decoder};
+
+use common::{TempDir, payload, solid_cover};
+
+// Far below the defaults, so the tests don't spend their time in Argon2.
+const CHEAP_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1 };
+
+fn encode_with_passphrase(cover: &Path, output: &Path, secret: &[u8], passphrase: &str) {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover.to_path_buf(), secret.to_vec(), mask).unwrap();
+    encoder.set_passphrase(passphrase);
+    encoder.set_kdf_params(CHEAP_KDF);
+    encoder.save(output.to_path_buf()).unwrap();
+}
+
+fn decode_with(output: &Path, passphrase: Option<&str>) -> Result<Vec<u8>, Error> {
+    let mut decoder = Decoder::new(output.to_path_buf())?;
+    if let Some(passphrase) = passphrase {
+        decoder.set_passphrase(passphrase);
+    }
+    decoder.read_to_vec()
+}
+
+#[test]
+fn round_trips_with_the_right_passphrase() {
+    let dir = TempDir::new("passphrase");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let secret = payload(200);
+    encode_with_passphrase(&cover, &output, &secret, "correct horse");
+    
+    assert_eq!(decode_with(&output, Some("correct horse")).unwrap(), secret);
+    assert_eq!(decoder::read_header(&output).unwrap().protection.unwrap().kdf, CHEAP_KDF);
+}
+
+#[test]
+fn embeds_only_ciphertext() {
+    let dir = TempDir::new("ciphertext");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let secret = payload(200);
+    encode_with_passphrase(&cover, &output, &secret, "correct horse");
+    
+    let embedded = decoder::extract(Cover::open(&output).unwrap().bytes()).unwrap();
+    assert_eq!(embedded.len(), secret.len());
+    assert_ne!(embedded, secret);
+}
+
+#[test]
+fn derives_keys_from_the_passphrase_and_salt() {
+    let derive = |passphrase, salt| Keys::derive(passphrase, &[salt; 16], CHEAP_KDF).unwrap();
+    
+    assert!(derive("correct horse", 1) == derive("correct horse", 1));
+    assert!(derive("correct horse", 1) != derive("correct horse", 2));
+    assert!(derive("correct horse", 1) != derive("battery staple", 1));
+}
+
+#[test]
+fn rejects_a_missing_or_wrong_passphrase() {
+    let dir = TempDir::new("wrong-passphrase");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    encode_with_passphrase(&cover, &output, &payload(200), "correct horse");
+    
+    assert!(matches!(decode_with(&output, None), Err(Error::PassphraseRequired)));
+    assert!(matches!(decode_with(&output, Some("battery staple")), Err(Error::IntegrityCheckFailed)));
+}
+
+#[test]
+fn detects_a_flipped_payload_bit() {
+    let dir = TempDir::new("tampered");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    encode_with_passphrase(&cover, &output, &payload(200), "correct horse");
+    let mut stego = Cover::open(&output).unwrap();
+    stego.bytes_mut()[HEADER_CARRIER_LEN + 10] ^= 1;
+    stego.save(&output).unwrap();
+    
+    assert!(matches!(decode_with(&output, Some("correct horse")), Err(Error::IntegrityCheckFailed)));
+}
+
+#[test]
+fn untagged_payloads_ignore_a_passphrase() {
+    let dir = TempDir::new("untagged");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let secret = payload(50);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    Encoder::from_bytes(cover, secret.clone(), mask).unwrap().save(output.clone()).unwrap();
+    
+    assert_eq!(decode_with(&output, Some("anything")).unwrap(), secret);
+}
+
+#[test]
+fn refuses_a_forged_kdf_cost_before_deriving() {
+    let dir = TempDir::new("kdf-cost");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    encode_with_passphrase(&cover, &output, &payload(200), "correct horse");
+    let mut stego = Cover::open(&output).unwrap();
+    let mut header = decoder::read_header(&output).unwrap();
+    header.protection.as_mut().unwrap().kdf.memory_kib = u32::MAX;
+    header.embed(stego.bytes_mut());
+    stego.save(&output).unwrap();
+    
+    assert!(matches!(decode_with(&output, Some("correct horse")), Err(Error::KdfCostTooHigh)));
+    assert!(matches!(Keys::derive("correct horse", &[0; 16], KdfParams { iterations: 1000, ..CHEAP_KDF }), Err(Error::KdfCostTooHigh)));
+    let above = KdfParams { iterations: MAX_KDF.iterations + 1, ..CHEAP_KDF };
+    assert!(matches!(Keys::derive("correct horse", &[0; 16], above), Err(Error::KdfCostTooHigh)));
+}
+
+#[test]
+fn reads_comments_without_the_passphrase() {
+    let dir = TempDir::new("comment");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover.clone(), payload(100), mask).unwrap();
+    encoder.set_passphrase("correct horse");
+    encoder.set_kdf_params(CHEAP_KDF);
+    encoder.set_comment("for Ada, see the usual place").unwrap();
+    encoder.save(output.clone()).unwrap();
+    
+    let header = decoder::read_header(&output).unwrap();
+    assert_eq!(header.comment.as_str(), "for Ada, see the usual place");
+    assert_eq!(Decoder::new(output.clone()).unwrap().comment().unwrap().as_deref(), Some("for Ada, see the usual place"));
+    assert_eq!(decode_with(&output, Some("correct horse")).unwrap(), payload(100));
+    
+    let too_long = "x".repeat(MAX_COMMENT_LEN + 1);
+    assert!(matches!(encoder.set_comment(&too_long), Err(Error::CommentTooLong)));
+}
+
+#[test]
+fn seals_comments_under_the_passphrase() {
+    let dir = TempDir::new("sealed-comment");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover.clone(), payload(100), mask).unwrap();
+    encoder.set_comment("only for the recipient").unwrap();
+    encoder.set_seal_comment(true);
+    assert!(matches!(encoder.save(output.clone()), Err(Error::PassphraseRequired)));
+    encoder.set_passphrase("correct horse");
+    encoder.set_kdf_params(CHEAP_KDF);
+    encoder.save(output.clone()).unwrap();
+    
+    let header = decoder::read_header(&output).unwrap();
+    assert!(header.sealed_comment && header.comment.is_empty());
+    let mut decoder = Decoder::new(output.clone()).unwrap();
+    assert!(matches!(decoder.comment(), Err(Error::PassphraseRequired)));
+    decoder.set_passphrase("correct horse");
+    assert_eq!(decoder.comment().unwrap().as_deref(), Some("only for the recipient"));
+    assert_eq!(decoder.read_to_vec().unwrap(), payload(100));
+}
+
+#[test]
+fn recovers_what_is_left_of_a_cropped_image() {
+    let dir = TempDir::new("cropped");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    let cropped = dir.path().join("cropped.png");
+    solid_cover(&cover, 32, 32);
+    
+    let secret = payload(200);
+    encode_with_passphrase(&cover, &output, &secret, "correct horse");
+    let stego = Cover::open(&output).unwrap();
+    let rows = 18;
+    let kept = stego.bytes()[..32 * rows as usize * 3].to_vec();
+    RgbImage::from_raw(32, rows, kept).unwrap().save(&cropped).unwrap();
+    assert!(Decoder::new(cropped.clone()).is_err());
+    
+    let mut decoder = Decoder::new_lenient(cropped).unwrap();
+    assert!(matches!(decoder.recover(), Err(Error::PassphraseRequired)));
+    decoder.set_passphrase("correct horse");
+    let recovery = decoder.recover().unwrap();
+    // Two bits per carrier byte after the header, whole bytes only.
+    let left = (32 * rows as usize * 3 - HEADER_CARRIER_LEN) / 4;
+    assert_eq!((recovery.recovered, recovery.declared, recovery.verified), (left, 200, Some(false)));
+    assert!(!recovery.complete());
+    assert_eq!(recovery.secret, secret[..left]);
+    
+    let mut whole = Decoder::new_lenient(output).unwrap();
+    whole.set_passphrase("correct horse");
+    let recovery = whole.recover().unwrap();
+    assert!(recovery.complete());
+    assert_eq!((recovery.secret, recovery.verified), (secret, Some(true)));
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use image::RgbImage;
+use proptest::prelude::*;
+
+use stegnoapp::header::{HEADER_CARRIER_LEN, HEADER_LEN, HEADER_VERSION, MAGIC};
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Error, StegoHeader, decoder};
+
+use common::TempDir;
+
+const REGION_FIELDS: usize = 5;
+
+// Writes `header` into the LSBs the way a real header is, so its fields
+// reach the decoder even though the bytes are arbitrary.
+fn carrier(header: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut carrier = vec![0; HEADER_CARRIER_LEN];
+    for (i, b) in carrier.iter_mut().take(HEADER_LEN * 8).enumerate() {
+        *b = (header[i / 8] >> (7 - i % 8)) & 1;
+    }
+    carrier.extend_from_slice(body);
+    carrier
+}
+
+// Arbitrary bytes with the magic and version in place, and the bit
+// depths, stride and length mostly in range so decoding gets past them.
+fn headers() -> impl Strategy<Value = Vec<u8>> {
+    (
+        prop::collection::vec(any::<u8>(), HEADER_LEN),
+        [0u8..=9, 0u8..=9, 0u8..=9],
+        0u16..4,
+        prop_oneof![0u32..300, any::<u32>()],
+        prop::collection::vec(0u8..40, REGION_FIELDS),
+    )
+        .prop_map(|(mut header, bits, stride, length, region)| {
+            header[0] = HEADER_VERSION;
+            header[1..5].copy_from_slice(MAGIC);
+            header[5..8].copy_from_slice(&bits);
+            header[8..10].copy_from_slice(&stride.to_be_bytes());
+            header[10..14].copy_from_slice(&length.to_be_bytes());
+            for (field, value) in header[19..39].chunks_mut(4).zip(region) {
+                field.copy_from_slice(&(value as u32).to_be_bytes());
+            }
+            header
+        })
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_headers_fail_without_panicking(
+        header in headers(),
+        body in prop::collection::vec(any::<u8>(), 0..600),
+        width in 1u32..16
+    ) {
+        let mut carrier = carrier(&header, &body);
+        let _ = decoder::extract(&carrier);
+        
+        let height = (carrier.len() / 3) as u32 / width;
+        carrier.truncate((width * height * 3) as usize);
+        let image = RgbImage::from_raw(width, height, carrier).unwrap();
+        if let Ok(decoder) = Decoder::from_cover(Cover::Rgb(image)) {
+            let _ = decoder.read_all();
+        }
+    }
+}
+
+#[test]
+fn rejects_lengths_past_the_image_capacity() {
+    let mut carrier = vec![0; HEADER_CARRIER_LEN + 300];
+    let mask = ChannelByteMask::uniform(8).unwrap();
+    let mut header = StegoHeader::new(mask.bits(), u32::MAX);
+    header.embed(&mut carrier);
+    assert!(matches!(decoder::extract(&carrier), Err(Error::InvalidHeader)));
+    
+    // Exactly full still decodes, one byte more doesn't.
+    StegoHeader::new(mask.bits(), 300).embed(&mut carrier);
+    assert_eq!(decoder::extract(&carrier).unwrap().len(), 300);
+    StegoHeader::new(mask.bits(), 301).embed(&mut carrier);
+    assert!(matches!(decoder::extract(&carrier), Err(Error::InvalidHeader)));
+    
+    header.spread = Some(3);
+    header.length = 300 / 3 / 8 + 1;
+    header.embed(&mut carrier);
+    let image = RgbImage::from_raw(carrier.len() as u32 / 3, 1, carrier).unwrap();
+    assert!(matches!(Decoder::from_cover(Cover::Rgb(image)), Err(Error::InvalidHeader)));
+}
+
+// A lenient decode skips locating the payload, which used to be the only
+// check on the depths, so a header can't carry depths no mask allows.
+#[test]
+fn rejects_invalid_bit_depths_even_when_lenient() {
+    let dir = TempDir::new("malformed-bits");
+    let path = dir.path().join("stego.png");
+    for bits in [[0, 0, 0], [9, 2, 2], [2, 2, 200]] {
+        let mut carrier = vec![0; HEADER_CARRIER_LEN + 300];
+        StegoHeader::new(bits, 10).embed(&mut carrier);
+        assert!(matches!(decoder::extract(&carrier), Err(Error::NotAStegoImage)));
+        
+        RgbImage::from_raw(carrier.len() as u32 / 3, 1, carrier).unwrap().save(&path).unwrap();
+        assert!(matches!(Decoder::new_lenient(path.clone()), Err(Error::NotAStegoImage)));
+    }
+}
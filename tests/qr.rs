@@ -0,0 +1,38 @@
+mod common;
+
+use stegnoapp::{ChannelByteMask, Decoder, Encoder, Error, qr};
+
+use common::{TempDir, solid_cover};
+
+#[test]
+fn round_trips_through_a_qr_code_in_a_cover() {
+    let dir = TempDir::new("qr");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let secret = b"https://example.com/a/rather/long/link?with=query".to_vec();
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    Encoder::from_bytes(cover, qr::encode(&secret).unwrap(), mask).unwrap().save(output.clone()).unwrap();
+    
+    let packed = Decoder::new(output).unwrap().read_to_vec().unwrap();
+    assert_eq!(qr::decode(&packed).unwrap(), secret);
+}
+
+#[test]
+fn corrects_damaged_modules() {
+    let secret = b"correct horse battery staple".to_vec();
+    let mut packed = qr::encode(&secret).unwrap();
+    // A few flipped modules in the data area, well within level H.
+    for i in [40, 41, 55, 70] {
+        packed[i] ^= 0x10;
+    }
+    
+    assert_eq!(qr::decode(&packed).unwrap(), secret);
+}
+
+#[test]
+fn rejects_secrets_too_large_for_a_qr_code() {
+    assert!(matches!(qr::encode(&[0xAB; 2000]), Err(Error::QrTooLarge)));
+    assert!(matches!(qr::decode(&[0; 4]), Err(Error::InvalidQr)));
+}
@@ -0,0 +1,511 @@
+mod common;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use stegnoapp::header::HEADER_CARRIER_LEN;
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, Padding, encoder};
+
+use common::{TempDir, payload, solid_cover};
+
+const SIZES: [usize; 7] = [0, 1, 2, 3, 7, 255, 1000];
+
+fn round_trip(cover: &Path, output: &Path, mask: ChannelByteMask, secret: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder::from_bytes(cover.to_path_buf(), secret.to_vec(), mask).unwrap();
+    encoder.save(output.to_path_buf()).unwrap();
+    
+    Decoder::new(output.to_path_buf()).unwrap().read_to_vec().unwrap()
+}
+
+#[test]
+fn round_trips_every_bit_depth_and_size() {
+    let dir = TempDir::new("every-depth");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    for bits in 1..=8 {
+        let mask = ChannelByteMask::uniform(bits).unwrap();
+        for size in SIZES {
+            let secret = payload(size);
+            assert_eq!(round_trip(&cover, &output, mask, &secret), secret, "bits {} size {}", bits, size);
+        }
+    }
+}
+
+// Odd dimensions leave carriers whose length after the header isn't a
+// multiple of any group of channels, and a full payload ends right at the
+// last byte that can hold it.
+#[test]
+fn round_trips_full_payloads_on_odd_sized_covers() {
+    let dir = TempDir::new("odd-sizes");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    
+    for (width, height) in [(23, 21), (29, 17), (31, 19)] {
+        solid_cover(&cover, width, height);
+        let len = Cover::open(&cover).unwrap().bytes().len();
+        for bits in 1..=8 {
+            let mask = ChannelByteMask::uniform(bits).unwrap();
+            let full = encoder::capacity(len, mask, 1);
+            for size in [full - 1, full] {
+                let secret = payload(size);
+                assert_eq!(round_trip(&cover, &output, mask, &secret), secret, "{}x{} bits {} size {}", width, height, bits, size);
+            }
+        }
+    }
+}
+
+#[test]
+fn round_trips_payloads_starting_with_zero_bytes() {
+    let dir = TempDir::new("leading-zero");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    for secret in [vec![0, 0, 0, 1, 2, 3], vec![0; 16]] {
+        for bits in 1..=8 {
+            let mask = ChannelByteMask::uniform(bits).unwrap();
+            assert_eq!(round_trip(&cover, &output, mask, &secret), secret, "bits {}", bits);
+        }
+    }
+}
+
+#[test]
+fn round_trips_per_channel_depths() {
+    let dir = TempDir::new("per-channel");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    for spec in ["R:1,G:3,B:2", "R:8,G:1,B:5", "r:7, g:7, b:6"] {
+        let mask: ChannelByteMask = spec.parse().unwrap();
+        let secret = payload(200);
+        assert_eq!(round_trip(&cover, &output, mask, &secret), secret, "spec {}", spec);
+        assert_eq!(Decoder::new(output.clone()).unwrap().mask(), mask);
+    }
+}
+
+#[test]
+fn round_trips_subsets_of_channels_leaving_the_others_alone() {
+    let dir = TempDir::new("channels");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    let original = Cover::open(&cover).unwrap().bytes().to_vec();
+    
+    for channels in ["rb", "g", "BR"] {
+        let mask = ChannelByteMask::uniform(2).unwrap().with_channels(channels).unwrap();
+        let secret = payload(100);
+        assert_eq!(round_trip(&cover, &output, mask, &secret), secret, "channels {}", channels);
+        assert_eq!(Decoder::new(output.clone()).unwrap().mask(), mask);
+        
+        let stego = Cover::open(&output).unwrap().bytes().to_vec();
+        let skipped = mask.bits().iter().position(|&bits| bits == 0).unwrap();
+        let body = HEADER_CARRIER_LEN..original.len();
+        assert!(body.filter(|i| i % 3 == skipped).all(|i| stego[i] == original[i]), "channels {}", channels);
+    }
+}
+
+#[test]
+fn rejects_empty_or_unknown_channel_sets() {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    for channels in ["", "rgx", "rr", "a"] {
+        assert!(matches!(mask.with_channels(channels), Err(Error::InvalidChannels)), "channels {:?}", channels);
+    }
+}
+
+#[test]
+fn round_trips_empty_secrets_as_empty_payloads() {
+    let dir = TempDir::new("empty");
+    let cover = dir.path().join("cover.png");
+    let secret = dir.path().join("empty.txt");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    std::fs::write(&secret, b"").unwrap();
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let setups: [fn(&mut Encoder); 3] = [|_| {}, |e| e.set_passphrase("hunter2"), |e| e.set_spread(5).unwrap()];
+    for setup in setups {
+        let mut encoder = Encoder::new(cover.clone(), secret.clone(), mask).unwrap();
+        setup(&mut encoder);
+        encoder.save(output.clone()).unwrap();
+        
+        let mut decoder = Decoder::new(output.clone()).unwrap();
+        decoder.set_passphrase("hunter2");
+        assert_eq!(decoder.headers()[0].length, 0);
+        assert_eq!(decoder.read_to_vec().unwrap(), b"");
+    }
+}
+
+#[test]
+fn decodes_an_image_already_in_memory() {
+    let dir = TempDir::new("in-memory");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(3).unwrap();
+    let secret = payload(120);
+    round_trip(&cover, &output, mask, &secret);
+    
+    let buffer = image::open(&output).unwrap().to_rgb8();
+    let decoder = Decoder::from_cover(Cover::Rgb(buffer)).unwrap();
+    assert_eq!(decoder.mask(), mask);
+    assert_eq!(decoder.read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn round_trips_bmp_cover_to_bmp() {
+    let dir = TempDir::new("bmp");
+    let cover = dir.path().join("cover.bmp");
+    let output = dir.path().join("stego.bmp");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = b"hidden in a bitmap".to_vec();
+    assert_eq!(round_trip(&cover, &output, mask, &secret), secret);
+}
+
+#[test]
+fn round_trips_strided_embedding_without_touching_skipped_pixels() {
+    let dir = TempDir::new("stride");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = payload(300);
+    for stride in [1, 2, 3, 7] {
+        let mut encoder = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap();
+        encoder.set_stride(stride).unwrap();
+        encoder.save(output.clone()).unwrap();
+        assert_eq!(Decoder::new(output.clone()).unwrap().read_to_vec().unwrap(), secret, "stride {}", stride);
+        
+        let original = Cover::open(&cover).unwrap();
+        let stego = Cover::open(&output).unwrap();
+        let body = |c: &Cover| c.bytes()[HEADER_CARRIER_LEN..].to_vec();
+        let (original, stego) = (body(&original), body(&stego));
+        for (i, (a, b)) in original.chunks(3).zip(stego.chunks(3)).enumerate() {
+            if i % stride != 0 {
+                assert_eq!(a, b, "stride {} pixel {}", stride, i);
+            }
+        }
+    }
+}
+
+#[test]
+fn region_embedding_leaves_the_rest_of_the_image_alone() {
+    let dir = TempDir::new("region");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = payload(200);
+    let mut encoder = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap();
+    encoder.set_region(16, 20, 32, 30).unwrap();
+    encoder.save(output.clone()).unwrap();
+    assert_eq!(Decoder::new(output.clone()).unwrap().read_to_vec().unwrap(), secret);
+    
+    let original = image::open(&cover).unwrap().to_rgb8();
+    let stego = image::open(&output).unwrap().to_rgb8();
+    for (x, y, pixel) in stego.enumerate_pixels() {
+        let inside = (16..48).contains(&x) && (20..50).contains(&y);
+        let header = (y * 64 + x) < (HEADER_CARRIER_LEN / 3) as u32;
+        if !inside && !header {
+            assert_eq!(pixel, original.get_pixel(x, y), "pixel {},{}", x, y);
+        }
+    }
+}
+
+#[test]
+fn rejects_regions_outside_the_image_or_too_small() {
+    let dir = TempDir::new("region-bounds");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(60), mask).unwrap();
+    assert!(matches!(encoder.set_region(20, 0, 13, 32), Err(Error::RegionOutOfBounds)));
+    assert!(matches!(encoder.set_region(0, 0, 0, 32), Err(Error::RegionOutOfBounds)));
+    assert!(matches!(encoder.set_region(u32::MAX, 0, 2, 2), Err(Error::RegionOutOfBounds)));
+    assert!(matches!(encoder.set_region(0, 30, 32, 2), Err(Error::SecretTooLarge)));
+    assert!(encoder.set_region(0, 16, 32, 16).is_ok());
+}
+
+#[test]
+fn rejects_strides_the_secret_does_not_fit() {
+    let dir = TempDir::new("stride-fit");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = payload(encoder::capacity(32 * 32 * 3, mask, 2));
+    let mut encoder = Encoder::from_bytes(cover, secret, mask).unwrap();
+    assert!(matches!(encoder.set_stride(0), Err(Error::InvalidStride)));
+    assert!(matches!(encoder.set_stride(3), Err(Error::SecretTooLarge)));
+    assert!(encoder.set_stride(2).is_ok());
+}
+
+#[test]
+fn refuses_to_write_over_the_cover_or_the_secret() {
+    let dir = TempDir::new("same-path");
+    let cover = dir.path().join("cover.png");
+    let secret = dir.path().join("secret.png");
+    solid_cover(&cover, 32, 32);
+    std::fs::write(&secret, payload(40)).unwrap();
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let dotted = dir.path().join(".").join("cover.png");
+    assert!(matches!(Encoder::new(cover.clone(), dotted.clone(), mask), Err(Error::SamePath)));
+    
+    let mut encoder = Encoder::new(cover.clone(), secret.clone(), mask).unwrap();
+    assert!(matches!(encoder.save(dotted), Err(Error::SamePath)));
+    assert!(matches!(encoder.save(secret.clone()), Err(Error::SamePath)));
+    assert_eq!(std::fs::read(&secret).unwrap(), payload(40));
+    assert!(encoder.save(dir.path().join("stego.png")).is_ok());
+}
+
+#[test]
+fn refuses_outputs_in_missing_directories_before_embedding() {
+    let dir = TempDir::new("unwritable");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(40), mask).unwrap();
+    let missing = dir.path().join("missing");
+    let result = encoder.save(missing.join("stego.png"));
+    assert!(matches!(result, Err(Error::OutputNotWritable(d)) if d == missing));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn rejects_secret_larger_than_capacity() {
+    let dir = TempDir::new("too-large");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 8, 8);
+    
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let result = Encoder::from_bytes(cover, payload(1000), mask);
+    assert!(matches!(result, Err(Error::SecretTooLarge)));
+}
+
+#[test]
+fn capacity_checks_do_not_overflow() {
+    let mask = ChannelByteMask::uniform(8).unwrap();
+    assert_eq!(encoder::capacity(usize::MAX, mask, 1), u32::MAX as usize);
+    
+    assert!(encoder::fits(u32::MAX as u64, usize::MAX, mask, 1));
+    assert!(!encoder::fits(u32::MAX as u64 + 1, usize::MAX, mask, 1));
+    assert!(!encoder::fits(u64::MAX, usize::MAX, mask, 1));
+    assert!(!encoder::fits(0, 10, mask, 1));
+}
+
+#[test]
+fn fits_secrets_at_the_fewest_bits_that_hold_them() {
+    let image_len = 32 * 32 * 3;
+    for bits in 1..=8 {
+        let mask = ChannelByteMask::uniform(bits).unwrap();
+        let most = encoder::capacity(image_len, mask, 1) as u64;
+        assert_eq!(encoder::fitting_bits(most, image_len, 8).unwrap(), bits);
+        if bits < 8 {
+            assert_eq!(encoder::fitting_bits(most + 1, image_len, 8).unwrap(), bits + 1);
+        }
+    }
+    
+    assert_eq!(encoder::fitting_bits(0, image_len, 8).unwrap(), 1);
+    let full = encoder::capacity(image_len, ChannelByteMask::uniform(8).unwrap(), 1) as u64;
+    assert!(matches!(encoder::fitting_bits(full, image_len, 7), Err(Error::SecretTooLarge)));
+    assert!(matches!(encoder::fitting_bits(full + 1, image_len, 8), Err(Error::SecretTooLarge)));
+}
+
+#[test]
+fn reports_the_bytes_an_encode_touched() {
+    let dir = TempDir::new("report");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(100), mask).unwrap();
+    encoder.set_padding(Padding::None);
+    let (_, report) = encoder.save_with_report(output).unwrap();
+    
+    assert_eq!(report.carrier_bytes, 32 * 32 * 3);
+    assert_eq!(report.mask, mask);
+    assert!(report.touched > 0);
+    assert!(report.touched <= HEADER_CARRIER_LEN + mask.carrier_len(100 * 8));
+    assert!((report.density() - 100.0 * report.touched as f64 / report.carrier_bytes as f64).abs() < 1e-9);
+    assert!(report.psnr.is_finite());
+}
+
+#[test]
+fn returns_what_an_encode_and_decode_wrote() {
+    let dir = TempDir::new("outcome");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(3).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(100), mask).unwrap();
+    let encoded = encoder.save_with_outcome(dir.path().join("stego")).unwrap();
+    assert_eq!(encoded.output, dir.path().join("stego.png"));
+    assert_eq!((encoded.payload_len, encoded.mask), (100, mask));
+    assert!(encoded.report.is_none());
+    
+    encoder.set_report(true);
+    assert!(encoder.save_with_outcome(encoded.output.clone()).unwrap().report.is_some());
+    
+    let decoded = Decoder::new(encoded.output).unwrap().save_with_outcome(dir.path().join("secret.bin")).unwrap();
+    assert_eq!(decoded.output, dir.path().join("secret.bin"));
+    assert_eq!((decoded.payload_len, decoded.mask), (100, mask));
+}
+
+#[test]
+fn buffer_length_does_not_change_what_is_written_or_read() {
+    let dir = TempDir::new("buffer-len");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 64, 64);
+    
+    for bits in [[1, 1, 1], [3, 2, 0], [8, 8, 8]] {
+        let mask = ChannelByteMask::new(bits).unwrap();
+        let secret = payload(encoder::capacity(64 * 64 * 3, mask, 1).min(3000));
+        let mut outputs = Vec::new();
+        for buffer_len in [0, 1, 7, 100, 1 << 20] {
+            let output = dir.path().join(format!("stego-{}.png", buffer_len));
+            let mut encoder = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap();
+            encoder.set_padding(Padding::Zero);
+            encoder.set_buffer_len(buffer_len);
+            encoder.save(output.clone()).unwrap();
+            outputs.push(Cover::open(&output).unwrap().bytes().to_vec());
+            
+            let mut decoder = Decoder::new(output).unwrap();
+            decoder.set_buffer_len(buffer_len);
+            assert_eq!(decoder.read_to_vec().unwrap(), secret, "bits {:?}, buffer {}", bits, buffer_len);
+        }
+        assert!(outputs.windows(2).all(|w| w[0] == w[1]), "bits {:?}", bits);
+    }
+}
+
+// Enables debug records without printing them, so timings are measured.
+struct Quiet;
+
+impl log::Log for Quiet {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+    
+    fn log(&self, _: &log::Record) {}
+    
+    fn flush(&self) {}
+}
+
+#[test]
+fn times_each_stage_only_while_debug_logging() {
+    let dir = TempDir::new("timings");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover.clone(), payload(100), mask).unwrap();
+    let encoded = encoder.save_with_outcome(dir.path().join("quiet.png")).unwrap();
+    assert!(encoded.timings.is_none());
+    
+    log::set_logger(&Quiet).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+    let mut encoder = Encoder::from_bytes(cover, payload(100), mask).unwrap();
+    let encoded = encoder.save_with_outcome(dir.path().join("timed.png")).unwrap();
+    let timings = encoded.timings.unwrap();
+    assert!(timings.load > Duration::ZERO && timings.save > Duration::ZERO);
+    assert_eq!(timings.total(), timings.load + timings.lsb + timings.save);
+    assert!(encoder.dry_run(dir.path().join("planned.png")).unwrap().timings.is_some_and(|t| t.save.is_zero()));
+    
+    let decoder = Decoder::new(encoded.output).unwrap();
+    let decoded = decoder.save_with_outcome(dir.path().join("secret.bin")).unwrap();
+    assert!(decoded.timings.is_some_and(|t| t.load > Duration::ZERO));
+}
+
+#[test]
+fn dry_runs_predict_the_encode_without_writing() {
+    let dir = TempDir::new("dry-run");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover.clone(), payload(100), mask).unwrap();
+    encoder.set_padding(Padding::None);
+    encoder.set_passphrase("dry");
+    let planned = encoder.dry_run(output.clone()).unwrap();
+    assert!(!output.exists());
+    
+    encoder.set_report(true);
+    let saved = encoder.save_with_outcome(output.clone()).unwrap();
+    assert_eq!((planned.output, planned.payload_len, planned.mask), (saved.output, saved.payload_len, saved.mask));
+    let (planned, saved) = (planned.report.unwrap(), saved.report.unwrap());
+    assert_eq!(planned.carrier_bytes, saved.carrier_bytes);
+    // Each seal draws a fresh salt, so only the bytes touched can differ.
+    assert!(planned.touched.abs_diff(saved.touched) <= HEADER_CARRIER_LEN + mask.carrier_len(100 * 8));
+    
+    assert!(matches!(encoder.dry_run(cover), Err(Error::SamePath)));
+}
+
+#[test]
+fn rejects_images_without_a_header() {
+    let dir = TempDir::new("no-header");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 16, 16);
+    
+    assert!(matches!(Decoder::new(cover), Err(Error::NotAStegoImage)));
+}
+
+#[test]
+fn cancelled_encode_leaves_no_output() {
+    let dir = TempDir::new("cancel");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(64), mask).unwrap();
+    encoder.set_cancel_flag(Arc::new(AtomicBool::new(true)));
+    
+    assert!(matches!(encoder.save(output.clone()), Err(Error::Cancelled)));
+    assert!(!output.exists());
+}
+
+#[test]
+fn reports_progress_batch_by_batch() {
+    let dir = TempDir::new("progress");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 1024, 1024);
+    
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let record = |calls: &Arc<Mutex<Vec<(u64, u64)>>>| {
+        let calls = Arc::clone(calls);
+        Arc::new(move |done, total| calls.lock().unwrap().push((done, total)))
+    };
+    
+    // Spans two encode batches and three decode ones at one bit per byte.
+    let secret = payload(300_000);
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, secret.clone(), mask).unwrap();
+    encoder.set_progress(record(&calls));
+    encoder.save(output.clone()).unwrap();
+    let encoded = std::mem::take(&mut *calls.lock().unwrap());
+    
+    let mut decoder = Decoder::new(output).unwrap();
+    decoder.set_progress(record(&calls));
+    assert_eq!(decoder.read_to_vec().unwrap(), secret);
+    let decoded = calls.lock().unwrap().clone();
+    
+    for progress in [encoded, decoded] {
+        assert!(progress.len() > 1);
+        assert!(progress.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(progress.last(), Some(&(secret.len() as u64, secret.len() as u64)));
+    }
+}
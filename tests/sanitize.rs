@@ -0,0 +1,26 @@
+mod common;
+
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, Padding, encoder};
+
+use common::{TempDir, payload, solid_cover};
+
+#[test]
+fn sanitizing_destroys_the_payload_but_only_the_low_bits() {
+    let dir = TempDir::new("sanitize");
+    let cover = dir.path().join("cover.png");
+    let stego = dir.path().join("stego.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    Encoder::from_bytes(cover, payload(100), mask).unwrap().save(stego.clone()).unwrap();
+    
+    let mut image = Cover::open(&stego).unwrap();
+    let before = image.bytes().to_vec();
+    let altered = encoder::sanitize(image.bytes_mut(), mask, Padding::Zero);
+    
+    assert_eq!(altered, before.iter().filter(|b| (*b & 0b11) != 0).count());
+    assert!(image.bytes().iter().zip(&before).all(|(a, b)| (a & !0b11) == (b & !0b11)));
+    
+    image.save(&stego).unwrap();
+    assert!(matches!(Decoder::new(stego), Err(Error::NotAStegoImage)));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+use stegnoapp::header::HEADER_CARRIER_LEN;
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, decoder, selection};
+
+use common::{TempDir, payload};
+
+// Busy top half over a flat bottom half.
+fn half_textured_cover(path: &Path, width: u32, height: u32) {
+    RgbImage::from_fn(width, height, |x, y| {
+        if y < height / 2 {
+            let v = ((x * 67 + y * 31) % 256) as u8;
+            Rgb([v, v ^ 0x5a, 255 - v])
+        } else {
+            Rgb([120, 200, 40])
+        }
+    })
+    .save(path)
+    .unwrap();
+}
+
+#[test]
+fn adaptive_embedding_leaves_flat_areas_alone() {
+    let dir = TempDir::new("adaptive");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    half_textured_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = payload(200);
+    let mut encoder = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap();
+    encoder.set_adaptive(true);
+    encoder.save(output.clone()).unwrap();
+    
+    assert!(decoder::read_header(&output).unwrap().threshold.is_some_and(|t| t > 0));
+    assert_eq!(Decoder::new(output.clone()).unwrap().read_to_vec().unwrap(), secret);
+    
+    let body = |path: &Path| Cover::open(path).unwrap().bytes()[HEADER_CARRIER_LEN..].to_vec();
+    let (original, stego) = (body(&cover), body(&output));
+    let flat = (64 * 32 * 3 - HEADER_CARRIER_LEN) / 3 + 2;
+    assert_eq!(original[flat * 3..], stego[flat * 3..]);
+}
+
+#[test]
+fn texture_ignores_the_payload_bits() {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let body: Vec<u8> = (0..300).map(|i| (i * 67 % 256) as u8).collect();
+    let flipped: Vec<u8> = body.iter().map(|b| b ^ 0b11).collect();
+    
+    for pixel in 0..100 {
+        assert_eq!(selection::texture(&body, mask, pixel), selection::texture(&flipped, mask, pixel));
+    }
+    assert_eq!(selection::texture(&[7; 30], mask, 4), 0);
+}
+
+#[test]
+fn interleaving_maps_every_byte_once_and_fills_a_channel_first() {
+    for (len, run) in [(0, 4), (2, 3), (30, 1), (30, 4), (31, 3), (32, 10), (300, 7)] {
+        let mut seen: Vec<usize> = (0..len).map(|slot| selection::interleaved(slot, len, run)).collect();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..len).collect::<Vec<_>>(), "len {} run {}", len, run);
+    }
+    
+    assert_eq!((0..9).map(|slot| selection::interleaved(slot, 9, 1)).collect::<Vec<_>>(), (0..9).collect::<Vec<_>>());
+    assert_eq!((0..6).map(|slot| selection::interleaved(slot, 30, 2)).collect::<Vec<_>>(), [0, 3, 1, 4, 2, 5]);
+}
+
+#[test]
+fn round_trips_interleaved_payloads() {
+    let dir = TempDir::new("interleave");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    half_textured_cover(&cover, 64, 64);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let secret = payload(300);
+    for (run, stride, adaptive) in [(3, 1, false), (16, 2, false), (5, 1, true), (u16::MAX as usize, 1, false)] {
+        let mut encoder = Encoder::from_bytes(cover.clone(), secret.clone(), mask).unwrap();
+        encoder.set_interleave(run).unwrap();
+        encoder.set_stride(stride).unwrap();
+        encoder.set_adaptive(adaptive);
+        encoder.save(output.clone()).unwrap();
+        
+        assert_eq!(decoder::read_header(&output).unwrap().interleave as usize, run);
+        assert_eq!(Decoder::new(output.clone()).unwrap().read_to_vec().unwrap(), secret, "run {}", run);
+    }
+}
+
+#[test]
+fn rejects_interleaving_per_channel_masks() {
+    let dir = TempDir::new("interleave-mask");
+    let cover = dir.path().join("cover.png");
+    half_textured_cover(&cover, 32, 32);
+    
+    let mask = "R:1,G:3,B:2".parse().unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(20), mask).unwrap();
+    assert!(matches!(encoder.set_interleave(0), Err(Error::InvalidInterleave)));
+    assert!(matches!(encoder.set_interleave(4), Err(Error::InvalidInterleave)));
+    assert!(encoder.set_interleave(1).is_ok());
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use rand::Rng;
+use stegnoapp::crypto::KdfParams;
+use stegnoapp::header::HEADER_CARRIER_LEN;
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Error, decoder, utils};
+
+use common::{TempDir, payload, solid_cover};
+
+const CHEAP_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1 };
+
+#[test]
+fn spread_payload_survives_flipped_lsbs() {
+    let dir = TempDir::new("spread");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let secret = payload(50);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, secret.clone(), mask).unwrap();
+    encoder.set_spread(9).unwrap();
+    encoder.set_passphrase("correct horse");
+    encoder.set_kdf_params(CHEAP_KDF);
+    encoder.save(output.clone()).unwrap();
+    assert_eq!(decoder::read_header(&output).unwrap().spread, Some(9));
+    
+    let mut stego = Cover::open(&output).unwrap();
+    let mut rng = rand::rng();
+    let len = stego.bytes().len();
+    for _ in 0..40 {
+        stego.bytes_mut()[rng.random_range(HEADER_CARRIER_LEN..len)] ^= 1;
+    }
+    stego.save(&output).unwrap();
+    
+    let mut decoder = Decoder::new(output).unwrap();
+    decoder.set_passphrase("correct horse");
+    assert_eq!(decoder.read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn spread_positions_depend_on_the_passphrase() {
+    let dir = TempDir::new("spread-seed");
+    let cover = dir.path().join("cover.png");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 64, 64);
+    
+    let secret = payload(40);
+    let mask = ChannelByteMask::uniform(1).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, secret.clone(), mask).unwrap();
+    encoder.set_spread(5).unwrap();
+    encoder.set_passphrase("correct horse");
+    encoder.set_kdf_params(CHEAP_KDF);
+    encoder.save(output.clone()).unwrap();
+    
+    let (stego, header) = (Cover::open(&output).unwrap(), decoder::read_header(&output).unwrap());
+    let positions = |p| decoder::spread_positions(stego.bytes(), &header, utils::spread_seed(Some(p))).unwrap();
+    assert_eq!(positions("correct horse"), positions("correct horse"));
+    assert_ne!(positions("correct horse"), positions("battery staple"));
+    
+    let mut decoder = Decoder::new(output).unwrap();
+    decoder.set_passphrase("battery staple");
+    assert!(matches!(decoder.read_to_vec(), Err(Error::IntegrityCheckFailed)));
+}
+
+#[test]
+fn rejects_spread_factors_that_do_not_fit() {
+    let dir = TempDir::new("spread-fit");
+    let cover = dir.path().join("cover.png");
+    solid_cover(&cover, 32, 32);
+    
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let mut encoder = Encoder::from_bytes(cover, payload(20), mask).unwrap();
+    let most = (32 * 32 * 3 - HEADER_CARRIER_LEN) / (20 * 8);
+    assert!(matches!(encoder.set_spread(0), Err(Error::InvalidSpreadFactor)));
+    assert!(matches!(encoder.set_spread(most + 1), Err(Error::SecretTooLarge)));
+    assert!(encoder.set_spread(most).is_ok());
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::{self, File};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use stegnoapp::header::StegoHeader;
+use stegnoapp::{ChannelByteMask, Cover, Decoder, Encoder, Padding, encoder};
+
+use common::{TempDir, payload, solid_cover};
+
+// Tracks the bytes allocated at the peak since the last reset. This crate
+// holds a single test so nothing else allocates alongside it.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[test]
+fn streams_file_secrets_without_buffering_them() {
+    let dir = TempDir::new("streaming");
+    let cover = dir.path().join("cover.png");
+    let secret = dir.path().join("secret.bin");
+    let output = dir.path().join("stego.png");
+    solid_cover(&cover, 2048, 2048);
+    fs::write(&secret, payload(6_000_000)).unwrap();
+    
+    let mask = ChannelByteMask::uniform(4).unwrap();
+    Encoder::new(cover.clone(), secret.clone(), mask).unwrap().save(output.clone()).unwrap();
+    assert_eq!(Decoder::new(output.clone()).unwrap().read_to_vec().unwrap(), fs::read(&secret).unwrap());
+    
+    // Embedding itself only ever holds one batch of the secret.
+    let mut image = Cover::open(&cover).unwrap();
+    let len = fs::metadata(&secret).unwrap().len() as usize;
+    let header = StegoHeader::new(mask.bits(), len as u32);
+    let mut file = File::open(&secret).unwrap();
+    let cancel = AtomicBool::new(false);
+    
+    PEAK.store(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed);
+    let before = CURRENT.load(Ordering::Relaxed);
+    encoder::embed_from(image.bytes_mut(), &header, &mut file, len, mask, Padding::None, &cancel).unwrap();
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    
+    assert!(peak < 1 << 20, "embedding a {} byte secret peaked at {} bytes", len, peak);
+    
+    // And so does saving it back out of the stego image.
+    let decoder = Decoder::new(output).unwrap();
+    let extracted = dir.path().join("extracted.bin");
+    PEAK.store(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed);
+    let before = CURRENT.load(Ordering::Relaxed);
+    decoder.save(extracted.clone()).unwrap();
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    
+    assert!(peak < 1 << 20, "saving a {} byte secret peaked at {} bytes", len, peak);
+    assert_eq!(fs::read(&extracted).unwrap(), fs::read(&secret).unwrap());
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::path::Path;
+
+use image::{ColorType, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage};
+use stegnoapp::{ChannelByteMask, Decoder, Encoder};
+
+use common::{TempDir, payload};
+
+fn round_trip(cover: &Path, output: &Path, secret: &[u8]) -> Vec<u8> {
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let written = Encoder::from_bytes(cover.to_path_buf(), secret.to_vec(), mask)
+        .unwrap()
+        .save(output.to_path_buf())
+        .unwrap();
+    assert_eq!(written, output);
+    
+    Decoder::new(written).unwrap().read_to_vec().unwrap()
+}
+
+// At 192 KiB the encoder splits the image over many strips.
+#[test]
+fn round_trips_a_multi_strip_tiff() {
+    let dir = TempDir::new("tiff");
+    let cover = dir.path().join("cover.tiff");
+    let output = dir.path().join("stego.tiff");
+    RgbImage::from_fn(256, 256, |x, y| Rgb([x as u8, y as u8, (x ^ y) as u8])).save(&cover).unwrap();
+    
+    let secret = payload(20_000);
+    assert_eq!(round_trip(&cover, &output, &secret), secret);
+}
+
+#[test]
+fn keeps_the_tif_spelling_of_the_output() {
+    let dir = TempDir::new("tif");
+    let cover = dir.path().join("cover.tif");
+    let output = dir.path().join("stego.tif");
+    RgbImage::from_pixel(32, 32, Rgb([120, 200, 40])).save(&cover).unwrap();
+    
+    let secret = payload(100);
+    assert_eq!(round_trip(&cover, &output, &secret), secret);
+}
+
+#[test]
+fn round_trips_a_16_bit_tiff_without_losing_depth() {
+    let dir = TempDir::new("tiff16");
+    let cover = dir.path().join("cover.tiff");
+    let output = dir.path().join("stego.tiff");
+    ImageBuffer::<Rgb<u16>, _>::from_pixel(32, 32, Rgb([30_000, 1_000, 65_000])).save(&cover).unwrap();
+    
+    let secret = payload(300);
+    assert_eq!(round_trip(&cover, &output, &secret), secret);
+    assert_eq!(image::open(&output).unwrap().color(), ColorType::Rgb16);
+}
+
+#[test]
+fn round_trips_a_tiff_with_alpha_as_rgb() {
+    let dir = TempDir::new("tiff-alpha");
+    let cover = dir.path().join("cover.tiff");
+    let output = dir.path().join("stego.tiff");
+    RgbaImage::from_pixel(32, 32, Rgba([120, 200, 40, 128])).save(&cover).unwrap();
+    
+    let secret = payload(100);
+    assert_eq!(round_trip(&cover, &output, &secret), secret);
+    assert_eq!(image::open(&output).unwrap().color(), ColorType::Rgb8);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::fs;
+
+use image::{Rgb, RgbImage};
+use stegnoapp::{ChannelByteMask, Decoder, Encoder, cover};
+
+use common::{TempDir, payload};
+
+// A RIFF container holding the given chunks, each a name and its length.
+// Only the chunk headers matter to the lossy check, so the data is zeros.
+fn webp_chunks(chunks: &[(&[u8; 4], u32)]) -> Vec<u8> {
+    let mut body = b"WEBP".to_vec();
+    for (name, len) in chunks {
+        body.extend_from_slice(*name);
+        body.extend_from_slice(&len.to_le_bytes());
+        body.resize(body.len() + (len + len % 2) as usize, 0);
+    }
+    
+    let mut file = b"RIFF".to_vec();
+    file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    file.extend_from_slice(&body);
+    file
+}
+
+#[test]
+fn round_trips_a_lossless_webp() {
+    let dir = TempDir::new("webp");
+    let cover = dir.path().join("cover.webp");
+    let output = dir.path().join("stego.webp");
+    RgbImage::from_fn(64, 64, |x, y| Rgb([x as u8 * 4, y as u8 * 4, (x ^ y) as u8])).save(&cover).unwrap();
+    assert!(!cover::is_lossy_webp(&cover).unwrap());
+    
+    let secret = payload(1000);
+    let mask = ChannelByteMask::uniform(2).unwrap();
+    let written = Encoder::from_bytes(cover, secret.clone(), mask).unwrap().save(output.clone()).unwrap();
+    assert_eq!(written, output);
+    assert!(!cover::is_lossy_webp(&written).unwrap());
+    assert_eq!(Decoder::new(written).unwrap().read_to_vec().unwrap(), secret);
+}
+
+#[test]
+fn tells_lossy_webp_from_lossless() {
+    let dir = TempDir::new("webp-lossy");
+    let path = dir.path().join("probe.webp");
+    let lossy = |chunks: &[(&[u8; 4], u32)]| {
+        fs::write(&path, webp_chunks(chunks)).unwrap();
+        cover::is_lossy_webp(&path).unwrap()
+    };
+    
+    assert!(lossy(&[(b"VP8 ", 20)]));
+    assert!(!lossy(&[(b"VP8L", 20)]));
+    assert!(lossy(&[(b"VP8X", 10), (b"ALPH", 3), (b"VP8 ", 20)]));
+    assert!(!lossy(&[(b"VP8X", 10), (b"ICCP", 7), (b"VP8L", 20)]));
+    
+    fs::write(&path, b"not a webp").unwrap();
+    assert!(!cover::is_lossy_webp(&path).unwrap());
+}